@@ -0,0 +1,410 @@
+//! Arbitrage against external reference prices.
+//!
+//! Given a price for every token in some common numeraire, the solver finds
+//! the trades that move `get_aggregated_price` onto those prices. Every token
+//! but the first (the hub) is traded once against the hub: sold into the pool
+//! with an exact-input trade or bought out of it with an exact-output trade.
+//! Those amounts pin every reserve but the hub's, so the end state does not
+//! depend on the order of the trades, and they are found by Newton's method
+//! on the log price errors.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::OrbitalError;
+use crate::journal::Operation;
+use crate::ticks::MultiTickAMM;
+
+/// Newton iterations before settling for the closest prices reached.
+const MAX_ITERATIONS: usize = 50;
+/// Largest log price error accepted as converged.
+const TOLERANCE: f64 = 1e-12;
+/// Bisection steps when sizing a single trade in `optimal_trade`.
+const SEARCH_STEPS: usize = 50;
+
+/// One arbitrage trade, valued at the reference prices.
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ArbTrade {
+    pub from: String,
+    pub to: String,
+    /// Gross input, fee included.
+    pub amount_in: f64,
+    pub amount_out: f64,
+    /// Whether the trade fixes `amount_out` rather than `amount_in`.
+    pub exact_out: bool,
+    pub profit: f64,
+}
+
+impl ArbTrade {
+    pub fn operation(&self) -> Operation {
+        Operation::Trade {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            amount: if self.exact_out { self.amount_out } else { self.amount_in },
+            exact_out: self.exact_out,
+            max_amount_in: None,
+        }
+    }
+}
+
+/// Trades that align a pool with reference prices, in execution order.
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct ArbPlan {
+    pub trades: Vec<ArbTrade>,
+    /// Total profit at the reference prices, fees paid.
+    pub profit: f64,
+    /// Net amount of each token the arbitrageur gains (negative: pays).
+    pub token_deltas: Vec<f64>,
+    pub target_prices: Vec<f64>,
+    /// Pool prices in the numeraire of `target_prices`, before and after.
+    pub prices_before: Vec<f64>,
+    pub prices_after: Vec<f64>,
+}
+
+impl ArbPlan {
+    pub fn operations(&self) -> Vec<Operation> {
+        self.trades
+            .iter()
+            .map(ArbTrade::operation)
+            .collect()
+    }
+
+    /// All of the plan's trades as one operation, so that executing it
+    /// never leaves the pool halfway to the target prices.
+    pub fn operation(&self) -> Operation {
+        Operation::Batch { ops: self.operations() }
+    }
+}
+
+/// Pool price of every token in the numeraire of `prices`, anchored on the
+/// first token. `NaN` where the pool has no price.
+fn pool_prices(amm: &MultiTickAMM, prices: &[f64]) -> Vec<f64> {
+    let base = &amm.token_names[0];
+    amm.token_names
+        .iter()
+        .map(|t| prices[0] * amm.get_aggregated_price(base, t).unwrap_or(f64::NAN))
+        .collect()
+}
+
+fn check_prices(amm: &MultiTickAMM, prices: &[f64]) -> Result<(), OrbitalError> {
+    if prices.len() != amm.token_names.len() {
+        return Err(OrbitalError::LengthMismatch {
+            expected: amm.token_names.len(),
+            got: prices.len(),
+        });
+    }
+    match prices.iter().find(|p| !(p.is_finite() && **p > 0.0)) {
+        Some(&p) => Err(OrbitalError::InvalidPrice(p)),
+        None => Ok(()),
+    }
+}
+
+/// Trade token `k + 1` against the hub for each entry of `amounts`: positive
+/// sells that much of it, negative buys it. Sells go first so that buys can
+/// draw on the liquidity they add.
+fn hub_trades(
+    amm: &MultiTickAMM,
+    amounts: &[f64]
+) -> Result<(MultiTickAMM, Vec<ArbTrade>), OrbitalError> {
+    let hub = &amm.token_names[0];
+    let mut pool = amm.clone();
+    let mut trades = Vec::new();
+    let mut order: Vec<usize> = (0..amounts.len()).filter(|&k| amounts[k] != 0.0).collect();
+    order.sort_by_key(|&k| amounts[k] < 0.0);
+    for k in order {
+        let token = &amm.token_names[k + 1];
+        let sell = amounts[k] > 0.0;
+        let (from, to) = if sell { (token, hub) } else { (hub, token) };
+        let op = Operation::Trade {
+            from: from.clone(),
+            to: to.clone(),
+            amount: amounts[k].abs(),
+            exact_out: !sell,
+            max_amount_in: None,
+        };
+        let output = op.apply(&mut pool)?;
+        let field = |name: &str| output.get(name).and_then(Value::as_f64).unwrap_or(0.0);
+        trades.push(ArbTrade {
+            from: from.clone(),
+            to: to.clone(),
+            amount_in: field("amount_in"),
+            amount_out: field("amount_out"),
+            exact_out: !sell,
+            profit: 0.0,
+        });
+    }
+    Ok((pool, trades))
+}
+
+/// Log errors of the pool's hub prices against the targets after trading
+/// `amounts`, or `None` if those trades cannot be made.
+fn residual(amm: &MultiTickAMM, prices: &[f64], amounts: &[f64]) -> Option<Vec<f64>> {
+    let (pool, _) = hub_trades(amm, amounts).ok()?;
+    let hub = &amm.token_names[0];
+    let mut errors = Vec::with_capacity(amounts.len());
+    for (k, token) in amm.token_names.iter().enumerate().skip(1) {
+        let price = pool.get_aggregated_price(hub, token).ok()?;
+        errors.push((price / (prices[k] / prices[0])).ln());
+    }
+    errors
+        .iter()
+        .all(|e| e.is_finite())
+        .then_some(errors)
+}
+
+fn max_abs(v: &[f64]) -> f64 {
+    v.iter().fold(0.0, |m, x| m.max(x.abs()))
+}
+
+/// Solve `a · x = b` by Gaussian elimination with partial pivoting.
+fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-300 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let (pivot_row, pivot_b) = (a[col].clone(), b[col]);
+        for row in col + 1..n {
+            let factor = a[row][col] / pivot_row[col];
+            for (x, p) in a[row].iter_mut().zip(&pivot_row).skip(col) {
+                *x -= factor * p;
+            }
+            b[row] -= factor * pivot_b;
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let tail: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - tail) / a[row][row];
+    }
+    Some(x)
+}
+
+impl MultiTickAMM {
+    /// Plan the trades that bring the pool's prices to `prices`, one price
+    /// per token in a common numeraire. When the targets are out of the
+    /// pool's reach the plan gets as close as it can; see `prices_after`.
+    pub fn arbitrage_plan(&self, prices: &[f64]) -> Result<ArbPlan, OrbitalError> {
+        check_prices(self, prices)?;
+        let n = self.token_names.len();
+        let mut amounts = vec![0.0; n.saturating_sub(1)];
+        let mut errors = residual(self, prices, &amounts).ok_or(OrbitalError::PriceUndefined)?;
+        // Finite-difference step, small against the pool's reserves.
+        let h = 1e-7 * (1.0 + self.global_reserves.iter().sum::<f64>() / (n as f64));
+
+        for _ in 0..MAX_ITERATIONS {
+            if max_abs(&errors) < TOLERANCE {
+                break;
+            }
+            let mut jacobian = vec![vec![0.0; amounts.len()]; amounts.len()];
+            for m in 0..amounts.len() {
+                // Probe away from zero so a sell stays a sell and a buy a buy.
+                let dh = if amounts[m] < 0.0 { -h } else { h };
+                let mut probe = amounts.clone();
+                probe[m] += dh;
+                let Some(shifted) = residual(self, prices, &probe) else {
+                    continue;
+                };
+                for (row, (e1, e0)) in jacobian.iter_mut().zip(shifted.iter().zip(&errors)) {
+                    row[m] = (e1 - e0) / dh;
+                }
+            }
+            let negated: Vec<f64> = errors
+                .iter()
+                .map(|e| -e)
+                .collect();
+            let Some(step) = solve_linear(jacobian, negated) else {
+                break;
+            };
+            // Halve the step until it lands on tradable and better prices.
+            let mut t = 1.0;
+            let mut improved = None;
+            while t > 1e-9 {
+                let candidate: Vec<f64> = amounts
+                    .iter()
+                    .zip(&step)
+                    .map(|(a, s)| a + t * s)
+                    .collect();
+                if let Some(e) = residual(self, prices, &candidate) {
+                    if max_abs(&e) < max_abs(&errors) {
+                        improved = Some((candidate, e));
+                        break;
+                    }
+                }
+                t /= 2.0;
+            }
+            let Some((next, next_errors)) = improved else {
+                break;
+            };
+            amounts = next;
+            errors = next_errors;
+        }
+
+        let (pool, mut trades) = hub_trades(self, &amounts)?;
+        let mut token_deltas = vec![0.0; n];
+        for trade in &mut trades {
+            let from = self.token_names.iter().position(|t| *t == trade.from).unwrap_or(0);
+            let to = self.token_names.iter().position(|t| *t == trade.to).unwrap_or(0);
+            trade.profit = trade.amount_out * prices[to] - trade.amount_in * prices[from];
+            token_deltas[from] -= trade.amount_in;
+            token_deltas[to] += trade.amount_out;
+        }
+        Ok(ArbPlan {
+            profit: trades.iter().fold(0.0, |sum, t| sum + t.profit),
+            trades,
+            token_deltas,
+            target_prices: prices.to_vec(),
+            prices_before: pool_prices(self, prices),
+            prices_after: pool_prices(&pool, prices),
+        })
+    }
+}
+
+/// The most profitable `from` → `to` trade of at most `max_amount` at
+/// `prices`, if any is profitable at all. For arbitrageurs that take one
+/// pair at a time rather than aligning the whole pool.
+pub fn optimal_trade(
+    amm: &MultiTickAMM,
+    from: usize,
+    to: usize,
+    prices: &[f64],
+    max_amount: f64
+) -> Option<ArbTrade> {
+    let (a, b) = (&amm.token_names[from], &amm.token_names[to]);
+    let output = |amount: f64| {
+        if amount <= 0.0 {
+            return Ok(0.0);
+        }
+        amm.quote_trade(a, b, amount).map(|q| q.amount_out)
+    };
+    // Profit is concave in the amount, so bisect on the sign of its slope.
+    // Past the point where the pool runs out of liquidity the slope counts
+    // as negative.
+    let slope = |amount: f64| {
+        let h = 1e-5 * (1.0 + amount);
+        let low = (amount - h).max(0.0);
+        match (output(amount + h), output(low)) {
+            (Ok(up), Ok(down)) => ((up - down) / (amount + h - low)) * prices[to] - prices[from],
+            _ => f64::NEG_INFINITY,
+        }
+    };
+    let (mut lo, mut hi) = (0.0, max_amount);
+    if slope(lo) <= 0.0 {
+        return None;
+    }
+    for _ in 0..SEARCH_STEPS {
+        let mid = (lo + hi) / 2.0;
+        if slope(mid) > 0.0 {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    let amount_out = output(lo).ok()?;
+    let profit = amount_out * prices[to] - lo * prices[from];
+    (profit > 0.0).then(|| ArbTrade {
+        from: a.clone(),
+        to: b.clone(),
+        amount_in: lo,
+        amount_out,
+        exact_out: false,
+        profit,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ stable_names, stable_pool };
+
+    const TARGET: [f64; 3] = [1.0, 0.98, 1.01];
+
+    fn two_tier_pool() -> MultiTickAMM {
+        let mut amm = MultiTickAMM::new(stable_names());
+        amm.add_tick(1800.0, vec![1000.0; 3]).unwrap();
+        amm.add_tick_with_fee(1750.0, vec![1000.0; 3], 5).unwrap();
+        amm
+    }
+
+    #[test]
+    fn test_pool_at_the_target_needs_no_trades() {
+        // Prices are relative: any common multiple of the pool's is a match.
+        let plan = two_tier_pool().arbitrage_plan(&[2.0, 2.0, 2.0]).unwrap();
+        assert!(plan.trades.is_empty());
+        assert_eq!(plan.profit, 0.0);
+    }
+
+    #[test]
+    fn test_plan_sells_cheap_tokens_and_buys_dear_ones() {
+        let plan = two_tier_pool().arbitrage_plan(&TARGET).unwrap();
+        assert_eq!(plan.trades.len(), 2);
+        assert!(plan.profit > 0.0);
+        // USDT is cheap in the market, so it is sold into the pool, and DAI
+        // is dear, so it is bought out of it.
+        assert_eq!((plan.trades[0].from.as_str(), plan.trades[0].exact_out), ("USDT", false));
+        assert_eq!((plan.trades[1].to.as_str(), plan.trades[1].exact_out), ("DAI", true));
+    }
+
+    #[test]
+    fn test_plan_lands_on_the_target_prices() {
+        let plan = two_tier_pool().arbitrage_plan(&TARGET).unwrap();
+        for (after, target) in plan.prices_after.iter().zip(TARGET) {
+            assert!((after - target).abs() < 1e-9, "{} vs {}", after, target);
+        }
+    }
+
+    #[test]
+    fn test_executing_the_plan_leaves_nothing_to_arbitrage() {
+        let mut amm = two_tier_pool();
+        amm.arbitrage_plan(&TARGET).unwrap().operation().apply(&mut amm).unwrap();
+        assert!(amm.arbitrage_plan(&TARGET).unwrap().trades.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_prices_that_are_not_positive_and_finite() {
+        let amm = two_tier_pool();
+        for bad in [0.0, -1.0, f64::INFINITY] {
+            let err = amm.arbitrage_plan(&[1.0, bad, 1.0]).unwrap_err();
+            assert_eq!(err, OrbitalError::InvalidPrice(bad));
+        }
+        let err = amm.arbitrage_plan(&[1.0, f64::NAN, 1.0]).unwrap_err();
+        assert_eq!(err.code(), "invalid_price");
+    }
+
+    #[test]
+    fn test_rejects_one_price_per_token_missing() {
+        let err = two_tier_pool().arbitrage_plan(&[1.0]).unwrap_err();
+        assert_eq!(err, OrbitalError::LengthMismatch { expected: 3, got: 1 });
+    }
+
+    #[test]
+    fn test_pool_without_ticks_has_no_prices_to_align() {
+        let err = MultiTickAMM::new(stable_names()).arbitrage_plan(&TARGET).unwrap_err();
+        assert_eq!(err, OrbitalError::PriceUndefined);
+    }
+
+    #[test]
+    fn test_optimal_trade_buys_the_cheap_token() {
+        let trade = optimal_trade(&stable_pool(0.95, 5), 1, 0, &[1.05, 1.0, 1.0], 500.0).unwrap();
+        assert_eq!((trade.from.as_str(), trade.to.as_str()), ("USDT", "USDC"));
+        assert!(trade.profit > 0.0 && trade.amount_in <= 500.0);
+    }
+
+    #[test]
+    fn test_optimal_trade_skips_unprofitable_pairs() {
+        let amm = stable_pool(0.95, 5);
+        assert!(optimal_trade(&amm, 0, 1, &[1.0; 3], 500.0).is_none());
+        assert!(optimal_trade(&amm, 1, 0, &[1.05, 1.0, 1.0], 0.0).is_none());
+    }
+
+    #[test]
+    fn test_optimal_trade_on_a_pool_without_ticks_is_none() {
+        let amm = MultiTickAMM::new(stable_names());
+        assert!(optimal_trade(&amm, 1, 0, &[1.05, 1.0, 1.0], 500.0).is_none());
+    }
+}
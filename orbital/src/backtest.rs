@@ -0,0 +1,494 @@
+//! Backtests of a pool configuration against historical prices.
+//!
+//! The input is a CSV with a header row. The first column is a timestamp and
+//! every pool token needs a price column named after it; a `<TOKEN>_volume`
+//! column, when present, gives the amount of that token sold into the pool
+//! over the row. Other columns are ignored, so exported data can be used as
+//! is:
+//!
+//! ```text
+//! timestamp,USDC,USDT,DAI,USDC_volume
+//! 1678406400,1.0,1.0,1.0,120
+//! 1678410000,0.97,1.0,1.0,450
+//! ```
+//!
+//! Each row first replays its volume as noise trades, then arbitrageurs take
+//! the most profitable trade at the row's prices until none is left. The
+//! report values the ticks' liquidity against simply holding it.
+
+use serde::Serialize;
+
+use crate::arbitrage;
+use crate::error::OrbitalError;
+use crate::journal::Operation;
+use crate::ticks::MultiTickAMM;
+
+/// Arbitrage trades per row at most.
+const ARB_ROUNDS: usize = 10;
+/// Profit, relative to the pool's value, below which arbitrage stops.
+const MIN_RELATIVE_PROFIT: f64 = 1e-9;
+
+/// One line of a price file.
+#[derive(Clone, Debug, Serialize)]
+pub struct PriceRow {
+    pub timestamp: String,
+    pub prices: Vec<f64>,
+    /// Amount of each token sold into the pool, before `volume_scale`.
+    pub volumes: Vec<f64>,
+}
+
+/// Parse a price file for a pool of `token_names`.
+pub fn parse_csv(text: &str, token_names: &[String]) -> Result<Vec<PriceRow>, OrbitalError> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+    let invalid = |line: usize, reason: String| OrbitalError::InvalidCsv { line, reason };
+    let Some((header_line, header)) = lines.next() else {
+        return Err(invalid(1, "missing header".into()));
+    };
+    let header: Vec<&str> = header
+        .split(',')
+        .map(str::trim)
+        .collect();
+    let column = |name: &str| header.iter().position(|h| *h == name);
+    let mut price_columns = Vec::with_capacity(token_names.len());
+    for token in token_names {
+        match column(token) {
+            Some(c) if c > 0 => price_columns.push(c),
+            _ => {
+                return Err(invalid(header_line, format!("no price column for {}", token)));
+            }
+        }
+    }
+    let volume_columns: Vec<Option<usize>> = token_names
+        .iter()
+        .map(|t| column(&format!("{}_volume", t)))
+        .collect();
+
+    let mut rows = Vec::new();
+    for (line, text) in lines {
+        let cells: Vec<&str> = text
+            .split(',')
+            .map(str::trim)
+            .collect();
+        let number = |c: usize| -> Result<f64, OrbitalError> {
+            let cell = cells.get(c).copied().unwrap_or("");
+            cell.parse::<f64>().map_err(|_| {
+                invalid(line, format!("'{}' in column {} is not a number", cell, header[c]))
+            })
+        };
+        let mut prices = Vec::with_capacity(token_names.len());
+        for &c in &price_columns {
+            let price = number(c)?;
+            if !(price.is_finite() && price > 0.0) {
+                return Err(invalid(line, format!("price {} in column {}", price, header[c])));
+            }
+            prices.push(price);
+        }
+        let mut volumes = Vec::with_capacity(token_names.len());
+        for c in &volume_columns {
+            // Blank or absent volume means no trading.
+            let volume = match c {
+                Some(c) if cells.get(*c).is_some_and(|v| !v.is_empty()) => number(*c)?,
+                _ => 0.0,
+            };
+            if !(volume.is_finite() && volume >= 0.0) {
+                return Err(invalid(line, format!("negative volume {}", volume)));
+            }
+            volumes.push(volume);
+        }
+        rows.push(PriceRow { timestamp: cells[0].to_string(), prices, volumes });
+    }
+    if rows.is_empty() {
+        return Err(invalid(header_line, "no data rows".into()));
+    }
+    Ok(rows)
+}
+
+/// Outcome for the liquidity of one tick, valued in the price numeraire.
+#[derive(Clone, Debug, Serialize)]
+pub struct TickReport {
+    pub index: usize,
+    pub plane_constant: f64,
+    /// Reserves at the first row's prices, before any trading.
+    pub initial_value: f64,
+    /// Reserves at the last row's prices.
+    pub final_value: f64,
+    /// The initial reserves, held untouched, at the last row's prices.
+    pub hold_value: f64,
+    /// Fees collected, at the last row's prices.
+    pub fees_value: f64,
+    /// `final_value + fees_value - initial_value`.
+    pub pnl: f64,
+    /// `final_value / hold_value - 1`, fees excluded. Usually negative, but
+    /// reserves also keep what noise traders lose to the curve.
+    pub impermanent_loss: f64,
+    /// Rows after which the tick sat on its boundary.
+    pub boundary_rows: usize,
+    /// Share of the backtest's duration spent on the boundary.
+    pub boundary_share: f64,
+}
+
+/// Result of [`backtest`]. Pool-wide figures sum the ticks.
+#[derive(Clone, Debug, Serialize)]
+pub struct BacktestReport {
+    pub token_names: Vec<String>,
+    pub rows: usize,
+    pub start: String,
+    pub end: String,
+    pub trades: usize,
+    /// Operations that failed and were skipped.
+    pub failed: usize,
+    /// Sum of trade inputs, valued at each row's prices.
+    pub volume: f64,
+    /// Profit taken by arbitrageurs, valued at each row's prices.
+    pub arbitrage_profit: f64,
+    /// Fees collected per token.
+    pub fees: Vec<f64>,
+    pub initial_value: f64,
+    pub final_value: f64,
+    pub hold_value: f64,
+    pub fees_value: f64,
+    pub pnl: f64,
+    pub impermanent_loss: f64,
+    pub final_reserves: Vec<f64>,
+    pub ticks: Vec<TickReport>,
+}
+
+fn value(amounts: &[f64], prices: &[f64]) -> f64 {
+    amounts
+        .iter()
+        .zip(prices)
+        .map(|(a, p)| a * p)
+        .sum()
+}
+
+/// Apply `op` unless it fails, leaving `pool` untouched in that case.
+fn try_apply(pool: &mut MultiTickAMM, op: &Operation) -> bool {
+    let mut next = pool.clone();
+    if op.apply(&mut next).is_err() {
+        return false;
+    }
+    *pool = next;
+    true
+}
+
+/// Replay `rows` through a copy of `amm`. Volumes are multiplied by
+/// `volume_scale`, to fit market-wide volumes to the size of the pool.
+///
+/// Durations come from the timestamps when they are all numbers (each row
+/// lasting until the next), and count one per row otherwise.
+pub fn backtest(
+    amm: &MultiTickAMM,
+    rows: &[PriceRow],
+    volume_scale: f64
+) -> Result<BacktestReport, OrbitalError> {
+    let n = amm.token_names.len();
+    if amm.ticks.is_empty() {
+        // There is no liquidity to report on, and nothing to value it by.
+        return Err(OrbitalError::NoInteriorLiquidity);
+    }
+    let (Some(first), Some(last)) = (rows.first(), rows.last()) else {
+        return Err(OrbitalError::InvalidCsv { line: 1, reason: "no data rows".into() });
+    };
+    if let Some(row) = rows.iter().find(|r| r.prices.len() != n || r.volumes.len() != n) {
+        return Err(OrbitalError::LengthMismatch { expected: n, got: row.prices.len() });
+    }
+    let times: Option<Vec<f64>> = rows
+        .iter()
+        .map(|r| r.timestamp.parse::<f64>().ok())
+        .collect();
+    let durations: Vec<f64> = match times {
+        Some(times) if times.len() > 1 => {
+            let mut d: Vec<f64> = times
+                .windows(2)
+                .map(|w| (w[1] - w[0]).max(0.0))
+                .collect();
+            d.push(0.0);
+            d
+        }
+        _ => vec![1.0; rows.len()],
+    };
+    let total_duration: f64 = durations.iter().sum();
+
+    let mut pool = amm.clone();
+    let (mut trades, mut failed, mut volume, mut arbitrage_profit) = (0, 0, 0.0, 0.0);
+    let mut boundary_rows = vec![0; pool.ticks.len()];
+    let mut boundary_time = vec![0.0; pool.ticks.len()];
+    for (row, duration) in rows.iter().zip(&durations) {
+        // Noise: each token's volume is sold evenly for the other tokens.
+        for (i, &amount) in row.volumes.iter().enumerate() {
+            let amount = amount * volume_scale;
+            if amount <= 0.0 || n < 2 {
+                continue;
+            }
+            for j in (0..n).filter(|&j| j != i) {
+                let op = Operation::Trade {
+                    from: pool.token_names[i].clone(),
+                    to: pool.token_names[j].clone(),
+                    amount: amount / ((n - 1) as f64),
+                    exact_out: false,
+                    max_amount_in: None,
+                };
+                if try_apply(&mut pool, &op) {
+                    trades += 1;
+                    volume += (amount / ((n - 1) as f64)) * row.prices[i];
+                } else {
+                    failed += 1;
+                }
+            }
+        }
+
+        // Arbitrage: the most profitable pair trade, until none is left.
+        let pool_value = value(&pool.global_reserves, &row.prices);
+        for _ in 0..ARB_ROUNDS {
+            let best = (0..n)
+                .flat_map(|from| (0..n).map(move |to| (from, to)))
+                .filter(|(from, to)| from != to)
+                .filter_map(|(from, to)| {
+                    let max_amount = pool_value / row.prices[from];
+                    arbitrage::optimal_trade(&pool, from, to, &row.prices, max_amount)
+                })
+                .max_by(|a, b| a.profit.total_cmp(&b.profit));
+            let Some(trade) = best else {
+                break;
+            };
+            if trade.profit <= MIN_RELATIVE_PROFIT * pool_value {
+                break;
+            }
+            if !try_apply(&mut pool, &trade.operation()) {
+                failed += 1;
+                break;
+            }
+            let i = pool.token_names.iter().position(|t| *t == trade.from).unwrap_or(0);
+            trades += 1;
+            volume += trade.amount_in * row.prices[i];
+            arbitrage_profit += trade.profit;
+        }
+
+        for (i, tick) in pool.ticks.iter().enumerate() {
+            if tick.is_boundary() {
+                boundary_rows[i] += 1;
+                boundary_time[i] += duration;
+            }
+        }
+    }
+
+    let ticks: Vec<TickReport> = amm.ticks
+        .iter()
+        .zip(&pool.ticks)
+        .enumerate()
+        .map(|(index, (before, after))| {
+            let initial_value = value(&before.sphere_amm.reserves, &first.prices);
+            let final_value = value(&after.sphere_amm.reserves, &last.prices);
+            let hold_value = value(&before.sphere_amm.reserves, &last.prices);
+            // Fees already in the starting pool are not the backtest's.
+            let fees: Vec<f64> = (0..n)
+                .map(|t| {
+                    let old = before.fees_accrued.get(t).copied().unwrap_or(0.0);
+                    after.fees_accrued.get(t).copied().unwrap_or(0.0) - old
+                })
+                .collect();
+            let fees_value = value(&fees, &last.prices);
+            TickReport {
+                index,
+                plane_constant: after.plane_constant,
+                initial_value,
+                final_value,
+                hold_value,
+                fees_value,
+                pnl: final_value + fees_value - initial_value,
+                impermanent_loss: final_value / hold_value - 1.0,
+                boundary_rows: boundary_rows[index],
+                boundary_share: if total_duration > 0.0 {
+                    boundary_time[index] / total_duration
+                } else {
+                    0.0
+                },
+            }
+        })
+        .collect();
+
+    let fees: Vec<f64> = amm
+        .total_fees()
+        .iter()
+        .zip(pool.total_fees())
+        .map(|(before, after)| after - before)
+        .collect();
+    let initial_value = value(&amm.global_reserves, &first.prices);
+    let final_value = value(&pool.global_reserves, &last.prices);
+    let hold_value = value(&amm.global_reserves, &last.prices);
+    let fees_value = value(&fees, &last.prices);
+    Ok(BacktestReport {
+        token_names: amm.token_names.clone(),
+        rows: rows.len(),
+        start: first.timestamp.clone(),
+        end: last.timestamp.clone(),
+        trades,
+        failed,
+        volume,
+        arbitrage_profit,
+        fees,
+        initial_value,
+        final_value,
+        hold_value,
+        fees_value,
+        pnl: final_value + fees_value - initial_value,
+        impermanent_loss: final_value / hold_value - 1.0,
+        final_reserves: pool.global_reserves.clone(),
+        ticks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::stable_names;
+
+    /// USDC loses its peg for two hours out of four, on heavy selling.
+    const DEPEG: &str = "\
+        timestamp,USDC,USDT,DAI,USDC_volume,note\n\
+        0,1.0,1.0,1.0,10,\n\
+        3600,0.95,1.0,1.0,50,depeg\n\
+        7200,0.90,1.0,1.0,,\n\
+        \n\
+        10800,1.0,1.0,1.0,10,\n\
+        14400,1.0,1.0,1.0,0,\n";
+
+    fn two_tick_pool() -> MultiTickAMM {
+        let mut amm = MultiTickAMM::new(stable_names());
+        amm.add_tick_from_depeg(vec![1000.0; 3], 0.99, 5).unwrap();
+        amm.add_tick_from_depeg(vec![1000.0; 3], 0.8, 5).unwrap();
+        amm
+    }
+
+    fn depeg_report() -> BacktestReport {
+        let rows = parse_csv(DEPEG, &stable_names()).unwrap();
+        backtest(&two_tick_pool(), &rows, 1.0).unwrap()
+    }
+
+    fn parse_error(csv: &str) -> OrbitalError {
+        parse_csv(csv, &stable_names()).unwrap_err()
+    }
+
+    #[test]
+    fn test_parse_skips_blank_lines_and_reads_volumes() {
+        let rows = parse_csv(DEPEG, &stable_names()).unwrap();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[1].prices, vec![0.95, 1.0, 1.0]);
+        assert_eq!(rows[1].volumes, vec![50.0, 0.0, 0.0]);
+        // A blank volume cell means no trading.
+        assert_eq!(rows[2].volumes, vec![0.0; 3]);
+    }
+
+    #[test]
+    fn test_parse_rejects_a_file_without_a_header() {
+        let err = parse_error("\n\n");
+        assert_eq!(err, OrbitalError::InvalidCsv { line: 1, reason: "missing header".into() });
+    }
+
+    #[test]
+    fn test_parse_rejects_a_missing_price_column() {
+        let err = parse_error("timestamp,USDC,USDT\n0,1,1\n");
+        assert_eq!(err, OrbitalError::InvalidCsv {
+            line: 1,
+            reason: "no price column for DAI".into(),
+        });
+        // The first column is the timestamp, never a price.
+        let err = parse_error("USDC,USDT,DAI\n1,1,1\n");
+        assert_eq!(err.code(), "invalid_csv");
+    }
+
+    #[test]
+    fn test_parse_rejects_a_header_without_rows() {
+        let err = parse_error("timestamp,USDC,USDT,DAI\n");
+        assert_eq!(err, OrbitalError::InvalidCsv { line: 1, reason: "no data rows".into() });
+    }
+
+    #[test]
+    fn test_parse_rejects_cells_that_are_not_numbers() {
+        let err = parse_error("timestamp,USDC,USDT,DAI\n0,1,x,1\n");
+        assert_eq!(err, OrbitalError::InvalidCsv {
+            line: 2,
+            reason: "'x' in column USDT is not a number".into(),
+        });
+        // A short row is missing its last price.
+        let err = parse_error("timestamp,USDC,USDT,DAI\n0,1,1\n");
+        assert_eq!(err, OrbitalError::InvalidCsv {
+            line: 2,
+            reason: "'' in column DAI is not a number".into(),
+        });
+    }
+
+    #[test]
+    fn test_parse_rejects_prices_that_are_not_positive_and_finite() {
+        for bad in ["0", "-1", "inf", "NaN"] {
+            let err = parse_error(&format!("timestamp,USDC,USDT,DAI\n0,1,{},1\n", bad));
+            assert_eq!(err.code(), "invalid_csv", "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_negative_volumes() {
+        let err = parse_error("timestamp,USDC,USDT,DAI,DAI_volume\n0,1,1,1,-5\n");
+        assert_eq!(err, OrbitalError::InvalidCsv { line: 2, reason: "negative volume -5".into() });
+    }
+
+    #[test]
+    fn test_report_spans_the_file_and_trades_without_failures() {
+        let report = depeg_report();
+        assert_eq!((report.start.as_str(), report.end.as_str()), ("0", "14400"));
+        assert_eq!(report.rows, 5);
+        assert!(report.trades > 0 && report.failed == 0);
+        assert!(report.fees_value > 0.0 && report.arbitrage_profit > 0.0);
+    }
+
+    #[test]
+    fn test_report_pnl_and_loss_follow_from_the_values() {
+        let report = depeg_report();
+        let expected = report.final_value + report.fees_value - report.initial_value;
+        assert!((report.pnl - expected).abs() < 1e-9);
+        let il = report.final_value / report.hold_value - 1.0;
+        assert!((report.impermanent_loss - il).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_report_times_each_tick_on_its_boundary() {
+        let report = depeg_report();
+        // The narrow tick is pinned through the depeg, the wide one never.
+        assert_eq!(report.ticks[0].boundary_rows, 2);
+        assert!((report.ticks[0].boundary_share - 0.5).abs() < 1e-9);
+        assert_eq!(report.ticks[1].boundary_rows, 0);
+    }
+
+    #[test]
+    fn test_rows_count_once_each_without_numeric_timestamps() {
+        let csv = "timestamp,USDC,USDT,DAI\nmon,1,1,1\ntue,0.9,1,1\nwed,1,1,1\n";
+        let rows = parse_csv(csv, &stable_names()).unwrap();
+        let report = backtest(&two_tick_pool(), &rows, 1.0).unwrap();
+        assert_eq!(report.ticks[0].boundary_rows, 1);
+        assert!((report.ticks[0].boundary_share - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_backtest_rejects_no_rows() {
+        let err = backtest(&two_tick_pool(), &[], 1.0).unwrap_err();
+        assert_eq!(err.code(), "invalid_csv");
+    }
+
+    #[test]
+    fn test_backtest_rejects_rows_for_other_tokens() {
+        let rows = parse_csv("timestamp,USDC,USDT\n0,1,1\n", &stable_names()[..2]).unwrap();
+        let err = backtest(&two_tick_pool(), &rows, 1.0).unwrap_err();
+        assert_eq!(err, OrbitalError::LengthMismatch { expected: 3, got: 2 });
+    }
+
+    #[test]
+    fn test_backtest_rejects_a_pool_without_ticks() {
+        let rows = parse_csv(DEPEG, &stable_names()).unwrap();
+        let err = backtest(&MultiTickAMM::new(stable_names()), &rows, 1.0).unwrap_err();
+        assert_eq!(err, OrbitalError::NoInteriorLiquidity);
+    }
+}
@@ -0,0 +1,279 @@
+use std::fmt;
+
+/// Every way an AMM operation can fail. `code()` and `exit_code()` are part of
+/// the public contract of the HTTP API and the CLI and must stay stable.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OrbitalError {
+    UnknownToken(String),
+    SameToken,
+    NonPositiveAmount,
+    InsufficientLiquidity,
+    ComplexSolution,
+    SlippageExceeded {
+        required: f64,
+        max: f64,
+    },
+    PriceUndefined,
+    InvariantViolation,
+    LengthMismatch {
+        expected: usize,
+        got: usize,
+    },
+    BadPercentage(f64),
+    UnknownLp(String),
+    NoShares(String),
+    InvalidDepegPrice(f64),
+    OutsideTick,
+    NoInteriorLiquidity,
+    NotConverged,
+    UnknownTick(usize),
+    MissingState(String),
+    CorruptState(String),
+    UnsupportedSchema(u32),
+    Io(String),
+    MissingPlane,
+    ReplayMismatch(String),
+    NothingToUndo,
+    NothingToRedo,
+    UnknownCheckpoint(String),
+    UnknownPool(String),
+    PoolExists(String),
+    InvalidPoolName(String),
+    NoRoute {
+        from: String,
+        to: String,
+    },
+    InvalidPrice(f64),
+    InvalidCsv {
+        line: usize,
+        reason: String,
+    },
+    EmptyTick,
+    InvalidFee(u32),
+    NotFinite(f64),
+    InvalidReserves(String),
+    DefaultPool(String),
+    OutdatedJournal(u32),
+    TooManyHops(usize),
+}
+
+impl OrbitalError {
+    /// Machine-readable error code used in HTTP responses.
+    pub fn code(&self) -> &'static str {
+        match self {
+            OrbitalError::UnknownToken(_) => "unknown_token",
+            OrbitalError::SameToken => "same_token",
+            OrbitalError::NonPositiveAmount => "non_positive_amount",
+            OrbitalError::InsufficientLiquidity => "insufficient_liquidity",
+            OrbitalError::ComplexSolution => "complex_solution",
+            OrbitalError::SlippageExceeded { .. } => "slippage_exceeded",
+            OrbitalError::PriceUndefined => "price_undefined",
+            OrbitalError::InvariantViolation => "invariant_violation",
+            OrbitalError::LengthMismatch { .. } => "length_mismatch",
+            OrbitalError::BadPercentage(_) => "bad_percentage",
+            OrbitalError::UnknownLp(_) => "unknown_lp",
+            OrbitalError::NoShares(_) => "no_shares",
+            OrbitalError::InvalidDepegPrice(_) => "invalid_depeg_price",
+            OrbitalError::OutsideTick => "outside_tick",
+            OrbitalError::NoInteriorLiquidity => "no_interior_liquidity",
+            OrbitalError::NotConverged => "not_converged",
+            OrbitalError::UnknownTick(_) => "unknown_tick",
+            OrbitalError::MissingState(_) => "missing_state",
+            OrbitalError::CorruptState(_) => "corrupt_state",
+            OrbitalError::UnsupportedSchema(_) => "unsupported_schema",
+            OrbitalError::Io(_) => "io_error",
+            OrbitalError::MissingPlane => "missing_plane",
+            OrbitalError::ReplayMismatch(_) => "replay_mismatch",
+            OrbitalError::NothingToUndo => "nothing_to_undo",
+            OrbitalError::NothingToRedo => "nothing_to_redo",
+            OrbitalError::UnknownCheckpoint(_) => "unknown_checkpoint",
+            OrbitalError::UnknownPool(_) => "unknown_pool",
+            OrbitalError::PoolExists(_) => "pool_exists",
+            OrbitalError::InvalidPoolName(_) => "invalid_pool_name",
+            OrbitalError::NoRoute { .. } => "no_route",
+            OrbitalError::InvalidPrice(_) => "invalid_price",
+            OrbitalError::InvalidCsv { .. } => "invalid_csv",
+            OrbitalError::EmptyTick => "empty_tick",
+            OrbitalError::InvalidFee(_) => "invalid_fee",
+            OrbitalError::NotFinite(_) => "not_finite",
+            OrbitalError::InvalidReserves(_) => "invalid_reserves",
+            OrbitalError::DefaultPool(_) => "default_pool",
+            OrbitalError::OutdatedJournal(_) => "outdated_journal",
+            OrbitalError::TooManyHops(_) => "too_many_hops",
+        }
+    }
+
+    /// Process exit code used by the CLI. 1 and 2 are left to generic
+    /// failures and argument parsing.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            OrbitalError::UnknownToken(_) => 10,
+            OrbitalError::SameToken => 11,
+            OrbitalError::NonPositiveAmount => 12,
+            OrbitalError::InsufficientLiquidity => 13,
+            OrbitalError::ComplexSolution => 14,
+            OrbitalError::SlippageExceeded { .. } => 15,
+            OrbitalError::PriceUndefined => 16,
+            OrbitalError::InvariantViolation => 17,
+            OrbitalError::LengthMismatch { .. } => 18,
+            OrbitalError::BadPercentage(_) => 19,
+            OrbitalError::UnknownLp(_) => 20,
+            OrbitalError::NoShares(_) => 21,
+            OrbitalError::InvalidDepegPrice(_) => 22,
+            OrbitalError::OutsideTick => 23,
+            OrbitalError::NoInteriorLiquidity => 24,
+            OrbitalError::NotConverged => 25,
+            OrbitalError::UnknownTick(_) => 26,
+            OrbitalError::MissingState(_) => 27,
+            OrbitalError::CorruptState(_) => 28,
+            OrbitalError::UnsupportedSchema(_) => 29,
+            OrbitalError::Io(_) => 30,
+            OrbitalError::MissingPlane => 31,
+            OrbitalError::ReplayMismatch(_) => 32,
+            OrbitalError::NothingToUndo => 33,
+            OrbitalError::NothingToRedo => 34,
+            OrbitalError::UnknownCheckpoint(_) => 35,
+            OrbitalError::UnknownPool(_) => 36,
+            OrbitalError::PoolExists(_) => 37,
+            OrbitalError::InvalidPoolName(_) => 38,
+            OrbitalError::NoRoute { .. } => 39,
+            OrbitalError::InvalidPrice(_) => 40,
+            OrbitalError::InvalidCsv { .. } => 41,
+            OrbitalError::EmptyTick => 42,
+            OrbitalError::InvalidFee(_) => 43,
+            OrbitalError::NotFinite(_) => 44,
+            OrbitalError::InvalidReserves(_) => 45,
+            OrbitalError::DefaultPool(_) => 46,
+            OrbitalError::OutdatedJournal(_) => 47,
+            OrbitalError::TooManyHops(_) => 48,
+        }
+    }
+}
+
+impl fmt::Display for OrbitalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrbitalError::UnknownToken(token) => write!(f, "Token '{}' not found in pool", token),
+            OrbitalError::SameToken => write!(f, "Cannot swap a token for itself"),
+            OrbitalError::NonPositiveAmount => write!(f, "Swap amount must be positive"),
+            OrbitalError::InsufficientLiquidity =>
+                write!(f, "Insufficient liquidity for the requested swap"),
+            OrbitalError::ComplexSolution =>
+                write!(f, "Swap leads to complex solution – amount too large for the curve"),
+            OrbitalError::SlippageExceeded { required, max } =>
+                write!(f, "Required input {} exceeds max_amount_in {}", required, max),
+            OrbitalError::PriceUndefined =>
+                write!(f, "Division by zero – from-token is at radius"),
+            OrbitalError::InvariantViolation => write!(f, "Invariant violated"),
+            OrbitalError::LengthMismatch { expected, got } =>
+                write!(f, "Length mismatch: expected {} values, got {}", expected, got),
+            OrbitalError::BadPercentage(p) => write!(f, "percentage must be in [0,1], got {}", p),
+            OrbitalError::UnknownLp(lp) => write!(f, "LP id '{}' not found", lp),
+            OrbitalError::NoShares(lp) => write!(f, "LP '{}' has no shares", lp),
+            OrbitalError::InvalidDepegPrice(p) =>
+                write!(f, "Depeg price must be in (0, 1), got {}", p),
+            OrbitalError::OutsideTick =>
+                write!(f, "Reserves lie outside the tick's plane boundary"),
+            OrbitalError::NoInteriorLiquidity =>
+                write!(f, "Not enough liquidity across ticks to satisfy trade"),
+            OrbitalError::NotConverged =>
+                write!(f, "Trade did not settle across tick boundaries"),
+            OrbitalError::UnknownTick(index) => write!(f, "Invalid tick index {}", index),
+            OrbitalError::MissingState(path) =>
+                write!(f, "No existing state at {} – initialise first with `init`", path),
+            OrbitalError::CorruptState(reason) => write!(f, "Corrupt state file {}", reason),
+            OrbitalError::UnsupportedSchema(v) =>
+                write!(f, "State file has schema version {}, newer than this release", v),
+            OrbitalError::Io(reason) => write!(f, "I/O error: {}", reason),
+            OrbitalError::MissingPlane => write!(f, "Either plane or depeg_price is required"),
+            OrbitalError::ReplayMismatch(reason) =>
+                write!(f, "Journal replay does not match: {}", reason),
+            OrbitalError::NothingToUndo => write!(f, "Nothing to undo"),
+            OrbitalError::NothingToRedo => write!(f, "Nothing to redo"),
+            OrbitalError::UnknownCheckpoint(name) => write!(f, "Checkpoint '{}' not found", name),
+            OrbitalError::UnknownPool(id) => write!(f, "Pool '{}' not found", id),
+            OrbitalError::PoolExists(id) => write!(f, "Pool '{}' already exists", id),
+            OrbitalError::InvalidPoolName(name) =>
+                write!(
+                    f,
+                    "Invalid pool name '{}': use letters, digits, '-' and '_' \
+                     ('default' is reserved)",
+                    name
+                ),
+            OrbitalError::NoRoute { from, to } =>
+                write!(f, "No route from {} to {} across the given pools", from, to),
+            OrbitalError::InvalidPrice(p) => write!(f, "Price must be positive, got {}", p),
+            OrbitalError::InvalidCsv { line, reason } =>
+                write!(f, "Invalid CSV at line {}: {}", line, reason),
+            OrbitalError::EmptyTick =>
+                write!(f, "Tick holds no liquidity to measure a deposit against"),
+            OrbitalError::InvalidFee(bps) => write!(f, "Fee must be below 10000 bps, got {}", bps),
+            OrbitalError::NotFinite(v) => write!(f, "Expected a finite number, got {}", v),
+            OrbitalError::InvalidReserves(reason) => write!(f, "Invalid reserves: {}", reason),
+            OrbitalError::DefaultPool(id) =>
+                write!(f, "Pool '{}' is the default pool and cannot be deleted", id),
+            OrbitalError::OutdatedJournal(v) =>
+                write!(f, "Journal version {} is outdated and cannot be replayed", v),
+            OrbitalError::TooManyHops(hops) =>
+                write!(f, "Routes are limited to {} hops, got {}", crate::router::MAX_HOPS, hops),
+        }
+    }
+}
+
+impl std::error::Error for OrbitalError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_codes_are_distinct() {
+        let all = [
+            OrbitalError::UnknownToken("X".into()),
+            OrbitalError::SameToken,
+            OrbitalError::NonPositiveAmount,
+            OrbitalError::InsufficientLiquidity,
+            OrbitalError::ComplexSolution,
+            OrbitalError::SlippageExceeded { required: 2.0, max: 1.0 },
+            OrbitalError::PriceUndefined,
+            OrbitalError::InvariantViolation,
+            OrbitalError::LengthMismatch { expected: 3, got: 2 },
+            OrbitalError::BadPercentage(2.0),
+            OrbitalError::UnknownLp("lp".into()),
+            OrbitalError::NoShares("lp".into()),
+            OrbitalError::InvalidDepegPrice(1.5),
+            OrbitalError::OutsideTick,
+            OrbitalError::NoInteriorLiquidity,
+            OrbitalError::NotConverged,
+            OrbitalError::UnknownTick(3),
+            OrbitalError::MissingState("pool.json".into()),
+            OrbitalError::CorruptState("pool.json".into()),
+            OrbitalError::UnsupportedSchema(9),
+            OrbitalError::Io("disk full".into()),
+            OrbitalError::MissingPlane,
+            OrbitalError::ReplayMismatch("tick 0".into()),
+            OrbitalError::NothingToUndo,
+            OrbitalError::NothingToRedo,
+            OrbitalError::UnknownCheckpoint("base".into()),
+            OrbitalError::UnknownPool("p".into()),
+            OrbitalError::PoolExists("p".into()),
+            OrbitalError::InvalidPoolName("../p".into()),
+            OrbitalError::NoRoute { from: "A".into(), to: "B".into() },
+            OrbitalError::InvalidPrice(-1.0),
+            OrbitalError::InvalidCsv { line: 2, reason: "bad".into() },
+            OrbitalError::EmptyTick,
+            OrbitalError::InvalidFee(10_000),
+            OrbitalError::NotFinite(f64::NAN),
+            OrbitalError::InvalidReserves("-1".into()),
+            OrbitalError::DefaultPool("default".into()),
+            OrbitalError::OutdatedJournal(0),
+            OrbitalError::TooManyHops(9),
+        ];
+        let codes: HashSet<_> = all.iter().map(|e| e.code()).collect();
+        let exits: HashSet<_> = all.iter().map(|e| e.exit_code()).collect();
+        assert_eq!(codes.len(), all.len());
+        assert_eq!(exits.len(), all.len());
+        assert!(exits.iter().all(|&c| c > 2));
+    }
+}
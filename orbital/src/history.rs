@@ -0,0 +1,259 @@
+//! Undo, redo and named checkpoints over a pool's journal.
+//!
+//! Nothing here is stored separately: the undo and redo stacks and the
+//! checkpoints are read off the journal as event indices, and only the state
+//! a move returns to is rebuilt, by replaying the journal up to it. Moving
+//! through history is itself journaled as `undo`, `redo` and `restore` events
+//! that carry the state they return to.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::OrbitalError;
+use crate::journal::{ self, Event, Operation };
+use crate::ticks::MultiTickAMM;
+
+/// One journal event as shown to users.
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct HistoryEntry {
+    pub index: usize,
+    pub timestamp_ms: u64,
+    pub op: String,
+    /// Operation parameters, without embedded pool states.
+    pub params: Value,
+    pub output: Value,
+    pub reserves_after: Vec<f64>,
+}
+
+impl HistoryEntry {
+    fn new(index: usize, event: &Event) -> Self {
+        let (op, params) = event.op.summary();
+        HistoryEntry {
+            index,
+            timestamp_ms: event.timestamp_ms,
+            op,
+            params,
+            output: event.output.clone(),
+            reserves_after: event.reserves_after.clone(),
+        }
+    }
+}
+
+/// Undo/redo stacks and checkpoints of a pool, read off its journal.
+///
+/// Every stack entry is the index of an event; it stands for the pool as it
+/// was just before that event.
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    pub entries: Vec<HistoryEntry>,
+    events: Vec<Event>,
+    /// Undoable operations, most recent last.
+    undo: Vec<usize>,
+    /// Undo events since the last regular operation, most recent last.
+    redo: Vec<usize>,
+    checkpoints: BTreeMap<String, usize>,
+}
+
+impl History {
+    /// Track where each of `events` leaves the stacks. A regular operation
+    /// (or a restore) pushes the state it replaced and clears the redo stack;
+    /// checkpoints do not change the pool and are not undoable.
+    pub fn from_events(events: Vec<Event>) -> Self {
+        let mut history = History::default();
+        for (index, event) in events.iter().enumerate() {
+            history.entries.push(HistoryEntry::new(index, event));
+            match &event.op {
+                Operation::Genesis { .. } => {}
+                Operation::Checkpoint { name } => {
+                    history.checkpoints.insert(name.clone(), index);
+                }
+                Operation::Undo { .. } => {
+                    history.undo.pop();
+                    history.redo.push(index);
+                }
+                Operation::Redo { .. } => {
+                    history.redo.pop();
+                    history.undo.push(index);
+                }
+                _ => {
+                    history.undo.push(index);
+                    history.redo.clear();
+                }
+            }
+        }
+        history.events = events;
+        history
+    }
+
+    /// The pool just before event `index`.
+    fn state_before(&self, index: usize) -> Result<MultiTickAMM, OrbitalError> {
+        journal::replay(&self.events[..index])
+    }
+
+    pub fn undo_depth(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn redo_depth(&self) -> usize {
+        self.redo.len()
+    }
+
+    pub fn checkpoint_names(&self) -> Vec<String> {
+        self.checkpoints.keys().cloned().collect()
+    }
+
+    /// Operation that undoes the last undoable operation.
+    pub fn undo_op(&self) -> Result<Operation, OrbitalError> {
+        let &index = self.undo.last().ok_or(OrbitalError::NothingToUndo)?;
+        Ok(Operation::Undo { state: Box::new(self.state_before(index)?) })
+    }
+
+    /// Operation that re-applies the last undone operation.
+    pub fn redo_op(&self) -> Result<Operation, OrbitalError> {
+        let &index = self.redo.last().ok_or(OrbitalError::NothingToRedo)?;
+        Ok(Operation::Redo { state: Box::new(self.state_before(index)?) })
+    }
+
+    /// Operation that returns to the checkpoint `name`.
+    pub fn restore_op(&self, name: &str) -> Result<Operation, OrbitalError> {
+        let &index = self.checkpoints
+            .get(name)
+            .ok_or_else(|| OrbitalError::UnknownCheckpoint(name.to_string()))?;
+        let state = self.state_before(index)?;
+        Ok(Operation::Restore { name: name.to_string(), state: Box::new(state) })
+    }
+}
+
+impl MultiTickAMM {
+    /// History of this pool; empty if it has no journal yet, or only one
+    /// from an older release that the next commit archives.
+    pub fn history(&self) -> Result<History, OrbitalError> {
+        let path = self.journal_path();
+        if !path.exists() || journal::is_outdated(&path)? {
+            return Ok(History::default());
+        }
+        Ok(History::from_events(journal::read_journal(&path)?))
+    }
+
+    pub fn undo(&mut self) -> Result<Value, OrbitalError> {
+        let op = self.history()?.undo_op()?;
+        self.commit(op)
+    }
+
+    pub fn redo(&mut self) -> Result<Value, OrbitalError> {
+        let op = self.history()?.redo_op()?;
+        self.commit(op)
+    }
+
+    /// Record the current state under `name`, replacing any older checkpoint
+    /// of that name.
+    pub fn checkpoint(&mut self, name: &str) -> Result<Value, OrbitalError> {
+        self.commit(Operation::Checkpoint { name: name.to_string() })
+    }
+
+    /// Return to the checkpoint `name`. The restore can itself be undone.
+    pub fn restore(&mut self, name: &str) -> Result<Value, OrbitalError> {
+        let op = self.history()?.restore_op(name)?;
+        self.commit(op)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn trade(amount: f64) -> Operation {
+        Operation::Trade {
+            from: "USDC".into(),
+            to: "USDT".into(),
+            amount,
+            exact_out: false,
+            max_amount_in: None,
+        }
+    }
+
+    #[test]
+    fn test_undo_redo_and_checkpoints() {
+        let dir = crate::testing::temp_dir("history");
+        let path = dir.join("multi_tick.json");
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut amm = MultiTickAMM::new(names).with_state_path(&path);
+        assert_eq!(amm.undo().unwrap_err(), OrbitalError::NothingToUndo);
+
+        amm.commit(Operation::AddTick {
+            plane: Some(150.0),
+            depeg_price: None,
+            reserves: vec![100.0, 100.0],
+            fee_bps: 0,
+        }).unwrap();
+        amm.checkpoint("base").unwrap();
+        let base = amm.global_reserves.clone();
+        amm.commit(trade(10.0)).unwrap();
+        let after_trade = amm.global_reserves.clone();
+        amm.commit(trade(5.0)).unwrap();
+
+        amm.undo().unwrap();
+        assert_eq!(amm.global_reserves, after_trade);
+        amm.undo().unwrap();
+        assert_eq!(amm.global_reserves, base);
+        amm.redo().unwrap();
+        assert_eq!(amm.global_reserves, after_trade);
+
+        // A new operation drops the redo stack.
+        amm.commit(trade(1.0)).unwrap();
+        assert_eq!(amm.redo().unwrap_err(), OrbitalError::NothingToRedo);
+
+        amm.restore("base").unwrap();
+        assert_eq!(amm.global_reserves, base);
+        assert_eq!(amm.restore("nope").unwrap_err().code(), "unknown_checkpoint");
+        amm.undo().unwrap();
+        assert_ne!(amm.global_reserves, base);
+
+        let history = amm.history().unwrap();
+        assert_eq!(history.checkpoint_names(), vec!["base".to_string()]);
+        assert_eq!(history.redo_depth(), 1);
+        let ops: Vec<&str> = history.entries
+            .iter()
+            .map(|e| e.op.as_str())
+            .collect();
+        assert_eq!(ops[..3], ["genesis", "add_tick", "checkpoint"]);
+        assert!(history.entries.iter().all(|e| e.params.get("state").is_none()));
+
+        // The journal, history moves included, still reproduces the snapshot.
+        let rebuilt = journal::replay(&journal::read_journal(&amm.journal_path()).unwrap());
+        let snapshot = MultiTickAMM::load_state(&path, Vec::new()).unwrap();
+        journal::verify_same(&rebuilt.unwrap(), &snapshot).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_history_rebuilds_only_the_state_it_returns_to() {
+        let dir = crate::testing::temp_dir("undo");
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut amm = MultiTickAMM::new(names).with_state_path(dir.join("multi_tick.json"));
+        amm.commit(Operation::AddTick {
+            plane: Some(150.0),
+            depeg_price: None,
+            reserves: vec![100.0, 100.0],
+            fee_bps: 0,
+        }).unwrap();
+        let base = amm.global_reserves.clone();
+        amm.commit(trade(10.0)).unwrap();
+
+        // Undoing the trade replays the journal only up to it, so a bad
+        // record after that point does not get in the way.
+        let mut events = journal::read_journal(&amm.journal_path()).unwrap();
+        events.last_mut().unwrap().reserves_after = vec![0.0, 0.0];
+        let history = History::from_events(events);
+        assert_eq!(history.undo_depth(), 2);
+        let Operation::Undo { state } = history.undo_op().unwrap() else {
+            panic!("expected an undo");
+        };
+        assert_eq!(state.global_reserves, base);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
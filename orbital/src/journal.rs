@@ -0,0 +1,699 @@
+//! Append-only JSONL journal of every mutation applied to a `MultiTickAMM`.
+//!
+//! The first line of a journal is a `genesis` event holding the full pool
+//! state at the time journaling started; every later line is an operation
+//! with its inputs, outputs and the resulting global reserves. Replaying the
+//! journal from genesis must reproduce the snapshot exactly.
+//!
+//! The genesis event records the `JOURNAL_VERSION` the journal was started
+//! with. Operations from an older version may have applied differently, so
+//! such a journal is never replayed: it is archived on the next commit and a
+//! new one starts from the current snapshot.
+
+use std::collections::HashMap;
+use std::fs::{ self, File, OpenOptions };
+use std::io::{ BufRead, BufReader, Write };
+use std::path::{ Path, PathBuf };
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use serde::{ Deserialize, Serialize };
+use serde_json::{ json, Value };
+
+use crate::error::OrbitalError;
+use crate::sphere::SphereAMM;
+use crate::ticks::MultiTickAMM;
+
+/// Absolute tolerance when comparing replayed reserves with the recorded ones.
+const REPLAY_TOLERANCE: f64 = 1e-9;
+
+/// Version of the operation semantics written by this release. Version 0
+/// journals predate LP shares minted against tick holdings.
+pub const JOURNAL_VERSION: u32 = 1;
+
+/// A state-changing operation on a `MultiTickAMM`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    /// Pool state journaling starts from.
+    Genesis {
+        state: Box<MultiTickAMM>,
+        /// `JOURNAL_VERSION` of the journal; absent before versioning.
+        #[serde(default)]
+        version: u32,
+    },
+    Trade {
+        from: String,
+        to: String,
+        /// Input amount, or the exact output amount when `exact_out` is set.
+        amount: f64,
+        #[serde(default)]
+        exact_out: bool,
+        #[serde(default)]
+        max_amount_in: Option<f64>,
+    },
+    AddTick {
+        /// Raw plane constant; ignored when `depeg_price` is given.
+        plane: Option<f64>,
+        depeg_price: Option<f64>,
+        reserves: Vec<f64>,
+        #[serde(default)]
+        fee_bps: u32,
+    },
+    RemoveTick {
+        tick_index: usize,
+    },
+    /// Overwrite a tick's reserves and re-solve its radius.
+    SetReserves {
+        tick_index: usize,
+        reserves: Vec<f64>,
+    },
+    AddLiquidity {
+        tick_index: usize,
+        lp_id: String,
+        amounts: Vec<f64>,
+    },
+    RemoveLiquidity {
+        tick_index: usize,
+        lp_id: String,
+        percentage: f64,
+    },
+    /// Replace the pool with a single tick over a new token set.
+    Reconfigure {
+        token_names: Vec<String>,
+        initial_reserves: Vec<f64>,
+        initial_plane: f64,
+    },
+    /// Replace the pool with a single tick over the current tokens.
+    Reset {
+        initial_reserves: Vec<f64>,
+        initial_plane: f64,
+    },
+    /// Return to the state before the last undoable operation.
+    Undo {
+        state: Box<MultiTickAMM>,
+    },
+    /// Re-apply the last undone operation.
+    Redo {
+        state: Box<MultiTickAMM>,
+    },
+    /// Name the current state so it can be restored later.
+    Checkpoint {
+        name: String,
+    },
+    /// Return to a named checkpoint.
+    Restore {
+        name: String,
+        state: Box<MultiTickAMM>,
+    },
+    /// Several operations applied in order as one: they all take effect or
+    /// none does, and a single undo reverts them together.
+    Batch {
+        ops: Vec<Operation>,
+    },
+}
+
+impl Operation {
+    /// The operation's `op` tag and its remaining fields, leaving out embedded
+    /// pool states.
+    pub fn summary(&self) -> (String, Value) {
+        let mut params = serde_json::to_value(self).unwrap_or(Value::Null);
+        let mut name = String::new();
+        if let Value::Object(map) = &mut params {
+            if let Some(Value::String(op)) = map.remove("op") {
+                name = op;
+            }
+            map.remove("state");
+        }
+        (name, params)
+    }
+
+    /// Apply the operation to `amm` and return its outputs. On error `amm`
+    /// may be partially modified; callers work on a clone.
+    pub fn apply(&self, amm: &mut MultiTickAMM) -> Result<Value, OrbitalError> {
+        match self {
+            | Operation::Genesis { state, .. }
+            | Operation::Undo { state }
+            | Operation::Redo { state }
+            | Operation::Restore { state, .. } => {
+                let path = amm.state_path().to_path_buf();
+                *amm = state.as_ref().clone().with_state_path(path);
+                Ok(Value::Null)
+            }
+            Operation::Checkpoint { name } => Ok(json!({ "name": name })),
+            Operation::Trade { from, to, amount, exact_out, max_amount_in } => {
+                let (amount_in, amount_out) = if *exact_out {
+                    let max_in = max_amount_in.unwrap_or(f64::INFINITY);
+                    (amm.route_trade_exact_out(from, to, *amount, max_in)?, *amount)
+                } else {
+                    (*amount, amm.route_trade(from, to, *amount)?)
+                };
+                Ok(json!({ "amount_in": amount_in, "amount_out": amount_out }))
+            }
+            Operation::AddTick { plane, depeg_price, reserves, fee_bps } => {
+                check_length(amm, reserves)?;
+                let params = match (depeg_price, plane) {
+                    (Some(price), _) => {
+                        Some(amm.add_tick_from_depeg(reserves.clone(), *price, *fee_bps)?)
+                    }
+                    (None, Some(plane)) => {
+                        amm.add_tick_with_fee(*plane, reserves.clone(), *fee_bps)?;
+                        None
+                    }
+                    (None, None) => {
+                        return Err(OrbitalError::MissingPlane);
+                    }
+                };
+                let tick_index = amm.ticks.len() - 1;
+                Ok(
+                    json!({
+                    "tick_index": tick_index,
+                    "plane_constant": amm.ticks[tick_index].plane_constant,
+                    "params": params,
+                })
+                )
+            }
+            Operation::RemoveTick { tick_index } => {
+                let tick = amm.remove_tick(*tick_index)?;
+                Ok(json!({ "reserves": tick.sphere_amm.reserves }))
+            }
+            Operation::SetReserves { tick_index, reserves } => {
+                check_length(amm, reserves)?;
+                let radius = SphereAMM::checked_radius(reserves)?;
+                let tick = amm.ticks
+                    .get_mut(*tick_index)
+                    .ok_or(OrbitalError::UnknownTick(*tick_index))?;
+                tick.sphere_amm.reserves = reserves.clone();
+                tick.sphere_amm.radius = radius;
+                amm.recompute_global_reserves();
+                Ok(json!({ "radius": radius }))
+            }
+            Operation::AddLiquidity { tick_index, lp_id, amounts } => {
+                let deposit = amm.ticks
+                    .get_mut(*tick_index)
+                    .ok_or(OrbitalError::UnknownTick(*tick_index))?
+                    .add_liquidity(lp_id, amounts)?;
+                amm.recompute_global_reserves();
+                Ok(
+                    json!({
+                    "deposited": deposit.deposited,
+                    "refunded": deposit.refunded,
+                    "shares": deposit.shares,
+                })
+                )
+            }
+            Operation::RemoveLiquidity { tick_index, lp_id, percentage } => {
+                let withdrawn = amm.ticks
+                    .get_mut(*tick_index)
+                    .ok_or(OrbitalError::UnknownTick(*tick_index))?
+                    .withdraw_liquidity(lp_id, *percentage)?;
+                amm.recompute_global_reserves();
+                Ok(json!({ "withdrawn": withdrawn }))
+            }
+            Operation::Reconfigure { token_names, initial_reserves, initial_plane } => {
+                reinitialise(amm, token_names.clone(), initial_reserves, *initial_plane)
+            }
+            Operation::Reset { initial_reserves, initial_plane } => {
+                let token_names = amm.token_names.clone();
+                reinitialise(amm, token_names, initial_reserves, *initial_plane)
+            }
+            Operation::Batch { ops } => {
+                let outputs = ops
+                    .iter()
+                    .map(|op| op.apply(amm))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::Array(outputs))
+            }
+        }
+    }
+}
+
+fn check_length(amm: &MultiTickAMM, reserves: &[f64]) -> Result<(), OrbitalError> {
+    if reserves.len() != amm.token_names.len() {
+        return Err(OrbitalError::LengthMismatch {
+            expected: amm.token_names.len(),
+            got: reserves.len(),
+        });
+    }
+    Ok(())
+}
+
+fn reinitialise(
+    amm: &mut MultiTickAMM,
+    token_names: Vec<String>,
+    reserves: &[f64],
+    plane: f64
+) -> Result<Value, OrbitalError> {
+    if reserves.len() != token_names.len() {
+        return Err(OrbitalError::LengthMismatch {
+            expected: token_names.len(),
+            got: reserves.len(),
+        });
+    }
+    let path = amm.state_path().to_path_buf();
+    *amm = MultiTickAMM::new(token_names).with_state_path(path);
+    amm.add_tick(plane, reserves.to_vec())?;
+    Ok(Value::Null)
+}
+
+/// One line of the journal.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Event {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub op: Operation,
+    pub output: Value,
+    pub reserves_after: Vec<f64>,
+}
+
+impl Event {
+    fn new(op: Operation, output: Value, reserves_after: &[f64]) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        Event { timestamp_ms, op, output, reserves_after: reserves_after.to_vec() }
+    }
+}
+
+/// Journal kept next to a state file: `multi_tick.json` → `multi_tick.journal.jsonl`.
+pub fn journal_path(state_path: &Path) -> PathBuf {
+    let stem = state_path.file_stem().unwrap_or_default().to_string_lossy();
+    state_path.with_file_name(format!("{}.journal.jsonl", stem))
+}
+
+fn append(path: &Path, event: &Event) -> Result<(), OrbitalError> {
+    let io = |e: std::io::Error| OrbitalError::Io(format!("{}: {}", path.display(), e));
+    let mut line = serde_json::to_string(event).map_err(|e| OrbitalError::Io(e.to_string()))?;
+    line.push('\n');
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(io)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path).map_err(io)?;
+    file.write_all(line.as_bytes()).map_err(io)?;
+    file.sync_data().map_err(io)
+}
+
+/// First event of the journal at `path`, without reading the rest; `None`
+/// if there is no journal.
+fn first_event(path: &Path) -> Result<Option<Event>, OrbitalError> {
+    let io = |e: std::io::Error| OrbitalError::Io(format!("{}: {}", path.display(), e));
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(None);
+        }
+        Err(e) => {
+            return Err(io(e));
+        }
+    };
+    let mut first = String::new();
+    BufReader::new(file).read_line(&mut first).map_err(io)?;
+    serde_json
+        ::from_str(&first)
+        .map(Some)
+        .map_err(|e| OrbitalError::CorruptState(format!("{}:1: {}", path.display(), e)))
+}
+
+/// Whether the journal at `path` exists and was started by an older release,
+/// judging by its genesis event alone.
+pub fn is_outdated(path: &Path) -> Result<bool, OrbitalError> {
+    Ok(
+        matches!(
+            first_event(path)?,
+            Some(Event { op: Operation::Genesis { version, .. }, .. }) if version < JOURNAL_VERSION
+        )
+    )
+}
+
+/// Where an outdated journal whose genesis event is from `started_ms` is
+/// moved: `multi_tick.journal.jsonl` → `multi_tick.journal.<started_ms>.jsonl`.
+pub fn archive_path(journal: &Path, started_ms: u64) -> PathBuf {
+    journal.with_extension(format!("{}.jsonl", started_ms))
+}
+
+/// Archived journals next to `journal`, oldest first.
+pub fn archived_journals(journal: &Path) -> Vec<PathBuf> {
+    let name = journal.file_name().unwrap_or_default().to_string_lossy();
+    let prefix = format!("{}.", name.trim_end_matches(".jsonl"));
+    let dir = journal.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut archives: Vec<PathBuf> = fs
+        ::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".jsonl"))
+                .is_some_and(|started| started.parse::<u64>().is_ok())
+        })
+        .collect();
+    archives.sort();
+    archives
+}
+
+/// Move an outdated journal aside. An existing archive is never replaced:
+/// the link fails instead and the journal stays where it is.
+fn archive(journal: &Path) -> Result<PathBuf, OrbitalError> {
+    let started_ms = first_event(journal)?.map_or(0, |event| event.timestamp_ms);
+    let archive = archive_path(journal, started_ms);
+    let io = |path: &Path, e: std::io::Error| {
+        OrbitalError::Io(format!("{}: {}", path.display(), e))
+    };
+    fs::hard_link(journal, &archive).map_err(|e| io(&archive, e))?;
+    fs::remove_file(journal).map_err(|e| io(journal, e))?;
+    Ok(archive)
+}
+
+/// Read every event of a journal.
+pub fn read_journal(path: &Path) -> Result<Vec<Event>, OrbitalError> {
+    let text = fs
+        ::read_to_string(path)
+        .map_err(|e| OrbitalError::Io(format!("{}: {}", path.display(), e)))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json
+                ::from_str(line)
+                .map_err(|e| {
+                    OrbitalError::CorruptState(format!("{}:{}: {}", path.display(), i + 1, e))
+                })
+        })
+        .collect()
+}
+
+fn reserves_match(a: &[f64], b: &[f64]) -> bool {
+    a.len() == b.len() &&
+        a
+            .iter()
+            .zip(b)
+            .all(|(x, y)| (x - y).abs() <= REPLAY_TOLERANCE)
+}
+
+/// Rebuild a pool by replaying `events` from genesis, checking the recorded
+/// reserves after every step.
+pub fn replay(events: &[Event]) -> Result<MultiTickAMM, OrbitalError> {
+    let mismatch = |n: usize, what: String| {
+        OrbitalError::ReplayMismatch(format!("event {}: {}", n + 1, what))
+    };
+    match events.first() {
+        Some(Event { op: Operation::Genesis { version, .. }, .. }) => {
+            if *version < JOURNAL_VERSION {
+                return Err(OrbitalError::OutdatedJournal(*version));
+            }
+        }
+        _ => {
+            return Err(mismatch(0, "journal does not start with a genesis event".into()));
+        }
+    }
+    let mut amm = MultiTickAMM::new(Vec::new());
+    for (n, event) in events.iter().enumerate() {
+        event.op.apply(&mut amm).map_err(|e| mismatch(n, e.to_string()))?;
+        if !reserves_match(&amm.global_reserves, &event.reserves_after) {
+            return Err(
+                mismatch(
+                    n,
+                    format!(
+                        "reserves {:?} differ from recorded {:?}",
+                        amm.global_reserves,
+                        event.reserves_after
+                    )
+                )
+            );
+        }
+    }
+    Ok(amm)
+}
+
+/// Check that `a` and `b` describe the same pool, tick by tick.
+pub fn verify_same(a: &MultiTickAMM, b: &MultiTickAMM) -> Result<(), OrbitalError> {
+    let mismatch = |what: String| OrbitalError::ReplayMismatch(what);
+    if a.token_names != b.token_names {
+        return Err(mismatch(format!("tokens {:?} vs {:?}", a.token_names, b.token_names)));
+    }
+    if a.ticks.len() != b.ticks.len() {
+        return Err(mismatch(format!("{} ticks vs {}", a.ticks.len(), b.ticks.len())));
+    }
+    for (i, (x, y)) in a.ticks.iter().zip(&b.ticks).enumerate() {
+        let same_bases = |a: &HashMap<String, Vec<f64>>, b: &HashMap<String, Vec<f64>>| {
+            a.len() == b.len() &&
+                a.iter().all(|(lp, d)| b.get(lp).is_some_and(|e| reserves_match(d, e)))
+        };
+        let same =
+            reserves_match(&x.sphere_amm.reserves, &y.sphere_amm.reserves) &&
+            (x.sphere_amm.radius - y.sphere_amm.radius).abs() <= REPLAY_TOLERANCE &&
+            x.plane_constant == y.plane_constant &&
+            x.fee_bps == y.fee_bps &&
+            reserves_match(&x.fees_accrued, &y.fees_accrued) &&
+            x.lp_shares == y.lp_shares &&
+            same_bases(&x.lp_deposits, &y.lp_deposits) &&
+            same_bases(&x.lp_fee_buy_in, &y.lp_fee_buy_in);
+        if !same {
+            return Err(mismatch(format!("tick {} differs", i)));
+        }
+    }
+    Ok(())
+}
+
+impl MultiTickAMM {
+    /// Journal next to this pool's state file.
+    pub fn journal_path(&self) -> PathBuf {
+        journal_path(self.state_path())
+    }
+
+    /// Apply `op`, save the snapshot and append the operation to the journal.
+    /// Nothing is written and the pool is unchanged if the operation fails.
+    /// The first commit to a pool without a journal, or with an outdated
+    /// one, records a genesis event first; an outdated journal is archived.
+    pub fn commit(&mut self, op: Operation) -> Result<Value, OrbitalError> {
+        let mut next = self.clone();
+        let output = op.apply(&mut next)?;
+        let journal = self.journal_path();
+        if is_outdated(&journal)? {
+            archive(&journal)?;
+        }
+        if !journal.exists() {
+            let genesis = Operation::Genesis {
+                state: Box::new(self.clone()),
+                version: JOURNAL_VERSION,
+            };
+            append(&journal, &Event::new(genesis, Value::Null, &self.global_reserves))?;
+        }
+        // The snapshot goes first: a failed save leaves both files as they
+        // were, and a failed append puts the old snapshot back.
+        next.save_state()?;
+        if let Err(e) = append(&journal, &Event::new(op, output.clone(), &next.global_reserves)) {
+            self.save_state()?;
+            return Err(e);
+        }
+        *self = next;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_reserves_rejects_reserves_on_no_sphere() {
+        let dir = crate::testing::temp_dir("journal-set");
+        let path = dir.join("multi_tick.json");
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into(), "DAI".into()];
+        let mut amm = MultiTickAMM::new(names).with_state_path(&path);
+        amm.add_tick(1800.0, vec![1000.0; 3]).unwrap();
+        amm.save_state().unwrap();
+
+        for reserves in [vec![1000.0, 0.0, 0.0], vec![f64::NAN, 1000.0, 1000.0]] {
+            let op = Operation::SetReserves { tick_index: 0, reserves };
+            assert_eq!(amm.commit(op).unwrap_err().code(), "invalid_reserves");
+        }
+        let loaded = MultiTickAMM::load_state(&path, Vec::new()).unwrap();
+        verify_same(&loaded, &amm).unwrap();
+        assert!(loaded.ticks[0].sphere_amm.radius.is_finite());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_replay_reproduces_snapshot() {
+        let dir = crate::testing::temp_dir("journal");
+        let path = dir.join("multi_tick.json");
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut amm = MultiTickAMM::new(names).with_state_path(&path);
+        let ops = [
+            Operation::AddTick {
+                plane: Some(150.0),
+                depeg_price: None,
+                reserves: vec![100.0, 100.0],
+                fee_bps: 30,
+            },
+            Operation::AddTick {
+                plane: None,
+                depeg_price: Some(0.95),
+                reserves: vec![50.0, 50.0],
+                fee_bps: 0,
+            },
+            Operation::Trade {
+                from: "USDC".into(),
+                to: "USDT".into(),
+                amount: 20.0,
+                exact_out: false,
+                max_amount_in: None,
+            },
+            Operation::AddLiquidity {
+                tick_index: 1,
+                lp_id: "alice".into(),
+                amounts: vec![5.0, 5.0],
+            },
+            Operation::RemoveLiquidity { tick_index: 1, lp_id: "alice".into(), percentage: 0.5 },
+            Operation::SetReserves { tick_index: 0, reserves: vec![90.0, 110.0] },
+            Operation::Batch {
+                ops: vec![
+                    Operation::RemoveLiquidity {
+                        tick_index: 1,
+                        lp_id: "alice".into(),
+                        percentage: 0.5,
+                    },
+                    Operation::SetReserves { tick_index: 0, reserves: vec![100.0, 100.0] }
+                ],
+            },
+        ];
+        for op in ops {
+            amm.commit(op).unwrap();
+        }
+        // A failed operation leaves no trace, even when part of a batch
+        // would succeed on its own.
+        let bad = Operation::RemoveTick { tick_index: 9 };
+        assert!(amm.commit(bad).is_err());
+        let before = amm.clone();
+        let bad = Operation::Batch {
+            ops: vec![
+                Operation::SetReserves { tick_index: 0, reserves: vec![80.0, 120.0] },
+                Operation::RemoveTick { tick_index: 9 }
+            ],
+        };
+        assert!(amm.commit(bad).is_err());
+        verify_same(&amm, &before).unwrap();
+
+        let events = read_journal(&amm.journal_path()).unwrap();
+        assert_eq!(events.len(), 8);
+        let rebuilt = replay(&events).unwrap();
+        let snapshot = MultiTickAMM::load_state(&path, Vec::new()).unwrap();
+        verify_same(&rebuilt, &snapshot).unwrap();
+
+        let mut tampered = snapshot.clone();
+        tampered.ticks[0].sphere_amm.reserves[0] += 1.0;
+        assert_eq!(verify_same(&rebuilt, &tampered).unwrap_err().code(), "replay_mismatch");
+        let mut tampered = snapshot.clone();
+        tampered.ticks[0].fees_accrued[0] = 0.0;
+        assert!(verify_same(&rebuilt, &tampered).is_err());
+        let mut tampered = snapshot.clone();
+        tampered.ticks[1].lp_deposits.remove("alice");
+        assert!(verify_same(&rebuilt, &tampered).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn trade() -> Operation {
+        Operation::Trade {
+            from: "USDC".into(),
+            to: "USDT".into(),
+            amount: 5.0,
+            exact_out: false,
+            max_amount_in: None,
+        }
+    }
+
+    /// A saved pool next to a journal written before versioning, whose
+    /// deposit was taken whole and would be refused if replayed now. Returns
+    /// the pool and the journal's text.
+    fn pool_with_outdated_journal(name: &str) -> (MultiTickAMM, String) {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let path = crate::testing::temp_dir(name).join("multi_tick.json");
+        let mut amm = MultiTickAMM::new(names).with_state_path(path);
+        amm.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        amm.save_state().unwrap();
+        let genesis = json!({
+            "timestamp_ms": 0,
+            "op": "genesis",
+            "state": amm,
+            "output": null,
+            "reserves_after": [100.0, 100.0],
+        });
+        let deposit = json!({
+            "timestamp_ms": 0,
+            "op": "add_liquidity",
+            "tick_index": 0,
+            "lp_id": "alice",
+            "amounts": [10.0, 0.0],
+            "output": null,
+            "reserves_after": [110.0, 100.0],
+        });
+        let outdated = format!("{}\n{}\n", genesis, deposit);
+        fs::write(amm.journal_path(), &outdated).unwrap();
+        (amm, outdated)
+    }
+
+    fn remove_pool_dir(amm: &MultiTickAMM) {
+        fs::remove_dir_all(amm.journal_path().parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_outdated_journal_is_not_replayed() {
+        let (amm, _) = pool_with_outdated_journal("journal-v0-replay");
+        assert!(is_outdated(&amm.journal_path()).unwrap());
+        let events = read_journal(&amm.journal_path()).unwrap();
+        assert_eq!(replay(&events).unwrap_err(), OrbitalError::OutdatedJournal(0));
+        remove_pool_dir(&amm);
+    }
+
+    #[test]
+    fn test_outdated_journal_has_no_history() {
+        let (mut amm, _) = pool_with_outdated_journal("journal-v0-history");
+        assert_eq!(amm.history().unwrap().undo_depth(), 0);
+        assert_eq!(amm.undo().unwrap_err(), OrbitalError::NothingToUndo);
+        remove_pool_dir(&amm);
+    }
+
+    #[test]
+    fn test_commit_archives_an_outdated_journal() {
+        let (mut amm, _) = pool_with_outdated_journal("journal-v0-archive");
+        amm.commit(trade()).unwrap();
+        let journal = amm.journal_path();
+        assert_eq!(archived_journals(&journal), vec![archive_path(&journal, 0)]);
+        assert!(!is_outdated(&journal).unwrap());
+        let events = read_journal(&journal).unwrap();
+        assert_eq!(events.len(), 2);
+        verify_same(&replay(&events).unwrap(), &amm).unwrap();
+        amm.undo().unwrap();
+        assert_eq!(amm.global_reserves, vec![100.0, 100.0]);
+        remove_pool_dir(&amm);
+    }
+
+    #[test]
+    fn test_archive_never_overwrites_an_earlier_one() {
+        let (mut amm, outdated) = pool_with_outdated_journal("journal-v0-collision");
+        amm.commit(trade()).unwrap();
+        // A second archive with the same start time would overwrite the
+        // first, so the commit fails and both journals stay as they were.
+        fs::write(amm.journal_path(), &outdated).unwrap();
+        let err = amm.commit(trade()).unwrap_err();
+        assert_eq!(err.code(), "io_error");
+        assert_eq!(fs::read_to_string(amm.journal_path()).unwrap(), outdated);
+        assert_eq!(archived_journals(&amm.journal_path()).len(), 1);
+        remove_pool_dir(&amm);
+    }
+
+    #[test]
+    fn test_archives_of_other_start_times_sit_side_by_side() {
+        let (mut amm, outdated) = pool_with_outdated_journal("journal-v0-later");
+        amm.commit(trade()).unwrap();
+        let later = outdated.replacen("\"timestamp_ms\":0", "\"timestamp_ms\":1", 1);
+        fs::write(amm.journal_path(), later).unwrap();
+        amm.commit(trade()).unwrap();
+        assert_eq!(archived_journals(&amm.journal_path()).len(), 2);
+        remove_pool_dir(&amm);
+    }
+}
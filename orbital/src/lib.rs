@@ -0,0 +1,62 @@
+//! Simulator for Paradigm's Orbital AMM.
+//!
+//! The math lives in a handful of modules with no server or terminal
+//! dependencies:
+//!
+//! * [`sphere`] – the single-sphere invariant [`SphereAMM`] and the geometry
+//!   helpers (plane constants, depeg prices, capital efficiency).
+//! * [`ticks`] – concentrated liquidity: [`OrbitalTick`] and the torus-routed
+//!   [`MultiTickAMM`].
+//! * [`scalar`] – the numeric backends the sphere math runs on.
+//! * [`error`] – [`OrbitalError`], returned by every fallible operation.
+//! * [`persist`] – atomic, versioned state files.
+//! * [`journal`] – append-only log of pool mutations and its replay.
+//! * [`history`] – undo, redo and checkpoints rebuilt from the journal.
+//! * [`lp`] – LP positions valued against holding, with fee income.
+//! * [`arbitrage`] – trades that align a pool with external reference prices.
+//! * [`router`] – multi-hop routing and path splitting across pools.
+//! * [`simulate`] – seeded agent-based simulations producing time series.
+//! * [`backtest`] – replays of historical price files with LP PnL reports.
+//!
+//! The HTTP server and the terminal UI are behind the `server` and `tui`
+//! features. Depend on the crate with `default-features = false` to get the
+//! math alone.
+//!
+//! ```
+//! use orbital::{ MultiTickAMM, SphereAMM };
+//!
+//! let names: Vec<String> = vec!["USDC".into(), "USDT".into(), "DAI".into()];
+//! let mut pool = SphereAMM::new(names.clone(), vec![1000.0; 3]);
+//! let out = pool.swap("USDC", "USDT", 10.0).unwrap();
+//! assert!(out > 0.0 && pool.check_invariant());
+//!
+//! let mut multi = MultiTickAMM::new(names);
+//! multi.add_tick_from_depeg(vec![1000.0; 3], 0.99, 5).unwrap();
+//! let quote = multi.quote_trade("USDC", "DAI", 5.0).unwrap();
+//! assert!(quote.amount_out > 0.0);
+//! ```
+
+pub mod arbitrage;
+pub mod backtest;
+pub mod error;
+pub mod history;
+pub mod journal;
+pub mod lp;
+pub mod persist;
+pub mod router;
+pub mod scalar;
+pub mod simulate;
+pub mod sphere;
+pub mod ticks;
+
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "tui")]
+pub mod ui;
+
+pub use error::OrbitalError;
+pub use scalar::{ Exact, Fixed18, Rounding, Scalar };
+pub use sphere::{ DepegTickParams, SphereAMM, SwapQuote };
+pub use ticks::{ MultiTickAMM, OrbitalTick, PoolEfficiency, RouteQuote, TickFill, TorusInvariant };
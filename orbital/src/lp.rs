@@ -0,0 +1,323 @@
+//! LP position analytics.
+//!
+//! A position is what an LP would get by withdrawing everything from a tick
+//! now: its fraction of the tick's reserves and of its accrued fees. It is
+//! compared with holding the tokens the LP deposited instead.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::error::OrbitalError;
+use crate::ticks::MultiTickAMM;
+
+/// One LP's position in one tick, valued in the numeraire of the prices.
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct LpPosition {
+    pub tick_index: usize,
+    pub shares: f64,
+    /// Fraction of the tick's reserves and fees the LP owns.
+    pub fraction: f64,
+    /// Token amounts the LP owns in the tick, fees excluded.
+    pub amounts: Vec<f64>,
+    /// The LP's slice of the tick's accrued fees.
+    pub fees: Vec<f64>,
+    /// Net token amounts deposited.
+    pub deposited: Vec<f64>,
+    /// The part of `deposited` that bought into fees accrued before it.
+    pub fee_buy_in: Vec<f64>,
+    pub value: f64,
+    /// Value of `fees`, including the part bought with `fee_buy_in`.
+    pub fee_value: f64,
+    /// `fee_value` less the value of `fee_buy_in`: fees earned since the
+    /// deposit.
+    pub fee_income: f64,
+    /// Value of `deposited`, had it been held.
+    pub value_if_held: f64,
+    /// `value` against holding `deposited` less `fee_buy_in`, fees excluded
+    /// on both sides.
+    pub impermanent_loss: f64,
+    /// `value + fee_value - value_if_held`.
+    pub pnl: f64,
+}
+
+/// Every position of an LP, with totals over ticks.
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct LpReport {
+    pub lp_id: String,
+    pub token_names: Vec<String>,
+    /// Prices the positions are valued at.
+    pub prices: Vec<f64>,
+    pub positions: Vec<LpPosition>,
+    pub amounts: Vec<f64>,
+    pub fees: Vec<f64>,
+    pub value: f64,
+    pub fee_value: f64,
+    pub fee_income: f64,
+    pub value_if_held: f64,
+    pub impermanent_loss: f64,
+    pub pnl: f64,
+}
+
+fn value(amounts: &[f64], prices: &[f64]) -> f64 {
+    amounts
+        .iter()
+        .zip(prices)
+        .map(|(a, p)| a * p)
+        .sum()
+}
+
+/// `value / held - 1`, or zero when nothing is held.
+fn loss(value: f64, held: f64) -> f64 {
+    if held > 0.0 { value / held - 1.0 } else { 0.0 }
+}
+
+impl MultiTickAMM {
+    /// Pool price of every token in units of the first one.
+    pub fn pool_prices(&self) -> Result<Vec<f64>, OrbitalError> {
+        let base = self.token_names.first().ok_or(OrbitalError::InsufficientLiquidity)?;
+        self.token_names
+            .iter()
+            .map(|t| self.get_aggregated_price(base, t))
+            .collect()
+    }
+
+    /// Positions of `lp_id` across ticks, valued at `prices` (one per token,
+    /// in a common numeraire) or at the pool's own prices when absent.
+    pub fn lp_report(&self, lp_id: &str, prices: Option<&[f64]>) -> Result<LpReport, OrbitalError> {
+        let n = self.token_names.len();
+        let prices = match prices {
+            Some(prices) => {
+                if prices.len() != n {
+                    return Err(OrbitalError::LengthMismatch { expected: n, got: prices.len() });
+                }
+                if let Some(&p) = prices.iter().find(|p| !(p.is_finite() && **p > 0.0)) {
+                    return Err(OrbitalError::InvalidPrice(p));
+                }
+                prices.to_vec()
+            }
+            None => self.pool_prices()?,
+        };
+
+        let mut positions = Vec::new();
+        for (tick_index, tick) in self.ticks.iter().enumerate() {
+            let Some(&shares) = tick.lp_shares.get(lp_id) else {
+                continue;
+            };
+            let fraction = tick.lp_fraction(lp_id);
+            let scaled = |v: &[f64]| -> Vec<f64> {
+                (0..n)
+                    .map(|i| v.get(i).copied().unwrap_or(0.0) * fraction)
+                    .collect()
+            };
+            let amounts = scaled(&tick.sphere_amm.reserves);
+            let fees = scaled(&tick.fees_accrued);
+            let basis = |bases: &HashMap<String, Vec<f64>>| -> Vec<f64> {
+                let mut basis = bases.get(lp_id).cloned().unwrap_or_default();
+                basis.resize(n, 0.0);
+                basis
+            };
+            let deposited = basis(&tick.lp_deposits);
+            let fee_buy_in = basis(&tick.lp_fee_buy_in);
+            let (value, fee_value) = (value(&amounts, &prices), value(&fees, &prices));
+            let value_if_held = self::value(&deposited, &prices);
+            let buy_in_value = self::value(&fee_buy_in, &prices);
+            let reserves_if_held = value_if_held - buy_in_value;
+            positions.push(LpPosition {
+                tick_index,
+                shares,
+                fraction,
+                amounts,
+                fees,
+                deposited,
+                fee_buy_in,
+                value,
+                fee_value,
+                fee_income: fee_value - buy_in_value,
+                value_if_held,
+                impermanent_loss: loss(value, reserves_if_held),
+                pnl: value + fee_value - value_if_held,
+            });
+        }
+        if positions.is_empty() {
+            return Err(OrbitalError::UnknownLp(lp_id.to_string()));
+        }
+
+        let sum = |f: &dyn Fn(&LpPosition) -> &Vec<f64>| -> Vec<f64> {
+            (0..n)
+                .map(|i| {
+                    positions
+                        .iter()
+                        .map(|p| f(p)[i])
+                        .sum()
+                })
+                .collect()
+        };
+        let amounts = sum(&|p| &p.amounts);
+        let fees = sum(&|p| &p.fees);
+        let total = |f: fn(&LpPosition) -> f64| positions.iter().map(f).sum::<f64>();
+        let (value, fee_value) = (total(|p| p.value), total(|p| p.fee_value));
+        let (fee_income, value_if_held) = (total(|p| p.fee_income), total(|p| p.value_if_held));
+        let reserves_if_held = value_if_held + fee_income - fee_value;
+        Ok(LpReport {
+            lp_id: lp_id.to_string(),
+            token_names: self.token_names.clone(),
+            prices,
+            amounts,
+            fees,
+            value,
+            fee_value,
+            fee_income,
+            value_if_held,
+            impermanent_loss: loss(value, reserves_if_held),
+            pnl: value + fee_value - value_if_held,
+            positions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::Operation;
+    use crate::testing::{ stable_names, stable_pool };
+    use crate::ticks::CREATOR_LP;
+
+    const PRICES: [f64; 3] = [1.0, 0.99, 1.01];
+
+    /// A pool that has charged fees, and alice's deposit of 100 of each
+    /// token into it after that, with the net amounts deposited.
+    fn pool_with_alice() -> (MultiTickAMM, Vec<f64>) {
+        let mut amm = stable_pool(0.99, 30);
+        amm.route_trade("USDC", "USDT", 5.0).unwrap();
+        let deposit = Operation::AddLiquidity {
+            tick_index: 0,
+            lp_id: "alice".into(),
+            amounts: vec![100.0; 3],
+        };
+        let output = deposit.apply(&mut amm).unwrap();
+        let deposited = serde_json::from_value(output["deposited"].clone()).unwrap();
+        (amm, deposited)
+    }
+
+    #[test]
+    fn test_new_position_is_worth_its_deposit() {
+        let (amm, deposited) = pool_with_alice();
+        let report = amm.lp_report("alice", Some(&PRICES)).unwrap();
+        assert_eq!(report.positions[0].deposited, deposited);
+        assert!(report.pnl.abs() < 1e-9);
+        let expected = report.value + report.fee_value - report.value_if_held;
+        assert!((report.pnl - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fees_owned_at_deposit_are_bought_not_lost() {
+        let (amm, _) = pool_with_alice();
+        let report = amm.lp_report("alice", Some(&PRICES)).unwrap();
+        let position = &report.positions[0];
+        assert!(position.fees[0] > 0.0 && position.fee_value > 0.0);
+        let buy_in: f64 = position.fee_buy_in
+            .iter()
+            .zip(&PRICES)
+            .map(|(f, p)| f * p)
+            .sum();
+        assert!((buy_in - position.fee_value).abs() < 1e-9);
+        assert!(position.impermanent_loss.abs() < 1e-9);
+        assert!(report.impermanent_loss.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fee_income_is_zero_right_after_a_deposit() {
+        let (amm, _) = pool_with_alice();
+        let report = amm.lp_report("alice", Some(&[1.0; 3])).unwrap();
+        assert!(report.fee_value > 0.0);
+        assert!(report.fee_income.abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_fee_income_counts_fees_charged_after_the_deposit() {
+        let (mut amm, _) = pool_with_alice();
+        let fraction = amm.ticks[0].lp_fraction("alice");
+        let before = amm.ticks[0].fees_accrued[1];
+        amm.route_trade("USDT", "USDC", 5.0).unwrap();
+        let earned = (amm.ticks[0].fees_accrued[1] - before) * fraction;
+        let report = amm.lp_report("alice", Some(&[1.0; 3])).unwrap();
+        assert!(earned > 0.0);
+        assert!((report.positions[0].fee_income - earned).abs() < 1e-12);
+        assert!((report.fee_income - earned).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_creator_fees_are_all_income() {
+        let (amm, _) = pool_with_alice();
+        let creator = amm.lp_report(CREATOR_LP, Some(&PRICES)).unwrap();
+        assert_eq!(creator.positions[0].deposited, vec![1000.0; 3]);
+        assert!((creator.fee_income - creator.fee_value).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_position_is_what_a_full_withdrawal_pays() {
+        let (amm, _) = pool_with_alice();
+        let position = amm.lp_report("alice", Some(&PRICES)).unwrap().positions[0].clone();
+        let withdrawn = amm.ticks[0].clone().withdraw_liquidity("alice", 1.0).unwrap();
+        for (i, w) in withdrawn.iter().enumerate() {
+            assert!((position.amounts[i] + position.fees[i] - w).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_half_withdrawal_halves_the_basis() {
+        let (mut amm, deposited) = pool_with_alice();
+        let full = amm.lp_report("alice", Some(&PRICES)).unwrap().positions[0].clone();
+        let withdrawal = Operation::RemoveLiquidity {
+            tick_index: 0,
+            lp_id: "alice".into(),
+            percentage: 0.5,
+        };
+        withdrawal.apply(&mut amm).unwrap();
+        let half = amm.lp_report("alice", Some(&PRICES)).unwrap();
+        for (h, d) in half.positions[0].deposited.iter().zip(&deposited) {
+            assert!((h - d / 2.0).abs() < 1e-9);
+        }
+        for (h, b) in half.positions[0].fee_buy_in.iter().zip(&full.fee_buy_in) {
+            assert!((h - b / 2.0).abs() < 1e-9);
+        }
+        assert!(half.impermanent_loss.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rejects_an_unknown_lp() {
+        let (amm, _) = pool_with_alice();
+        assert_eq!(amm.lp_report("bob", None).unwrap_err(), OrbitalError::UnknownLp("bob".into()));
+    }
+
+    #[test]
+    fn test_rejects_prices_of_the_wrong_length() {
+        let (amm, _) = pool_with_alice();
+        let err = amm.lp_report("alice", Some(&[1.0, 1.0])).unwrap_err();
+        assert_eq!(err, OrbitalError::LengthMismatch { expected: 3, got: 2 });
+    }
+
+    #[test]
+    fn test_rejects_prices_that_are_not_positive_and_finite() {
+        let (amm, _) = pool_with_alice();
+        for bad in [0.0, -1.0, f64::INFINITY] {
+            let err = amm.lp_report("alice", Some(&[1.0, bad, 1.0])).unwrap_err();
+            assert_eq!(err, OrbitalError::InvalidPrice(bad));
+        }
+        let err = amm.lp_report("alice", Some(&[1.0, f64::NAN, 1.0])).unwrap_err();
+        assert_eq!(err.code(), "invalid_price");
+    }
+
+    #[test]
+    fn test_pool_without_ticks_has_no_positions() {
+        let amm = MultiTickAMM::new(stable_names());
+        let err = amm.lp_report(CREATOR_LP, Some(&[1.0; 3])).unwrap_err();
+        assert_eq!(err.code(), "unknown_lp");
+        // Nor any prices of its own to value them at.
+        assert!(amm.lp_report(CREATOR_LP, None).is_err());
+        assert!(MultiTickAMM::new(Vec::new()).pool_prices().is_err());
+    }
+}
@@ -1,18 +1,59 @@
-mod sphere;
-mod ticks;
-mod server;
+use std::path::{ Path, PathBuf };
 
-use clap::{ Parser, Subcommand };
-use sphere::SphereAMM;
+use clap::{ Parser, Subcommand, ValueEnum };
+use orbital::{ MultiTickAMM, OrbitalError, SphereAMM };
+use orbital::backtest;
+use orbital::journal::{ self, Operation };
+use orbital::persist;
+use orbital::router::{ self, Router };
+use orbital::simulate::{ self, SimConfig };
+use orbital::sphere::DEFAULT_POOL_FILE;
+use orbital::ticks::DEFAULT_STATE_FILE;
+#[cfg(feature = "server")]
+use orbital::server;
 
 #[derive(Parser)]
 #[command(name = "orbital")]
 #[command(about = "A simple CLI simulator for Paradigm's Orbital AMM")]
 struct Cli {
+    /// State file to operate on instead of multi_tick.json / orbital_pool.json
+    #[arg(long, global = true, env = "ORBITAL_STATE", conflicts_with = "pool")]
+    state: Option<PathBuf>,
+    /// Named pool, stored under pools/<name>/
+    #[arg(long, global = true, value_parser = parse_pool_name)]
+    pool: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
 
+impl Cli {
+    /// State file for this invocation; `default` is the file name used when
+    /// neither `--state` nor `--pool` is given.
+    fn state_path(&self, default: &str) -> PathBuf {
+        match (&self.state, &self.pool) {
+            (Some(path), _) => path.clone(),
+            (None, Some(pool)) => Path::new(persist::POOLS_DIR).join(pool).join(default),
+            (None, None) => PathBuf::from(default),
+        }
+    }
+}
+
+fn parse_pool_name(name: &str) -> Result<String, String> {
+    persist::validate_pool_name(name).map_err(|e| e.to_string())?;
+    Ok(name.to_string())
+}
+
+/// Like `parse_pool_name`, but also accepts the default pool's id.
+fn parse_route_pool(name: &str) -> Result<String, String> {
+    if name == persist::DEFAULT_POOL { Ok(name.to_string()) } else { parse_pool_name(name) }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Csv,
+    Json,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize a new Orbital pool with specified reserves
@@ -27,8 +68,26 @@ enum Commands {
         from: String,
         /// Token to swap to
         to: String,
-        /// Amount to swap
+        /// Amount to swap (the exact output amount with `--exact-out`)
+        amount: f64,
+        /// Treat `amount` as the exact amount of `to` to receive
+        #[arg(long)]
+        exact_out: bool,
+        /// Maximum input to pay for an exact-output swap
+        #[arg(long, requires = "exact_out")]
+        max_in: Option<f64>,
+    },
+    /// Preview a swap without executing it
+    Quote {
+        /// Token to swap from
+        from: String,
+        /// Token to swap to
+        to: String,
+        /// Amount to swap (the exact output amount with `--exact-out`)
         amount: f64,
+        /// Treat `amount` as the exact amount of `to` to receive
+        #[arg(long)]
+        exact_out: bool,
     },
     /// Show current pool state
     State,
@@ -39,7 +98,135 @@ enum Commands {
         /// Quote token
         quote: String,
     },
+    /// Show capital efficiency of each tick in the server's multi-tick pool
+    Efficiency,
+    /// Manage the ticks of the multi-tick pool
+    Tick {
+        #[command(subcommand)]
+        command: TickCommand,
+    },
+    /// Route a trade across the ticks of the multi-tick pool
+    Route {
+        /// Token to swap from
+        from: String,
+        /// Token to swap to
+        to: String,
+        /// Amount to swap (the exact output amount with `--exact-out`)
+        amount: f64,
+        /// Treat `amount` as the exact amount of `to` to receive
+        #[arg(long)]
+        exact_out: bool,
+        /// Maximum input to pay for an exact-output trade
+        #[arg(long, requires = "exact_out")]
+        max_in: Option<f64>,
+    },
+    /// Route a trade through several pools, hopping over intermediate tokens
+    MultiRoute {
+        /// Token to swap from
+        from: String,
+        /// Token to swap to
+        to: String,
+        /// Amount to swap
+        amount: f64,
+        /// Pools to route through (default: every pool under pools/ plus the
+        /// default state file, named "default")
+        #[arg(long, value_delimiter = ',', value_parser = parse_route_pool)]
+        pools: Vec<String>,
+        /// Longest path to consider, at most 4
+        #[arg(long, default_value_t = router::DEFAULT_MAX_HOPS)]
+        max_hops: usize,
+        /// Split across up to this many paths that share no pool
+        #[arg(long, default_value = "1")]
+        max_paths: usize,
+        /// Execute the trades instead of only quoting them
+        #[arg(long)]
+        execute: bool,
+    },
+    /// Find the trades that bring the multi-tick pool's prices to external ones
+    Arb {
+        /// Reference price of every token, in pool order and a common numeraire
+        #[arg(long, value_delimiter = ',', required = true)]
+        prices: Vec<f64>,
+        /// Execute the trades instead of only planning them
+        #[arg(long)]
+        execute: bool,
+    },
+    /// Run agents against a copy of the multi-tick pool and print the time series
+    Simulate {
+        /// JSON simulation config (steps, seed, volatility, agents); a noise
+        /// trader, an arbitrageur and an LP when absent
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Number of steps, overriding the config
+        #[arg(long)]
+        steps: Option<usize>,
+        /// RNG seed, overriding the config
+        #[arg(long)]
+        seed: Option<u64>,
+        #[arg(long, value_enum, default_value = "csv")]
+        format: OutputFormat,
+        /// Write the time series to this file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Replay a CSV of historical prices and volumes through a copy of the
+    /// multi-tick pool and report how its liquidity fared
+    Backtest {
+        /// CSV with a timestamp column, a price column per token and optional
+        /// `<TOKEN>_volume` columns
+        csv: PathBuf,
+        /// Multiply every volume by this factor
+        #[arg(long, default_value = "1.0")]
+        volume_scale: f64,
+        /// Print the full report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Add or remove an LP's liquidity in a tick of the multi-tick pool
+    Liquidity {
+        #[command(subcommand)]
+        command: LiquidityCommand,
+    },
+    /// Inspect LP positions in the multi-tick pool
+    Lp {
+        #[command(subcommand)]
+        command: LpCommand,
+    },
+    /// Show aggregated prices for every token pair of the multi-tick pool
+    Prices,
+    /// Rebuild the multi-tick pool from its journal and check it against the snapshot
+    Replay {
+        /// Journal to replay instead of the one next to the state file
+        #[arg(long)]
+        journal: Option<PathBuf>,
+        /// Overwrite the snapshot with the rebuilt pool instead of comparing
+        #[arg(long)]
+        write: bool,
+    },
+    /// Undo the last operation on the multi-tick pool
+    Undo,
+    /// Re-apply the last undone operation
+    Redo,
+    /// List the journaled operations, undo/redo depth and checkpoints
+    History,
+    /// Name the current multi-tick pool state so it can be restored later
+    Checkpoint {
+        /// Checkpoint name; an existing checkpoint of that name is replaced
+        name: String,
+    },
+    /// Return the multi-tick pool to a named checkpoint
+    Restore {
+        /// Checkpoint name as shown by `history`
+        name: String,
+    },
+    /// Interactive terminal dashboard over the multi-tick pool
+    #[cfg(feature = "tui")]
+    Tui,
+    /// Print the server's OpenAPI document, e.g. to generate API clients
+    #[cfg(feature = "server")]
+    Openapi,
     /// Run web server
+    #[cfg(feature = "server")]
     Server {
         /// Port to run on
         #[arg(short, long, default_value = "8080")]
@@ -54,15 +241,275 @@ enum Commands {
         #[arg(short, long, default_value = "1000,1000,1000")]
         reserves: String,
         /// Initial plane constant for default tick
-        #[arg(long, default_value = "600")]
+        #[arg(long, default_value = "1800")]
         plane: f64,
+        /// Derive the default tick's plane from the lowest price it should
+        /// cover (e.g. 0.99) instead of `--plane`
+        #[arg(long, conflicts_with = "plane")]
+        depeg_price: Option<f64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TickCommand {
+    /// Add a tick, creating the pool if its state file does not exist yet
+    Add {
+        /// Plane constant bounding the tick
+        #[arg(long, required_unless_present = "depeg_price")]
+        plane: Option<f64>,
+        /// Derive the plane from the lowest price the tick should cover
+        #[arg(long, conflicts_with = "plane")]
+        depeg_price: Option<f64>,
+        /// Initial reserves (format: "1000,1000,1000")
+        #[arg(long, value_delimiter = ',', required = true)]
+        reserves: Vec<f64>,
+        /// Swap fee in basis points
+        #[arg(long, default_value = "0")]
+        fee_bps: u32,
+        /// Token names, only used when creating a new pool
+        #[arg(long, value_delimiter = ',', default_value = "USDC,USDT,DAI")]
+        tokens: Vec<String>,
+    },
+    /// List ticks with their reserves and state
+    List,
+    /// Remove a tick and its reserves from the pool
+    Remove {
+        /// Index of the tick as shown by `tick list`
+        index: usize,
+    },
+}
+
+#[derive(Subcommand)]
+enum LiquidityCommand {
+    /// Deposit token amounts into a tick on behalf of an LP
+    Add {
+        /// Tick index
+        tick: usize,
+        /// LP identifier
+        lp_id: String,
+        /// Amounts per token (format: "10,10,10")
+        #[arg(value_delimiter = ',')]
+        amounts: Vec<f64>,
+    },
+    /// Withdraw a fraction (0..=1) of an LP's position in a tick
+    Remove {
+        /// Tick index
+        tick: usize,
+        /// LP identifier
+        lp_id: String,
+        /// Fraction of the position to withdraw
+        fraction: f64,
     },
 }
 
+#[derive(Subcommand)]
+enum LpCommand {
+    /// Show an LP's positions: amounts, value, value if held, impermanent
+    /// loss and fee income per tick
+    Show {
+        /// LP identifier
+        lp_id: String,
+        /// Price per token to value positions at (format: "1,0.99,1"); the
+        /// pool's own prices when absent
+        #[arg(long, value_delimiter = ',')]
+        prices: Option<Vec<f64>>,
+    },
+}
+
+fn tick_command(command: &TickCommand, path: &Path) {
+    let mut amm = or_fail(MultiTickAMM::load_state(path, Vec::new()));
+    match command {
+        TickCommand::Add { plane, depeg_price, reserves, fee_bps, tokens } => {
+            if amm.token_names.is_empty() {
+                amm = MultiTickAMM::new(tokens.clone()).with_state_path(path);
+            }
+            let op = Operation::AddTick {
+                plane: *plane,
+                depeg_price: *depeg_price,
+                reserves: reserves.clone(),
+                fee_bps: *fee_bps,
+            };
+            let output = or_fail(amm.commit(op));
+            println!(
+                "Added tick {} with plane constant {}",
+                output["tick_index"],
+                output["plane_constant"]
+            );
+        }
+        TickCommand::List => {
+            if amm.ticks.is_empty() {
+                println!("No ticks in {}", path.display());
+                return;
+            }
+            println!("Tokens: {:?}", amm.token_names);
+            for (i, tick) in amm.ticks.iter().enumerate() {
+                let state = if tick.is_interior() {
+                    "interior"
+                } else if tick.is_boundary() {
+                    "boundary"
+                } else {
+                    "exterior"
+                };
+                println!(
+                    "Tick {}: plane={:.4} radius={:.4} state={} fee={}bps liquidity={:.4}",
+                    i,
+                    tick.plane_constant,
+                    tick.sphere_amm.radius,
+                    state,
+                    tick.fee_bps,
+                    tick.liquidity()
+                );
+                println!("  reserves: {:?}", tick.sphere_amm.reserves);
+                for (lp, shares) in &tick.lp_shares {
+                    println!("  LP {}: {} shares", lp, shares);
+                }
+            }
+            println!("Global reserves: {:?}", amm.global_reserves);
+        }
+        TickCommand::Remove { index } => {
+            let output = or_fail(amm.commit(Operation::RemoveTick { tick_index: *index }));
+            println!("Removed tick {} with reserves {}", index, output["reserves"]);
+        }
+    }
+}
+
+fn liquidity_command(command: &LiquidityCommand, path: &Path) {
+    let mut amm = or_fail(MultiTickAMM::load_state(path, Vec::new()));
+    match command {
+        LiquidityCommand::Add { tick, lp_id, amounts } => {
+            let op = Operation::AddLiquidity {
+                tick_index: *tick,
+                lp_id: lp_id.clone(),
+                amounts: amounts.clone(),
+            };
+            let output = or_fail(amm.commit(op));
+            println!("Added liquidity for LP {} to tick {}", lp_id, tick);
+            let refunded: Vec<String> = amm.token_names
+                .iter()
+                .zip(output["refunded"].as_array().into_iter().flatten())
+                .filter(|(_, amount)| amount.as_f64().is_some_and(|a| a > 0.0))
+                .map(|(name, amount)| format!("{} {}", amount, name))
+                .collect();
+            if !refunded.is_empty() {
+                println!("Refunded {} not matching the tick's proportions", refunded.join(", "));
+            }
+        }
+        LiquidityCommand::Remove { tick, lp_id, fraction } => {
+            let op = Operation::RemoveLiquidity {
+                tick_index: *tick,
+                lp_id: lp_id.clone(),
+                percentage: *fraction,
+            };
+            let output = or_fail(amm.commit(op));
+            let parts: Vec<String> = amm.token_names
+                .iter()
+                .zip(output["withdrawn"].as_array().into_iter().flatten())
+                .map(|(name, amount)| format!("{} {}", amount, name))
+                .collect();
+            println!("Withdrew {} for LP {} from tick {}", parts.join(", "), lp_id, tick);
+        }
+    }
+}
+
+fn lp_command(command: &LpCommand, path: &Path) {
+    let amm = or_fail(MultiTickAMM::load_state(path, Vec::new()));
+    match command {
+        LpCommand::Show { lp_id, prices } => {
+            let report = or_fail(amm.lp_report(lp_id, prices.as_deref()));
+            println!("LP {} at prices {:?}", report.lp_id, report.prices);
+            for p in &report.positions {
+                println!(
+                    "Tick {}: {} shares ({:.4}% of the tick)",
+                    p.tick_index,
+                    p.shares,
+                    p.fraction * 100.0
+                );
+                println!("  amounts:   {:?}", p.amounts);
+                println!("  fees:      {:?}", p.fees);
+                println!("  deposited: {:?}", p.deposited);
+                println!("  fee buy-in: {:?}", p.fee_buy_in);
+                println!(
+                    "  value {}, if held {}, impermanent loss {:.4}%, fees {} (income {}), PnL {}",
+                    p.value,
+                    p.value_if_held,
+                    p.impermanent_loss * 100.0,
+                    p.fee_value,
+                    p.fee_income,
+                    p.pnl
+                );
+            }
+            println!(
+                "Total: value {}, if held {}, impermanent loss {:.4}%, fees {} (income {}), PnL {}",
+                report.value,
+                report.value_if_held,
+                report.impermanent_loss * 100.0,
+                report.fee_value,
+                report.fee_income,
+                report.pnl
+            );
+        }
+    }
+}
+
+/// Pools for `multi-route`: the named ones, or every pool under `pools/` plus
+/// the default state file if it exists.
+/// Pools named explicitly must exist; routing through an empty stand-in
+/// would quote hops that can never execute.
+fn load_route_pools(ids: &[String], default: &Path) -> Vec<(String, MultiTickAMM)> {
+    let path_of = |id: &str| {
+        if id == persist::DEFAULT_POOL {
+            default.to_path_buf()
+        } else {
+            Path::new(persist::POOLS_DIR).join(id).join(DEFAULT_STATE_FILE)
+        }
+    };
+    let load = |id: &str| {
+        (id.to_string(), or_fail(MultiTickAMM::load_state(path_of(id), Vec::new())))
+    };
+    if !ids.is_empty() {
+        return ids
+            .iter()
+            .map(|id| {
+                if !path_of(id).exists() {
+                    fail(OrbitalError::UnknownPool(id.clone()));
+                }
+                load(id)
+            })
+            .collect();
+    }
+    let mut pools = Vec::new();
+    if default.exists() {
+        pools.push(load(persist::DEFAULT_POOL));
+    }
+    let mut ids: Vec<String> = std::fs::read_dir(persist::POOLS_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|id| persist::validate_pool_name(id).is_ok())
+        .filter(|id| Path::new(persist::POOLS_DIR).join(id).join(DEFAULT_STATE_FILE).exists())
+        .collect();
+    ids.sort();
+    pools.extend(ids.iter().map(|id| load(id)));
+    pools
+}
+
+/// Report an AMM error and exit with its dedicated exit code.
+fn fail(e: OrbitalError) -> ! {
+    eprintln!("Error [{}]: {}", e.code(), e);
+    std::process::exit(e.exit_code())
+}
+
+fn or_fail<T>(result: Result<T, OrbitalError>) -> T {
+    result.unwrap_or_else(|e| fail(e))
+}
+
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
 
+    let pool_file = cli.state_path(DEFAULT_POOL_FILE);
+    let state_file = cli.state_path(DEFAULT_STATE_FILE);
     match &cli.command {
         Commands::Init { reserves } => {
             let mut token_names = Vec::new();
@@ -75,32 +522,335 @@ async fn main() {
                     amounts.push(amount);
                 }
             }
+            or_fail(SphereAMM::checked_radius(&amounts));
             let pool = SphereAMM::new(token_names, amounts);
-            pool.save_state();
+            or_fail(pool.save_state(&pool_file));
             println!("Pool initialised with {} tokens", pool.token_names.len());
         }
-        Commands::Swap { from, to, amount } => {
-            let mut pool = SphereAMM::load_state();
+        Commands::Swap { from, to, amount, exact_out: true, max_in } => {
+            let mut pool = or_fail(SphereAMM::load_state(&pool_file));
+            let max_in = max_in.unwrap_or(f64::INFINITY);
+            match pool.swap_exact_out(from, to, *amount, max_in) {
+                Ok(input_amount) => {
+                    println!("Swapped {} {} for {} {}", input_amount, from, amount, to);
+                    or_fail(pool.save_state(&pool_file));
+                }
+                Err(e) => fail(e),
+            }
+        }
+        Commands::Swap { from, to, amount, .. } => {
+            let mut pool = or_fail(SphereAMM::load_state(&pool_file));
             match pool.swap(from, to, *amount) {
                 Ok(output_amount) => {
                     println!("Swapped {} {} for {} {}", amount, from, output_amount, to);
-                    pool.save_state();
+                    or_fail(pool.save_state(&pool_file));
+                }
+                Err(e) => fail(e),
+            }
+        }
+        Commands::Quote { from, to, amount, exact_out } => {
+            let pool = or_fail(SphereAMM::load_state(&pool_file));
+            let quote = if *exact_out {
+                pool.quote_exact_out(from, to, *amount)
+            } else {
+                pool.quote(from, to, *amount)
+            };
+            match quote {
+                Ok(q) => {
+                    println!("Quote {} {} -> {} {}", q.amount_in, from, q.amount_out, to);
+                    println!("  effective price: {}", q.effective_price);
+                    println!("  spot price:      {}", q.spot_price);
+                    println!("  price impact:    {:.4}%", q.price_impact * 100.0);
+                    for (name, reserve) in pool.token_names.iter().zip(&q.reserves_after) {
+                        println!("  {} after: {}", name, reserve);
+                    }
                 }
-                Err(e) => println!("Error: {}", e),
+                Err(e) => fail(e),
             }
         }
         Commands::State => {
-            let pool = SphereAMM::load_state();
+            let pool = or_fail(SphereAMM::load_state(&pool_file));
             pool.print_state();
         }
         Commands::Price { base, quote } => {
-            let pool = SphereAMM::load_state();
+            let pool = or_fail(SphereAMM::load_state(&pool_file));
             match pool.get_spot_price(base, quote) {
                 Ok(price) => println!("Spot price of {} in {}: {}", quote, base, price),
-                Err(e) => println!("Error: {}", e),
+                Err(e) => fail(e),
+            }
+        }
+        Commands::Efficiency => {
+            let amm = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+            if amm.ticks.is_empty() {
+                println!("No ticks in {}", state_file.display());
+                return;
+            }
+            let report = amm.efficiency_report();
+            for (i, (tick, eff)) in amm.ticks.iter().zip(&report.ticks).enumerate() {
+                println!(
+                    "Tick {}: plane={:.4} min_reserve={:.4} max_reserve={:.4} efficiency={:.2}x",
+                    i,
+                    tick.plane_constant,
+                    eff.min_reserve,
+                    eff.max_reserve,
+                    eff.capital_efficiency
+                );
+                println!("  virtual reserves: {:?}", eff.virtual_reserves);
+            }
+            println!("Pool efficiency: {:.2}x", report.capital_efficiency);
+        }
+        Commands::Tick { command } => tick_command(command, &state_file),
+        Commands::Route { from, to, amount, exact_out, max_in } => {
+            let mut amm = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+            let op = Operation::Trade {
+                from: from.clone(),
+                to: to.clone(),
+                amount: *amount,
+                exact_out: *exact_out,
+                max_amount_in: *max_in,
+            };
+            let output = or_fail(amm.commit(op));
+            println!(
+                "Swapped {} {} for {} {}",
+                output["amount_in"],
+                from,
+                output["amount_out"],
+                to
+            );
+        }
+        Commands::MultiRoute { from, to, amount, pools, max_hops, max_paths, execute } => {
+            let mut pools = load_route_pools(pools, &state_file);
+            let quote = {
+                let mut router = Router::new(pools.iter().map(|(id, amm)| (id.as_str(), amm)));
+                router.max_hops = *max_hops;
+                let quote = or_fail(router.quote(from, to, *amount, *max_paths));
+                if *execute {
+                    or_fail(router.check_execution(&quote));
+                }
+                quote
+            };
+            for path in &quote.paths {
+                let route: String = path.hops
+                    .iter()
+                    .map(|hop| format!(" -[{}]-> {}", hop.pool, hop.to))
+                    .collect();
+                println!("{} {} via {}{}", path.amount_in, from, from, route);
+                for hop in &path.hops {
+                    println!(
+                        "  {}: {} {} -> {} {} (impact {:.4}%, {} fills)",
+                        hop.pool,
+                        hop.amount_in,
+                        hop.from,
+                        hop.amount_out,
+                        hop.to,
+                        hop.price_impact * 100.0,
+                        hop.fills.len()
+                    );
+                }
+            }
+            println!("Quote {} {} -> {} {}", quote.amount_in, from, quote.amount_out, to);
+            println!("  effective price: {}", quote.effective_price);
+            println!("  spot price:      {}", quote.spot_price);
+            println!("  price impact:    {:.4}%", quote.price_impact * 100.0);
+            if *execute {
+                let mut targets: Vec<(&str, &mut MultiTickAMM)> = pools
+                    .iter_mut()
+                    .map(|(id, amm)| (id.as_str(), amm))
+                    .collect();
+                or_fail(quote.execute(&mut targets, |_, amm, op| amm.commit(op)));
+                println!("Executed {} trades", quote.operations().len());
             }
         }
-        Commands::Server { port, addr, tokens, reserves, plane } => {
+        Commands::Arb { prices, execute } => {
+            let mut amm = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+            let plan = or_fail(amm.arbitrage_plan(prices));
+            if plan.trades.is_empty() {
+                println!("Pool already at the reference prices");
+            }
+            for trade in &plan.trades {
+                println!(
+                    "{} {} -> {} {} (profit {})",
+                    trade.amount_in,
+                    trade.from,
+                    trade.amount_out,
+                    trade.to,
+                    trade.profit
+                );
+            }
+            println!("Profit: {}", plan.profit);
+            for (i, token) in amm.token_names.iter().enumerate() {
+                println!(
+                    "  {}: target {}, pool {} -> {}",
+                    token,
+                    plan.target_prices[i],
+                    plan.prices_before[i],
+                    plan.prices_after[i]
+                );
+            }
+            if *execute {
+                or_fail(amm.commit(plan.operation()));
+                println!("Executed {} trades", plan.trades.len());
+            }
+        }
+        Commands::Simulate { config, steps, seed, format, output } => {
+            let amm = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+            let mut config = match config {
+                Some(path) => {
+                    let io = |e: &dyn std::fmt::Display| {
+                        OrbitalError::Io(format!("{}: {}", path.display(), e))
+                    };
+                    let text = or_fail(std::fs::read_to_string(path).map_err(|e| io(&e)));
+                    or_fail(serde_json::from_str::<SimConfig>(&text).map_err(|e| io(&e)))
+                }
+                None => SimConfig::default(),
+            };
+            config.steps = steps.unwrap_or(config.steps);
+            config.seed = seed.unwrap_or(config.seed);
+            let sim = or_fail(simulate::simulate(&amm, &config));
+            let text = match format {
+                OutputFormat::Csv => sim.to_csv(),
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&sim);
+                    or_fail(json.map_err(|e| OrbitalError::Io(e.to_string()))) + "\n"
+                }
+            };
+            match output {
+                Some(path) => {
+                    if let Err(e) = std::fs::write(path, text) {
+                        fail(OrbitalError::Io(format!("{}: {}", path.display(), e)));
+                    }
+                    eprintln!("Wrote {} steps to {}", config.steps, path.display());
+                }
+                None => print!("{}", text),
+            }
+        }
+        Commands::Backtest { csv, volume_scale, json } => {
+            let amm = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+            let text = or_fail(
+                std::fs::read_to_string(csv).map_err(|e| {
+                    OrbitalError::Io(format!("{}: {}", csv.display(), e))
+                })
+            );
+            let rows = or_fail(backtest::parse_csv(&text, &amm.token_names));
+            let report = or_fail(backtest::backtest(&amm, &rows, *volume_scale));
+            if *json {
+                let json = serde_json::to_string_pretty(&report);
+                println!("{}", or_fail(json.map_err(|e| OrbitalError::Io(e.to_string()))));
+                return;
+            }
+            println!("Backtest {} -> {} ({} rows)", report.start, report.end, report.rows);
+            println!(
+                "  trades: {} ({} failed), volume {}, arbitrage profit {}",
+                report.trades,
+                report.failed,
+                report.volume,
+                report.arbitrage_profit
+            );
+            println!("  fees: {:?} (worth {})", report.fees, report.fees_value);
+            println!(
+                "  LP value {} -> {} (hold {}), PnL {}, impermanent loss {:.4}%",
+                report.initial_value,
+                report.final_value,
+                report.hold_value,
+                report.pnl,
+                report.impermanent_loss * 100.0
+            );
+            for tick in &report.ticks {
+                println!(
+                    "  tick {}: PnL {}, fees {}, impermanent loss {:.4}%, \
+                     on boundary {:.2}% of the time ({} rows)",
+                    tick.index,
+                    tick.pnl,
+                    tick.fees_value,
+                    tick.impermanent_loss * 100.0,
+                    tick.boundary_share * 100.0,
+                    tick.boundary_rows
+                );
+            }
+        }
+        Commands::Liquidity { command } => liquidity_command(command, &state_file),
+        Commands::Lp { command } => lp_command(command, &state_file),
+        Commands::Replay { journal: journal_file, write } => {
+            let journal_file = journal_file
+                .clone()
+                .unwrap_or_else(|| journal::journal_path(&state_file));
+            let events = or_fail(journal::read_journal(&journal_file));
+            let rebuilt = or_fail(journal::replay(&events)).with_state_path(&state_file);
+            println!("Replayed {} events from {}", events.len(), journal_file.display());
+            if *write {
+                or_fail(rebuilt.save_state());
+                println!("Wrote rebuilt pool to {}", state_file.display());
+            } else {
+                let snapshot = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+                or_fail(journal::verify_same(&rebuilt, &snapshot));
+                println!("Snapshot {} matches the journal", state_file.display());
+            }
+        }
+        Commands::Undo => {
+            let mut amm = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+            or_fail(amm.undo());
+            println!("Undid last operation; global reserves: {:?}", amm.global_reserves);
+        }
+        Commands::Redo => {
+            let mut amm = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+            or_fail(amm.redo());
+            println!("Redid last undone operation; global reserves: {:?}", amm.global_reserves);
+        }
+        Commands::History => {
+            let amm = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+            let history = or_fail(amm.history());
+            if history.entries.is_empty() {
+                println!("No history for {}", state_file.display());
+                return;
+            }
+            for entry in &history.entries {
+                println!("{:>4} {:<16} {}", entry.index, entry.op, entry.params);
+            }
+            println!(
+                "Undo depth: {}, redo depth: {}",
+                history.undo_depth(),
+                history.redo_depth()
+            );
+            println!("Checkpoints: {:?}", history.checkpoint_names());
+        }
+        Commands::Checkpoint { name } => {
+            let mut amm = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+            or_fail(amm.checkpoint(name));
+            println!("Saved checkpoint '{}'", name);
+        }
+        Commands::Restore { name } => {
+            let mut amm = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+            or_fail(amm.restore(name));
+            println!("Restored checkpoint '{}'; global reserves: {:?}", name, amm.global_reserves);
+        }
+        Commands::Prices => {
+            let amm = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+            for base in &amm.token_names {
+                for quote in amm.token_names.iter().filter(|t| *t != base) {
+                    match amm.get_aggregated_price(base, quote) {
+                        Ok(price) => println!("{}/{}: {}", base, quote, price),
+                        Err(e) => println!("{}/{}: n/a ({})", base, quote, e),
+                    }
+                }
+            }
+        }
+        #[cfg(feature = "tui")]
+        Commands::Tui => {
+            let amm = or_fail(MultiTickAMM::load_state(&state_file, Vec::new()));
+            if let Err(e) = orbital::ui::run_ui(amm) {
+                eprintln!("TUI error: {}", e);
+            }
+        }
+        #[cfg(feature = "server")]
+        Commands::Openapi => {
+            use utoipa::OpenApi;
+            match server::ApiDoc::openapi().to_pretty_json() {
+                Ok(json) => println!("{}", json),
+                Err(e) => fail(OrbitalError::Io(e.to_string())),
+            }
+        }
+        #[cfg(feature = "server")]
+        Commands::Server { port, addr, tokens, reserves, plane, depeg_price } => {
             println!("Starting Orbital server on {}:{}", addr, port);
 
             // Parse tokens
@@ -117,9 +867,23 @@ async fn main() {
 
             println!("Tokens: {:?}", token_names);
             println!("Initial reserves: {:?}", initial_reserves);
-            println!("Initial plane constant: {}", plane);
+            println!("State file: {}", state_file.display());
+            match depeg_price {
+                Some(p) => println!("Initial depeg price: {}", p),
+                None => println!("Initial plane constant: {}", plane),
+            }
 
-            if let Err(e) = server::run(addr, *port, token_names, initial_reserves, *plane).await {
+            if
+                let Err(e) = server::run(
+                    addr,
+                    *port,
+                    token_names,
+                    initial_reserves,
+                    *plane,
+                    *depeg_price,
+                    state_file
+                ).await
+            {
                 eprintln!("Server error: {}", e);
             }
         }
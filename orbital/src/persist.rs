@@ -0,0 +1,159 @@
+//! Versioned, crash-safe JSON state files.
+//!
+//! Every state file carries a top-level `schema_version`. Files written before
+//! the field existed are version 0 and are migrated on load; files from a newer
+//! release are rejected rather than misread.
+
+use std::fs::{ self, File };
+use std::io::Write;
+use std::path::{ Path, PathBuf };
+
+use serde::{ de::DeserializeOwned, Serialize };
+use serde_json::Value;
+
+use crate::error::OrbitalError;
+
+/// Schema version written by this release.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Directory holding named pools, one subdirectory per pool.
+pub const POOLS_DIR: &str = "pools";
+
+/// Id of the pool kept in the server's own state file rather than under
+/// `pools/`; reserved for it.
+pub const DEFAULT_POOL: &str = "default";
+
+/// Check that `name` is usable as a pool id and directory name.
+pub fn validate_pool_name(name: &str) -> Result<(), OrbitalError> {
+    let valid = name != DEFAULT_POOL &&
+        !name.is_empty() &&
+        name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(OrbitalError::InvalidPoolName(name.to_string()))
+    }
+}
+
+/// Upgrades a parsed state document from `from` to `from + 1` in place.
+pub type Migration = fn(&mut Value, u32) -> Result<(), String>;
+
+fn io_error(path: &Path, e: std::io::Error) -> OrbitalError {
+    OrbitalError::Io(format!("{}: {}", path.display(), e))
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Write `bytes` to `path` so that readers see either the old or the new
+/// contents, never a partial file: write a sibling temp file, fsync it and
+/// rename it over the target.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), OrbitalError> {
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty());
+    if let Some(dir) = dir {
+        fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+    }
+    let tmp = temp_path(path);
+    let mut file = File::create(&tmp).map_err(|e| io_error(&tmp, e))?;
+    file.write_all(bytes).map_err(|e| io_error(&tmp, e))?;
+    file.sync_all().map_err(|e| io_error(&tmp, e))?;
+    fs::rename(&tmp, path).map_err(|e| io_error(path, e))?;
+    // Persist the rename itself. Not every platform can open a directory,
+    // so this is best effort.
+    if let Ok(d) = File::open(dir.unwrap_or(Path::new("."))) {
+        let _ = d.sync_all();
+    }
+    Ok(())
+}
+
+/// Serialize `state` with the current `schema_version` and write it atomically.
+pub fn save_json<S: Serialize>(state: &S, path: &Path) -> Result<(), OrbitalError> {
+    let mut doc = serde_json::to_value(state).map_err(|e| OrbitalError::Io(e.to_string()))?;
+    if let Value::Object(map) = &mut doc {
+        map.insert("schema_version".into(), SCHEMA_VERSION.into());
+    }
+    let json = serde_json::to_string_pretty(&doc).map_err(|e| OrbitalError::Io(e.to_string()))?;
+    write_atomic(path, json.as_bytes())
+}
+
+/// Read a state file, migrating older schema versions with `migrate`.
+/// Returns `Ok(None)` if the file does not exist; any other problem is an
+/// error so a damaged file is never silently replaced.
+pub fn load_json<D: DeserializeOwned>(
+    path: &Path,
+    migrate: Migration
+) -> Result<Option<D>, OrbitalError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(None);
+        }
+        Err(e) => {
+            return Err(io_error(path, e));
+        }
+    };
+    let corrupt = |reason: String| {
+        OrbitalError::CorruptState(format!("{}: {}", path.display(), reason))
+    };
+    let mut doc: Value = serde_json::from_str(&text).map_err(|e| corrupt(e.to_string()))?;
+    if !doc.is_object() {
+        return Err(corrupt("expected a JSON object".into()));
+    }
+    let version = match doc.get("schema_version") {
+        None => 0,
+        Some(v) =>
+            v
+                .as_u64()
+                .map(|v| v as u32)
+                .ok_or_else(|| corrupt("schema_version is not a number".into()))?,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(OrbitalError::UnsupportedSchema(version));
+    }
+    for from in version..SCHEMA_VERSION {
+        migrate(&mut doc, from).map_err(corrupt)?;
+    }
+    if let Value::Object(map) = &mut doc {
+        map.remove("schema_version");
+    }
+    serde_json::from_value(doc).map(Some).map_err(|e| corrupt(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_migrations(_: &mut Value, from: u32) -> Result<(), String> {
+        Err(format!("no migration from {}", from))
+    }
+
+    #[test]
+    fn test_roundtrip_and_errors() {
+        let dir = crate::testing::temp_dir("persist");
+        let path = dir.join("state.json");
+        let state = serde_json::json!({ "a": 1 });
+
+        save_json(&state, &path).unwrap();
+        assert!(!temp_path(&path).exists());
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains(&format!("\"schema_version\": {}", SCHEMA_VERSION)));
+        let loaded: Option<Value> = load_json(&path, no_migrations).unwrap();
+        assert_eq!(loaded, Some(state));
+
+        let missing: Option<Value> = load_json(&dir.join("none.json"), no_migrations).unwrap();
+        assert!(missing.is_none());
+
+        fs::write(&path, "{ \"a\": ").unwrap();
+        let err = load_json::<Value>(&path, no_migrations).unwrap_err();
+        assert_eq!(err.code(), "corrupt_state");
+
+        fs::write(&path, "{ \"schema_version\": 99 }").unwrap();
+        let err = load_json::<Value>(&path, no_migrations).unwrap_err();
+        assert_eq!(err, OrbitalError::UnsupportedSchema(99));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,445 @@
+//! Multi-hop routing across several pools.
+//!
+//! A path is a chain of trades `A → B` in one pool, `B → C` in another, and so
+//! on, using each pool at most once. The router quotes every path up to
+//! `max_hops` long and picks the one with the highest output; with splitting
+//! enabled it also spreads the input over paths that share no pool, whose
+//! quotes are then independent of each other.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::OrbitalError;
+use crate::journal::Operation;
+use crate::ticks::{ MultiTickAMM, TickFill };
+
+/// Longest path considered unless configured otherwise.
+pub const DEFAULT_MAX_HOPS: usize = 3;
+/// Upper bound on `Router::max_hops`: paths are enumerated exhaustively, so
+/// the work grows exponentially with their length.
+pub const MAX_HOPS: usize = 4;
+/// Number of slices the input is cut into when splitting across paths.
+const SPLIT_STEPS: usize = 20;
+
+/// One trade of a path, inside a single pool.
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct HopQuote {
+    pub pool: String,
+    pub from: String,
+    pub to: String,
+    pub amount_in: f64,
+    pub amount_out: f64,
+    pub price_impact: f64,
+    pub fills: Vec<TickFill>,
+}
+
+/// A path and the share of the input sent along it.
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct PathQuote {
+    pub amount_in: f64,
+    pub amount_out: f64,
+    /// Product of the hops' spot prices, input paid per unit of output.
+    pub spot_price: f64,
+    pub hops: Vec<HopQuote>,
+}
+
+/// Result of a dry-run multi-hop trade.
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct MultiHopQuote {
+    pub amount_in: f64,
+    pub amount_out: f64,
+    /// Input paid per unit of output.
+    pub effective_price: f64,
+    /// Best spot price among the paths used.
+    pub spot_price: f64,
+    /// Relative deviation of `effective_price` from `spot_price`.
+    pub price_impact: f64,
+    pub paths: Vec<PathQuote>,
+}
+
+impl MultiHopQuote {
+    /// The trades that execute this quote, as `(pool, operation)` pairs in
+    /// order. Each hop trades exactly the quoted output of the previous one.
+    pub fn operations(&self) -> Vec<(String, Operation)> {
+        self.paths
+            .iter()
+            .flat_map(|path| &path.hops)
+            .map(|hop| {
+                let op = Operation::Trade {
+                    from: hop.from.clone(),
+                    to: hop.to.clone(),
+                    amount: hop.amount_in,
+                    exact_out: false,
+                    max_amount_in: None,
+                };
+                (hop.pool.clone(), op)
+            })
+            .collect()
+    }
+
+    /// Commit the quote's trades in order with `commit(pool id, pool, op)`.
+    /// If one fails, the hops already committed are undone, newest first and
+    /// through `commit` as well, so the route takes effect in full or not at
+    /// all. A failed undo is returned in place of the original error.
+    pub fn execute(
+        &self,
+        pools: &mut [(&str, &mut MultiTickAMM)],
+        mut commit: impl FnMut(&str, &mut MultiTickAMM, Operation) -> Result<Value, OrbitalError>
+    ) -> Result<(), OrbitalError> {
+        let mut committed: Vec<(usize, MultiTickAMM)> = Vec::new();
+        let mut error = None;
+        for (id, op) in self.operations() {
+            let Some(i) = pools.iter().position(|(name, _)| *name == id) else {
+                error = Some(OrbitalError::UnknownPool(id));
+                break;
+            };
+            let (name, amm) = &mut pools[i];
+            let before = amm.clone();
+            if let Err(e) = commit(name, amm, op) {
+                error = Some(e);
+                break;
+            }
+            committed.push((i, before));
+        }
+        let Some(error) = error else {
+            return Ok(());
+        };
+        for (i, before) in committed.into_iter().rev() {
+            let (name, amm) = &mut pools[i];
+            commit(name, amm, Operation::Undo { state: Box::new(before) })?;
+        }
+        Err(error)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Hop {
+    pool: usize,
+    from: String,
+    to: String,
+}
+
+/// Routes trades over a set of named pools.
+pub struct Router<'a> {
+    pools: Vec<(&'a str, &'a MultiTickAMM)>,
+    pub max_hops: usize,
+}
+
+impl<'a> Router<'a> {
+    pub fn new(pools: impl IntoIterator<Item = (&'a str, &'a MultiTickAMM)>) -> Self {
+        Router { pools: pools.into_iter().collect(), max_hops: DEFAULT_MAX_HOPS }
+    }
+
+    /// Every path from `from` to `to` of at most `max_hops` hops that visits
+    /// no token and no pool twice.
+    fn paths(&self, from: &str, to: &str) -> Vec<Vec<Hop>> {
+        let mut paths = Vec::new();
+        let mut stack = Vec::new();
+        self.extend(from, to, &mut stack, &mut paths);
+        paths
+    }
+
+    fn extend(&self, at: &str, to: &str, stack: &mut Vec<Hop>, paths: &mut Vec<Vec<Hop>>) {
+        if stack.len() == self.max_hops {
+            return;
+        }
+        for (pool, (_, amm)) in self.pools.iter().enumerate() {
+            if stack.iter().any(|h| h.pool == pool) || !amm.token_names.iter().any(|t| t == at) {
+                continue;
+            }
+            for next in &amm.token_names {
+                let visited = next == at || stack.iter().any(|h| &h.from == next);
+                if visited {
+                    continue;
+                }
+                stack.push(Hop { pool, from: at.to_string(), to: next.clone() });
+                if next == to {
+                    paths.push(stack.clone());
+                } else {
+                    self.extend(next, to, stack, paths);
+                }
+                stack.pop();
+            }
+        }
+    }
+
+    fn quote_path(&self, path: &[Hop], amount: f64) -> Result<PathQuote, OrbitalError> {
+        let mut hops = Vec::with_capacity(path.len());
+        let mut spot_price = 1.0;
+        let mut amount_out = amount;
+        for hop in path {
+            let (name, amm) = self.pools[hop.pool];
+            let amount_in = amount_out;
+            let quote = amm.quote_trade(&hop.from, &hop.to, amount_in)?;
+            spot_price *= quote.spot_price;
+            amount_out = quote.amount_out;
+            hops.push(HopQuote {
+                pool: name.to_string(),
+                from: hop.from.clone(),
+                to: hop.to.clone(),
+                amount_in,
+                amount_out: quote.amount_out,
+                price_impact: quote.price_impact,
+                fills: quote.fills,
+            });
+        }
+        Ok(PathQuote { amount_in: amount, amount_out, spot_price, hops })
+    }
+
+    /// Quote trading `amount` of `from` for `to` along the best path, or
+    /// across up to `max_paths` pool-disjoint paths if that yields more.
+    pub fn quote(
+        &self,
+        from: &str,
+        to: &str,
+        amount: f64,
+        max_paths: usize
+    ) -> Result<MultiHopQuote, OrbitalError> {
+        if from == to {
+            return Err(OrbitalError::SameToken);
+        }
+        if amount <= 0.0 {
+            return Err(OrbitalError::NonPositiveAmount);
+        }
+        if self.max_hops > MAX_HOPS {
+            return Err(OrbitalError::TooManyHops(self.max_hops));
+        }
+        let paths = self.paths(from, to);
+        if paths.is_empty() {
+            return Err(OrbitalError::NoRoute { from: from.to_string(), to: to.to_string() });
+        }
+
+        // Quote every path with the whole amount. Paths that cannot take it
+        // all may still take a share when splitting.
+        let mut error = None;
+        let mut ranked: Vec<(Vec<Hop>, Option<PathQuote>)> = paths
+            .into_iter()
+            .map(|path| {
+                let quote = self.quote_path(&path, amount).map_err(|e| error.get_or_insert(e)).ok();
+                (path, quote)
+            })
+            .collect();
+        let output = |quote: &Option<PathQuote>| {
+            quote.as_ref().map_or(f64::NEG_INFINITY, |q| q.amount_out)
+        };
+        ranked.sort_by(|a, b| output(&b.1).total_cmp(&output(&a.1)));
+        let best = ranked[0].1.clone();
+
+        let mut disjoint: Vec<&[Hop]> = Vec::new();
+        for (path, _) in &ranked {
+            if disjoint.len() == max_paths.max(1) {
+                break;
+            }
+            let shares_pool = disjoint
+                .iter()
+                .any(|other| other.iter().any(|a| path.iter().any(|b| a.pool == b.pool)));
+            if !shares_pool {
+                disjoint.push(path);
+            }
+        }
+        let split = if disjoint.len() > 1 { self.split(&disjoint, amount) } else { None };
+        let chosen = match (best, split) {
+            (Some(best), Some(split)) => {
+                let total: f64 = split
+                    .iter()
+                    .map(|q| q.amount_out)
+                    .sum();
+                if total > best.amount_out { split } else { vec![best] }
+            }
+            (Some(best), None) => vec![best],
+            (None, Some(split)) => split,
+            (None, None) => {
+                return Err(error.unwrap_or(OrbitalError::InsufficientLiquidity));
+            }
+        };
+        Ok(aggregate(amount, chosen))
+    }
+
+    /// Apply `quote`'s trades in order to copies of the pools. Callers run
+    /// this before committing any hop so a route either executes in full or
+    /// not at all.
+    pub fn check_execution(&self, quote: &MultiHopQuote) -> Result<(), OrbitalError> {
+        let mut copies: HashMap<&str, MultiTickAMM> = HashMap::new();
+        for (id, op) in quote.operations() {
+            let (name, amm) = self.pools
+                .iter()
+                .find(|(name, _)| *name == id)
+                .ok_or(OrbitalError::UnknownPool(id))?;
+            let copy = copies.entry(name).or_insert_with(|| (*amm).clone());
+            op.apply(copy)?;
+        }
+        Ok(())
+    }
+
+    /// Spread `amount` over independent `paths` one slice at a time, each
+    /// slice going to the path with the best marginal output.
+    fn split(&self, paths: &[&[Hop]], amount: f64) -> Option<Vec<PathQuote>> {
+        let step = amount / (SPLIT_STEPS as f64);
+        let mut allocated = vec![0.0; paths.len()];
+        let mut outputs = vec![0.0; paths.len()];
+        for _ in 0..SPLIT_STEPS {
+            let mut best: Option<(usize, f64, f64)> = None;
+            for (i, path) in paths.iter().enumerate() {
+                if let Ok(quote) = self.quote_path(path, allocated[i] + step) {
+                    let gain = quote.amount_out - outputs[i];
+                    if best.is_none_or(|(_, g, _)| gain > g) {
+                        best = Some((i, gain, quote.amount_out));
+                    }
+                }
+            }
+            let (i, _, output) = best?;
+            allocated[i] += step;
+            outputs[i] = output;
+        }
+        paths
+            .iter()
+            .zip(&allocated)
+            .filter(|(_, &a)| a > 0.0)
+            .map(|(path, &a)| self.quote_path(path, a).ok())
+            .collect()
+    }
+}
+
+fn aggregate(amount_in: f64, paths: Vec<PathQuote>) -> MultiHopQuote {
+    let amount_out: f64 = paths
+        .iter()
+        .map(|p| p.amount_out)
+        .sum();
+    let spot_price = paths
+        .iter()
+        .map(|p| p.spot_price)
+        .fold(f64::INFINITY, f64::min);
+    let effective_price = amount_in / amount_out;
+    MultiHopQuote {
+        amount_in,
+        amount_out,
+        effective_price,
+        spot_price,
+        price_impact: effective_price / spot_price - 1.0,
+        paths,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(names: &[&str], reserve: f64) -> MultiTickAMM {
+        let names: Vec<String> = names
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        let mut amm = MultiTickAMM::new(names);
+        let n = amm.token_names.len();
+        amm.add_tick_from_depeg(vec![reserve; n], 0.9, 0).unwrap();
+        amm
+    }
+
+    #[test]
+    fn test_routes_through_intermediate_token() {
+        let ab = pool(&["A", "B"], 1000.0);
+        let bc = pool(&["B", "C"], 1000.0);
+        let router = Router::new([("ab", &ab), ("bc", &bc)]);
+
+        let quote = router.quote("A", "C", 10.0, 1).unwrap();
+        assert_eq!(quote.paths.len(), 1);
+        let hops = &quote.paths[0].hops;
+        assert_eq!((hops[0].pool.as_str(), hops[1].pool.as_str()), ("ab", "bc"));
+        assert_eq!(hops[1].amount_in, hops[0].amount_out);
+        assert!(quote.amount_out > 0.0 && quote.amount_out < 10.0);
+        assert!(quote.price_impact > 0.0);
+
+        let ops = quote.operations();
+        assert_eq!(ops.len(), 2);
+        assert_eq!(ops[1].0, "bc");
+
+        let err = router.quote("A", "D", 10.0, 1).err().unwrap();
+        assert_eq!(err.code(), "no_route");
+        let mut short = Router::new([("ab", &ab), ("bc", &bc)]);
+        short.max_hops = 1;
+        assert!(short.quote("A", "C", 10.0, 1).is_err());
+    }
+
+    #[test]
+    fn test_rejects_more_than_max_hops() {
+        let ab = pool(&["A", "B"], 1000.0);
+        let mut router = Router::new([("ab", &ab)]);
+        router.max_hops = MAX_HOPS;
+        assert!(router.quote("A", "B", 10.0, 1).is_ok());
+        router.max_hops = MAX_HOPS + 1;
+        let err = router.quote("A", "B", 10.0, 1).err().unwrap();
+        assert_eq!(err, OrbitalError::TooManyHops(MAX_HOPS + 1));
+    }
+
+    #[test]
+    fn test_execute_undoes_committed_hops_when_one_fails() {
+        let dir = crate::testing::temp_dir("route");
+        let mut ab = pool(&["A", "B"], 1000.0).with_state_path(dir.join("ab.json"));
+        let mut bc = pool(&["B", "C"], 1000.0).with_state_path(dir.join("bc.json"));
+        let quote = Router::new([("ab", &ab), ("bc", &bc)]).quote("A", "C", 10.0, 1).unwrap();
+        let before = ab.clone();
+
+        // The second hop fails as if its pool could not be written.
+        let mut pools = [("ab", &mut ab), ("bc", &mut bc)];
+        let err = quote
+            .execute(&mut pools, |id, amm, op| {
+                if id == "bc" { Err(OrbitalError::Io("disk full".into())) } else { amm.commit(op) }
+            })
+            .unwrap_err();
+        assert_eq!(err, OrbitalError::Io("disk full".into()));
+
+        // The first hop was committed and then undone, both journaled.
+        assert_eq!(ab.global_reserves, before.global_reserves);
+        let saved = MultiTickAMM::load_state(ab.state_path(), Vec::new()).unwrap();
+        assert_eq!(saved.global_reserves, before.global_reserves);
+        let history = ab.history().unwrap();
+        assert_eq!((history.undo_depth(), history.redo_depth()), (0, 1));
+        assert_eq!(bc.global_reserves, vec![1000.0, 1000.0]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_execution_covers_every_hop() {
+        let ab = pool(&["A", "B"], 1000.0);
+        let bc = pool(&["B", "C"], 1000.0);
+        let quote = Router::new([("ab", &ab), ("bc", &bc)]).quote("A", "C", 10.0, 1).unwrap();
+        Router::new([("ab", &ab), ("bc", &bc)]).check_execution(&quote).unwrap();
+
+        // The first hop would succeed on its own; the missing second pool
+        // must still fail the whole route.
+        let err = Router::new([("ab", &ab)]).check_execution(&quote).unwrap_err();
+        assert_eq!(err, OrbitalError::UnknownPool("bc".into()));
+
+        // A second pool that can no longer fill the last hop fails it too.
+        let thin = pool(&["B", "C"], 1.0);
+        let router = Router::new([("ab", &ab), ("bc", &thin)]);
+        assert!(router.check_execution(&quote).is_err());
+    }
+
+    #[test]
+    fn test_prefers_deeper_pool_and_splits() {
+        let shallow = pool(&["A", "B"], 500.0);
+        let deep = pool(&["A", "B"], 1000.0);
+        let router = Router::new([("shallow", &shallow), ("deep", &deep)]);
+
+        // Only the deep pool can take the whole amount on its own.
+        assert!(shallow.quote_trade("A", "B", 100.0).is_err());
+        let single = router.quote("A", "B", 100.0, 1).unwrap();
+        assert_eq!(single.paths.len(), 1);
+        assert_eq!(single.paths[0].hops[0].pool, "deep");
+
+        let split = router.quote("A", "B", 100.0, 2).unwrap();
+        assert_eq!(split.paths.len(), 2);
+        assert!(split.amount_out > single.amount_out);
+        let sent: f64 = split.paths
+            .iter()
+            .map(|p| p.amount_in)
+            .sum();
+        assert!((sent - 100.0).abs() < 1e-9);
+    }
+}
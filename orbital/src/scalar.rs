@@ -0,0 +1,370 @@
+use std::fmt;
+use std::ops::{ Add, Div, Mul, Neg, Sub };
+use std::str::FromStr;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{ One, Signed, ToPrimitive, Zero };
+use serde::{ de, Deserialize, Deserializer, Serialize, Serializer };
+
+use crate::error::OrbitalError;
+
+/// Direction to round an inexact result in. The AMM always picks the one that
+/// favours the pool: outputs round down, required inputs round up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+/// Scalar type the invariant math runs on. `Add`/`Sub` are expected to be
+/// exact; multiplication, division and square roots take an explicit rounding
+/// direction so backends with limited precision can round against the trader.
+pub trait Scalar: Clone +
+    PartialOrd +
+    fmt::Debug +
+    fmt::Display +
+    Add<Output = Self> +
+    Sub<Output = Self> +
+    Neg<Output = Self> {
+    fn zero() -> Self;
+    fn one() -> Self;
+    /// Convert a computed value; NaN and infinities are an error.
+    fn try_from_f64(v: f64) -> Result<Self, OrbitalError>;
+    fn to_f64(&self) -> f64;
+    fn mul_round(&self, rhs: &Self, rounding: Rounding) -> Self;
+    fn div_round(&self, rhs: &Self, rounding: Rounding) -> Self;
+    fn sqrt_round(&self, rounding: Rounding) -> Self;
+    /// Absolute tolerance used when checking the invariant.
+    fn tolerance() -> Self;
+
+    /// Convert a constant known to be finite, such as a literal tolerance.
+    /// Panics otherwise; computed values go through `try_from_f64`.
+    fn from_f64(v: f64) -> Self {
+        Self::try_from_f64(v).expect("finite f64 constant")
+    }
+
+    fn from_usize(n: usize) -> Self {
+        Self::from_f64(n as f64)
+    }
+
+    fn abs(&self) -> Self {
+        if *self < Self::zero() { -self.clone() } else { self.clone() }
+    }
+}
+
+/* ---------- f64 ---------- */
+
+impl Scalar for f64 {
+    fn zero() -> Self {
+        0.0
+    }
+    fn one() -> Self {
+        1.0
+    }
+    fn try_from_f64(v: f64) -> Result<Self, OrbitalError> {
+        if !v.is_finite() {
+            return Err(OrbitalError::NotFinite(v));
+        }
+        Ok(v)
+    }
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+    fn mul_round(&self, rhs: &Self, _: Rounding) -> Self {
+        self * rhs
+    }
+    fn div_round(&self, rhs: &Self, _: Rounding) -> Self {
+        self / rhs
+    }
+    fn sqrt_round(&self, _: Rounding) -> Self {
+        self.sqrt()
+    }
+    fn tolerance() -> Self {
+        1e-6
+    }
+}
+
+/* ---------- 18-decimal fixed point ---------- */
+
+const FIXED_DECIMALS: u32 = 18;
+
+fn fixed_scale() -> BigInt {
+    BigInt::from(10u32).pow(FIXED_DECIMALS)
+}
+
+/// Divide rounding towards −∞ (`Down`) or +∞ (`Up`).
+fn div_floor_ceil(num: &BigInt, den: &BigInt, rounding: Rounding) -> BigInt {
+    let q = num / den;
+    let r = num - &q * den;
+    if r.is_zero() {
+        return q;
+    }
+    let negative = r.is_negative() != den.is_negative();
+    match (rounding, negative) {
+        (Rounding::Down, true) => q - 1,
+        (Rounding::Up, false) => q + 1,
+        _ => q,
+    }
+}
+
+/// Integer square root of a non-negative value, rounded in `rounding`.
+fn isqrt(v: &BigInt, rounding: Rounding) -> BigInt {
+    if !v.is_positive() {
+        return BigInt::zero();
+    }
+    let s = v.sqrt();
+    if rounding == Rounding::Up && &(&s * &s) < v { s + 1 } else { s }
+}
+
+/// 18-decimal fixed point number, the representation used by most EVM
+/// tokens. Stored as the raw integer `value × 10¹⁸`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fixed18(pub BigInt);
+
+impl Add for Fixed18 {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Fixed18(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Fixed18 {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Fixed18(self.0 - rhs.0)
+    }
+}
+
+impl Neg for Fixed18 {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Fixed18(-self.0)
+    }
+}
+
+impl Mul for Fixed18 {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        self.mul_round(&rhs, Rounding::Down)
+    }
+}
+
+impl Div for Fixed18 {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        self.div_round(&rhs, Rounding::Down)
+    }
+}
+
+impl Scalar for Fixed18 {
+    fn zero() -> Self {
+        Fixed18(BigInt::zero())
+    }
+    fn one() -> Self {
+        Fixed18(fixed_scale())
+    }
+    fn try_from_f64(v: f64) -> Result<Self, OrbitalError> {
+        if !v.is_finite() {
+            return Err(OrbitalError::NotFinite(v));
+        }
+        // Go through the shortest decimal representation so 0.1 stays 0.1.
+        v.to_string().parse().map_err(|_| OrbitalError::NotFinite(v))
+    }
+    fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+    fn mul_round(&self, rhs: &Self, rounding: Rounding) -> Self {
+        Fixed18(div_floor_ceil(&(&self.0 * &rhs.0), &fixed_scale(), rounding))
+    }
+    fn div_round(&self, rhs: &Self, rounding: Rounding) -> Self {
+        Fixed18(div_floor_ceil(&(&self.0 * fixed_scale()), &rhs.0, rounding))
+    }
+    fn sqrt_round(&self, rounding: Rounding) -> Self {
+        Fixed18(isqrt(&(&self.0 * fixed_scale()), rounding))
+    }
+    fn tolerance() -> Self {
+        Fixed18(BigInt::from(10u32).pow(FIXED_DECIMALS - 9))
+    }
+}
+
+impl fmt::Display for Fixed18 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scale = fixed_scale();
+        let sign = if self.0.is_negative() { "-" } else { "" };
+        let abs = self.0.abs();
+        let int = &abs / &scale;
+        let frac = &abs % &scale;
+        write!(f, "{}{}.{:0>width$}", sign, int, frac.to_string(), width = FIXED_DECIMALS as usize)
+    }
+}
+
+impl FromStr for Fixed18 {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (mantissa, exp) = match s.find(['e', 'E']) {
+            Some(pos) => (&s[..pos], s[pos + 1..].parse::<i32>().map_err(|e| e.to_string())?),
+            None => (s, 0),
+        };
+        let negative = mantissa.starts_with('-');
+        let digits = mantissa.trim_start_matches(['-', '+']);
+        let (int, frac) = digits.split_once('.').unwrap_or((digits, ""));
+        let raw: BigInt = format!("{}{}", int, frac)
+            .parse()
+            .map_err(|_| format!("invalid fixed-point number '{}'", s))?;
+        // value = raw × 10^(exp − len(frac)), stored × 10¹⁸.
+        let shift = exp - (frac.len() as i32) + (FIXED_DECIMALS as i32);
+        let ten = BigInt::from(10u32);
+        let scaled = if shift >= 0 {
+            raw * ten.pow(shift as u32)
+        } else {
+            div_floor_ceil(&raw, &ten.pow(-shift as u32), Rounding::Down)
+        };
+        Ok(Fixed18(if negative { -scaled } else { scaled }))
+    }
+}
+
+impl Serialize for Fixed18 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Fixed18 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/* ---------- exact rationals ---------- */
+
+/// Bits of precision used when a square root has to be approximated.
+const EXACT_SQRT_BITS: u32 = 128;
+
+/// Arbitrary-precision rational. Every operation is exact except square
+/// roots, which are rounded to 2⁻¹²⁸ in the requested direction.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Exact(pub BigRational);
+
+impl Add for Exact {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Exact(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Exact {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Exact(self.0 - rhs.0)
+    }
+}
+
+impl Neg for Exact {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Exact(-self.0)
+    }
+}
+
+impl Mul for Exact {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Exact(self.0 * rhs.0)
+    }
+}
+
+impl Div for Exact {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        Exact(self.0 / rhs.0)
+    }
+}
+
+impl Scalar for Exact {
+    fn zero() -> Self {
+        Exact(BigRational::zero())
+    }
+    fn one() -> Self {
+        Exact(BigRational::one())
+    }
+    fn try_from_f64(v: f64) -> Result<Self, OrbitalError> {
+        BigRational::from_float(v).map(Exact).ok_or(OrbitalError::NotFinite(v))
+    }
+    fn to_f64(&self) -> f64 {
+        self.0.to_f64().unwrap_or(f64::NAN)
+    }
+    fn mul_round(&self, rhs: &Self, _: Rounding) -> Self {
+        Exact(&self.0 * &rhs.0)
+    }
+    fn div_round(&self, rhs: &Self, _: Rounding) -> Self {
+        Exact(&self.0 / &rhs.0)
+    }
+    fn sqrt_round(&self, rounding: Rounding) -> Self {
+        // √(p/q) = √(p·2²ᵏ/q) / 2ᵏ
+        let unit = BigInt::one() << EXACT_SQRT_BITS;
+        let scaled = div_floor_ceil(
+            &(self.0.numer() * &unit * &unit),
+            self.0.denom(),
+            rounding
+        );
+        Exact(BigRational::new(isqrt(&scaled, rounding), unit))
+    }
+    fn tolerance() -> Self {
+        Exact(BigRational::new(BigInt::one(), BigInt::from(10u32).pow(24)))
+    }
+}
+
+impl fmt::Display for Exact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for Exact {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Exact {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map(Exact).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_rounding_directions() {
+        let one = Fixed18::one();
+        let three = Fixed18::from_usize(3);
+        let down = one.div_round(&three, Rounding::Down);
+        let up = one.div_round(&three, Rounding::Up);
+        assert_eq!(up.0.clone() - down.0.clone(), BigInt::one());
+        assert_eq!(down.to_string(), "0.333333333333333333");
+
+        let two = Fixed18::from_usize(2);
+        assert!(two.sqrt_round(Rounding::Down) < two.sqrt_round(Rounding::Up));
+        assert_eq!("12.5".parse::<Fixed18>().unwrap(), Fixed18::from_f64(12.5));
+        assert_eq!("-1.5e2".parse::<Fixed18>().unwrap().to_f64(), -150.0);
+        assert!(Fixed18::try_from_f64(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_exact_sqrt_brackets_root() {
+        let two = Exact::from_usize(2);
+        let lo = two.sqrt_round(Rounding::Down);
+        let hi = two.sqrt_round(Rounding::Up);
+        assert!(lo.mul_round(&lo, Rounding::Down) <= two);
+        assert!(hi.mul_round(&hi, Rounding::Up) >= two);
+        assert!((hi.to_f64() - std::f64::consts::SQRT_2).abs() < 1e-15);
+        let err = Exact::try_from_f64(f64::INFINITY).unwrap_err();
+        assert_eq!(err.code(), "not_finite");
+    }
+}
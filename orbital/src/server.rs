@@ -1,25 +1,152 @@
-use std::sync::Mutex;
-use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 use actix_cors::Cors;
-use actix_web::{ get, post, web, App, HttpResponse, HttpServer, Responder, middleware::Logger };
+use actix_web::{
+    delete,
+    get,
+    post,
+    web,
+    App,
+    HttpResponse,
+    HttpServer,
+    Responder,
+    middleware::Logger,
+};
 use serde::{ Deserialize, Serialize };
+use utoipa::{ IntoParams, OpenApi, ToSchema };
 use crate::{
-    sphere::{ decompose_reserves, equal_price_point, generate_phase_data },
-    ticks::MultiTickAMM,
+    arbitrage::ArbPlan,
+    error::OrbitalError,
+    history::HistoryEntry,
+    journal::Operation,
+    lp::LpReport,
+    persist,
+    router::{ MultiHopQuote, Router, DEFAULT_MAX_HOPS },
+    sphere::{
+        decompose_reserves,
+        equal_price_point,
+        generate_phase_data,
+        DepegTickParams,
+        PhasePoint,
+    },
+    ticks::{ MultiTickAMM, RouteQuote },
 };
 use actix_files as fs;
 
-// Helper function to safely get AMM from poisoned mutex
-fn get_amm_safe(
-    amm_data: &web::Data<Mutex<MultiTickAMM>>
-) -> Result<std::sync::MutexGuard<MultiTickAMM>, String> {
-    match amm_data.lock() {
-        Ok(guard) => Ok(guard),
-        Err(poisoned) => {
-            eprintln!("Mutex was poisoned, recovering...");
-            Ok(poisoned.into_inner())
-        }
-    }
+mod live;
+mod registry;
+
+use live::LiveFeed;
+use registry::{ Pool, PoolRef, Registry };
+
+/// OpenAPI description of the HTTP API, served at `/api/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Orbital AMM",
+        description = "Sandbox API for multi-tick Orbital pools. Every `/api/pools/{id}/...` \
+            endpoint is also served as `/api/...` for the `default` pool."
+    ),
+    paths(
+        list_pools,
+        create_pool,
+        delete_pool,
+        post_route,
+        get_state,
+        get_phase_diagram,
+        reconfigure_amm,
+        post_trade,
+        get_quote,
+        post_tick,
+        get_prices,
+        set_reserves,
+        add_liquidity,
+        remove_liquidity,
+        get_price_single,
+        reset_state,
+        undo,
+        redo,
+        get_history,
+        post_checkpoint,
+        post_restore,
+        post_arbitrage,
+        get_lp,
+        get_stream,
+        get_openapi
+    )
+)]
+pub struct ApiDoc;
+
+// Commit `op` and push the resulting changes to `/api/stream` subscribers.
+fn commit(
+    amm: &mut MultiTickAMM,
+    live: &LiveFeed,
+    op: Operation
+) -> Result<serde_json::Value, OrbitalError> {
+    let before = amm.clone();
+    let output = amm.commit(op.clone())?;
+    live.publish(&op, &output, &before, amm);
+    Ok(output)
+}
+
+/// Body of a successful mutation.
+#[derive(Serialize, ToSchema)]
+struct MessageResponse {
+    success: bool,
+    message: String,
+}
+
+/// Body of a failed mutation.
+#[derive(Serialize, ToSchema)]
+struct ErrorResponse {
+    success: bool,
+    /// Stable `OrbitalError` code, absent for request validation failures.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    message: String,
+}
+
+/// Body of a failed read-only query.
+#[derive(Serialize, ToSchema)]
+struct QueryError {
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
+
+fn message_response(message: String) -> HttpResponse {
+    HttpResponse::Ok().json(MessageResponse { success: true, message })
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(ErrorResponse {
+        success: false,
+        code: None,
+        message: message.to_string(),
+    })
+}
+
+// Error body for a failed AMM operation; `code` is stable for API clients.
+fn error_response(e: &OrbitalError) -> HttpResponse {
+    let mut response = match e {
+        | OrbitalError::Io(_)
+        | OrbitalError::CorruptState(_)
+        | OrbitalError::UnsupportedSchema(_)
+        | OrbitalError::MissingState(_)
+        | OrbitalError::InvariantViolation => HttpResponse::InternalServerError(),
+        OrbitalError::UnknownPool(_) => HttpResponse::NotFound(),
+        OrbitalError::PoolExists(_) => HttpResponse::Conflict(),
+        _ => HttpResponse::BadRequest(),
+    };
+    response.json(ErrorResponse {
+        success: false,
+        code: Some(e.code()),
+        message: e.to_string(),
+    })
+}
+
+fn query_error(error: String, code: Option<&'static str>) -> HttpResponse {
+    HttpResponse::BadRequest().json(QueryError { error, code })
 }
 
 pub async fn run(
@@ -27,10 +154,13 @@ pub async fn run(
     port: u16,
     token_names: Vec<String>,
     initial_reserves: Vec<f64>,
-    initial_plane: f64
+    initial_plane: f64,
+    initial_depeg: Option<f64>,
+    state_path: PathBuf
 ) -> std::io::Result<()> {
     // Initialize or load existing state
-    let mut amm = MultiTickAMM::load_state(token_names.clone());
+    let to_io = |e: OrbitalError| std::io::Error::other(e.to_string());
+    let mut amm = MultiTickAMM::load_state(&state_path, token_names.clone()).map_err(to_io)?;
 
     // If empty, add a tick with specified configuration
     if amm.ticks.is_empty() {
@@ -40,9 +170,18 @@ pub async fn run(
             vec![1000.0; token_names.len()] // fallback
         };
 
-        amm.add_tick(initial_plane, reserves.clone());
-        amm.save_state();
-        println!("Initialized with tick: plane={}, reserves={:?}", initial_plane, reserves);
+        let op = Operation::AddTick {
+            plane: Some(initial_plane),
+            depeg_price: initial_depeg,
+            reserves: reserves.clone(),
+            fee_bps: 0,
+        };
+        let output = amm.commit(op).map_err(to_io)?;
+        println!(
+            "Initialized with tick: plane={}, reserves={:?}",
+            output["plane_constant"],
+            reserves
+        );
     }
     let static_path = if std::path::Path::new("./web/dist").exists() {
         "./web/dist" // Docker/production path
@@ -56,29 +195,20 @@ pub async fn run(
             )
         );
     };
-    let amm_data = web::Data::new(Mutex::new(amm));
+    let registry = web::Data::new(Registry::load(amm, persist::POOLS_DIR).map_err(to_io)?);
 
     println!("Server running at http://{}:{}", addr, port);
+    println!("Pools: {:?}", registry.list().into_iter().map(|(id, _)| id).collect::<Vec<_>>());
     println!("Tokens: {:?}", token_names);
 
     HttpServer::new(move || {
         let cors = Cors::default().allow_any_origin().allow_any_method().allow_any_header();
         let static_path_clone = static_path.to_string();
         App::new()
-            .app_data(amm_data.clone())
+            .app_data(registry.clone())
             .wrap(cors)
             .wrap(Logger::default())
-            .service(get_state)
-            .service(post_trade)
-            .service(post_tick)
-            .service(get_prices)
-            .service(reset_state)
-            .service(set_reserves)
-            .service(add_liquidity)
-            .service(remove_liquidity)
-            .service(get_price_single)
-            .service(reconfigure_amm)
-            .service(get_phase_diagram)
+            .configure(api_routes)
             .service(
                 fs::Files::new("/", static_path_clone).index_file("index.html").show_files_listing()
             )
@@ -87,15 +217,55 @@ pub async fn run(
         .run().await
 }
 
-#[derive(Serialize)]
+/// Register every API endpoint; static files are served next to them by `run`.
+fn api_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_pools)
+        .service(create_pool)
+        .service(delete_pool)
+        .service(post_route)
+        .service(get_openapi)
+        .configure(pool_routes("/api/pools/{id}"))
+        // Unscoped routes act on the default pool.
+        .configure(pool_routes("/api"));
+}
+
+/// Register the per-pool endpoints under `prefix`.
+fn pool_routes(prefix: &'static str) -> impl FnOnce(&mut web::ServiceConfig) {
+    move |cfg| {
+        cfg.route(&format!("{}/state", prefix), web::get().to(get_state));
+        cfg.route(&format!("{}/phase-diagram", prefix), web::get().to(get_phase_diagram));
+        cfg.route(&format!("{}/reconfigure", prefix), web::post().to(reconfigure_amm));
+        cfg.route(&format!("{}/trade", prefix), web::post().to(post_trade));
+        cfg.route(&format!("{}/quote", prefix), web::get().to(get_quote));
+        cfg.route(&format!("{}/tick", prefix), web::post().to(post_tick));
+        cfg.route(&format!("{}/prices", prefix), web::get().to(get_prices));
+        cfg.route(&format!("{}/set-reserves", prefix), web::post().to(set_reserves));
+        cfg.route(&format!("{}/add-liquidity", prefix), web::post().to(add_liquidity));
+        cfg.route(&format!("{}/remove-liquidity", prefix), web::post().to(remove_liquidity));
+        cfg.route(&format!("{}/price", prefix), web::get().to(get_price_single));
+        cfg.route(&format!("{}/reset", prefix), web::post().to(reset_state));
+        cfg.route(&format!("{}/undo", prefix), web::post().to(undo));
+        cfg.route(&format!("{}/redo", prefix), web::post().to(redo));
+        cfg.route(&format!("{}/history", prefix), web::get().to(get_history));
+        cfg.route(&format!("{}/checkpoint/{{name}}", prefix), web::post().to(post_checkpoint));
+        cfg.route(&format!("{}/restore/{{name}}", prefix), web::post().to(post_restore));
+        cfg.route(&format!("{}/arbitrage", prefix), web::post().to(post_arbitrage));
+        cfg.route(&format!("{}/lp/{{lp_id}}", prefix), web::get().to(get_lp));
+        cfg.route(&format!("{}/stream", prefix), web::get().to(get_stream));
+    }
+}
+
+#[derive(Serialize, ToSchema)]
 struct StateResponse {
     ticks: Vec<TickInfo>,
     token_names: Vec<String>,
     global_reserves: Vec<f64>,
+    total_fees: Vec<f64>,
+    capital_efficiency: f64,
     tick_count: usize,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct TickInfo {
     index: usize,
     plane_constant: f64,
@@ -104,21 +274,19 @@ struct TickInfo {
     is_interior: bool,
     is_boundary: bool,
     liquidity: f64,
+    fee_bps: u32,
+    fees_accrued: Vec<f64>,
+    min_reserve: f64,
+    virtual_reserves: Vec<f64>,
+    capital_efficiency: f64,
 }
 
-#[get("/api/state")]
-async fn get_state(amm: web::Data<Mutex<MultiTickAMM>>) -> impl Responder {
-    let state = match get_amm_safe(&amm) {
-        Ok(guard) => guard,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({"error": e}));
-        }
-    };
-
+fn state_response(state: &MultiTickAMM) -> StateResponse {
     let tick_infos: Vec<TickInfo> = state.ticks
         .iter()
         .enumerate()
         .map(|(i, tick)| {
+            let efficiency = tick.efficiency();
             TickInfo {
                 index: i,
                 plane_constant: tick.plane_constant,
@@ -127,22 +295,72 @@ async fn get_state(amm: web::Data<Mutex<MultiTickAMM>>) -> impl Responder {
                 is_interior: tick.is_interior(),
                 is_boundary: tick.is_boundary(),
                 liquidity: tick.liquidity(),
+                fee_bps: tick.fee_bps,
+                fees_accrued: tick.fees_accrued.clone(),
+                min_reserve: efficiency.min_reserve,
+                virtual_reserves: efficiency.virtual_reserves,
+                capital_efficiency: efficiency.capital_efficiency,
             }
         })
         .collect();
 
-    let response = StateResponse {
+    StateResponse {
         ticks: tick_infos,
         token_names: state.token_names.clone(),
         global_reserves: state.global_reserves.clone(),
+        total_fees: state.total_fees(),
+        capital_efficiency: state.efficiency_report().capital_efficiency,
         tick_count: state.ticks.len(),
-    };
+    }
+}
 
-    HttpResponse::Ok().json(response)
+/// Current ticks, reserves, fees and capital efficiency.
+#[utoipa::path(
+    get,
+    path = "/api/pools/{id}/state",
+    params(("id" = String, Path, description = "Pool id")),
+    responses((status = 200, body = StateResponse))
+)]
+async fn get_state(pool: PoolRef) -> impl Responder {
+    let state = pool.lock();
+
+    HttpResponse::Ok().json(state_response(&state))
 }
-#[get("/api/phase-diagram")]
-async fn get_phase_diagram(amm: web::Data<Mutex<MultiTickAMM>>) -> impl Responder {
-    let state = amm.lock().unwrap();
+
+#[derive(Serialize, ToSchema)]
+struct PhaseTick {
+    parallel_magnitude: f64,
+    plane_constant: f64,
+    reserves: Vec<f64>,
+    is_interior: bool,
+    is_boundary: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PhaseDiagramResponse {
+    phase_points: Vec<PhasePoint>,
+    equal_price_point: f64,
+    radius: f64,
+    current_ticks: Vec<PhaseTick>,
+}
+
+/// Sphere cross-section of the first tick, with every tick's position on it.
+#[utoipa::path(
+    get,
+    path = "/api/pools/{id}/phase-diagram",
+    params(("id" = String, Path, description = "Pool id")),
+    responses(
+        (status = 200, body = PhaseDiagramResponse),
+        (
+            status = 400,
+            description = "No ticks available",
+            body = String,
+            content_type = "text/plain",
+        )
+    )
+)]
+async fn get_phase_diagram(pool: PoolRef) -> impl Responder {
+    let state = pool.lock();
 
     if state.ticks.is_empty() {
         return HttpResponse::BadRequest().body("No ticks available");
@@ -155,210 +373,245 @@ async fn get_phase_diagram(amm: web::Data<Mutex<MultiTickAMM>>) -> impl Responde
     let phase_data = generate_phase_data(radius, n_tokens, 50);
     let equal_price = equal_price_point(radius, n_tokens);
 
-    let response =
-        serde_json::json!({
-        "phase_points": phase_data,
-        "equal_price_point": equal_price,
-        "radius": radius,
-        "current_ticks": state.ticks.iter().map(|tick| {
-            let (parallel, _) = decompose_reserves(&tick.sphere_amm.reserves);
-            serde_json::json!({
-                "parallel_magnitude": parallel,
-                "plane_constant": tick.plane_constant,
-                "reserves": tick.sphere_amm.reserves,
-                "is_interior": tick.is_interior(),
-                "is_boundary": tick.is_boundary()
+    let response = PhaseDiagramResponse {
+        phase_points: phase_data,
+        equal_price_point: equal_price,
+        radius,
+        current_ticks: state.ticks
+            .iter()
+            .map(|tick| {
+                let (parallel, _) = decompose_reserves(&tick.sphere_amm.reserves);
+                PhaseTick {
+                    parallel_magnitude: parallel,
+                    plane_constant: tick.plane_constant,
+                    reserves: tick.sphere_amm.reserves.clone(),
+                    is_interior: tick.is_interior(),
+                    is_boundary: tick.is_boundary(),
+                }
             })
-        }).collect::<Vec<_>>()
-    });
+            .collect(),
+    };
 
     HttpResponse::Ok().json(response)
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct ReconfigureReq {
     token_names: Vec<String>,
     initial_reserves: Vec<f64>,
     initial_plane: f64,
 }
 
-#[post("/api/reconfigure")]
+/// Replace the pool with a single tick over a new token set.
+#[utoipa::path(
+    post,
+    path = "/api/pools/{id}/reconfigure",
+    params(("id" = String, Path, description = "Pool id")),
+    request_body = ReconfigureReq,
+    responses(
+        (status = 200, body = MessageResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
 async fn reconfigure_amm(
-    amm: web::Data<Mutex<MultiTickAMM>>,
+    pool: PoolRef,
     json: web::Json<ReconfigureReq>
 ) -> impl Responder {
-    let mut amm_guard = match get_amm_safe(&amm) {
-        Ok(guard) => guard,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({"error": e}));
-        }
-    };
+    let mut amm_guard = pool.lock();
 
     // Validation
     if json.initial_reserves.len() != json.token_names.len() {
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({
-            "success": false,
-            "message": "Token names and reserves length mismatch"
-        })
-        );
+        return bad_request("Token names and reserves length mismatch");
     }
 
     if json.token_names.is_empty() {
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({
-            "success": false,
-            "message": "At least one token is required"
-        })
-        );
+        return bad_request("At least one token is required");
     }
 
     if json.initial_reserves.iter().any(|&r| r < 0.0) {
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({
-            "success": false,
-            "message": "All reserves must be non-negative"
-        })
-        );
+        return bad_request("All reserves must be non-negative");
     }
 
     if json.initial_plane <= 0.0 {
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({
-            "success": false,
-            "message": "Plane constant must be positive"
-        })
-        );
+        return bad_request("Plane constant must be positive");
     }
 
-    // Create completely new AMM with new configuration
-    *amm_guard = MultiTickAMM::new(json.token_names.clone());
-
-    // Add initial tick with specified configuration
-    amm_guard.add_tick(json.initial_plane, json.initial_reserves.clone());
-    amm_guard.save_state();
+    // Create completely new AMM with a single tick, saved to the same file
+    let op = Operation::Reconfigure {
+        token_names: json.token_names.clone(),
+        initial_reserves: json.initial_reserves.clone(),
+        initial_plane: json.initial_plane,
+    };
+    if let Err(e) = commit(&mut amm_guard, &pool.live, op) {
+        return error_response(&e);
+    }
 
-    HttpResponse::Ok().json(
-        serde_json::json!({
-        "success": true,
-        "message": format!("AMM reconfigured with tokens: {:?}", json.token_names)
-    })
-    )
+    message_response(format!("AMM reconfigured with tokens: {:?}", json.token_names))
 }
 
-#[derive(Deserialize)]
-struct ResetConfig {
-    reserves: Vec<f64>,
-    plane: f64,
-}
-
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct TradeReq {
     from: String,
     to: String,
+    /// Input amount, or the exact output amount when `exact_out` is set.
     amount: f64,
+    #[serde(default)]
+    exact_out: bool,
+    max_amount_in: Option<f64>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct TradeResponse {
+    input: f64,
     output: f64,
     success: bool,
     message: String,
 }
 
-#[post("/api/trade")]
+/// Route a trade across the ticks.
+#[utoipa::path(
+    post,
+    path = "/api/pools/{id}/trade",
+    params(("id" = String, Path, description = "Pool id")),
+    request_body = TradeReq,
+    responses(
+        (status = 200, body = TradeResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
 async fn post_trade(
-    amm: web::Data<Mutex<MultiTickAMM>>,
+    pool: PoolRef,
     json: web::Json<TradeReq>
 ) -> impl Responder {
-    let mut amm_guard = match get_amm_safe(&amm) {
-        Ok(guard) => guard,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({"error": e}));
-        }
+    let mut amm_guard = pool.lock();
+
+    let op = Operation::Trade {
+        from: json.from.clone(),
+        to: json.to.clone(),
+        amount: json.amount,
+        exact_out: json.exact_out,
+        max_amount_in: json.max_amount_in,
     };
 
-    match amm_guard.route_trade(&json.from, &json.to, json.amount) {
+    match commit(&mut amm_guard, &pool.live, op) {
         Ok(output) => {
-            amm_guard.save_state();
+            let input = output["amount_in"].as_f64().unwrap_or_default();
+            let output = output["amount_out"].as_f64().unwrap_or_default();
             let response = TradeResponse {
+                input,
                 output,
                 success: true,
-                message: format!(
-                    "Swapped {} {} for {} {}",
-                    json.amount,
-                    json.from,
-                    output,
-                    json.to
-                ),
+                message: format!("Swapped {} {} for {} {}", input, json.from, output, json.to),
             };
             HttpResponse::Ok().json(response)
         }
-        Err(e) => {
-            let response = TradeResponse {
-                output: 0.0,
-                success: false,
-                message: format!("Trade failed: {}", e),
-            };
-            HttpResponse::BadRequest().json(response)
-        }
+        Err(e) => error_response(&e),
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+struct QuoteQuery {
+    from: String,
+    to: String,
+    amount: f64,
+    #[serde(default)]
+    exact_out: bool,
+}
+
+/// Dry-run a trade without changing the pool.
+#[utoipa::path(
+    get,
+    path = "/api/pools/{id}/quote",
+    params(("id" = String, Path, description = "Pool id"), QuoteQuery),
+    responses((status = 200, body = RouteQuote), (status = 400, body = QueryError))
+)]
+async fn get_quote(
+    pool: PoolRef,
+    query: web::Query<QuoteQuery>
+) -> impl Responder {
+    let state = pool.lock();
+
+    let quote = if query.exact_out {
+        state.quote_trade_exact_out(&query.from, &query.to, query.amount)
+    } else {
+        state.quote_trade(&query.from, &query.to, query.amount)
+    };
+
+    match quote {
+        Ok(q) => HttpResponse::Ok().json(q),
+        Err(e) => query_error(e.to_string(), Some(e.code())),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
 struct TickReq {
-    plane: f64,
+    /// Raw plane constant; ignored when `depeg_price` is given.
+    plane: Option<f64>,
+    /// Lowest price of a depegged token the tick should still cover.
+    depeg_price: Option<f64>,
     reserves: Vec<f64>,
+    /// Swap fee tier in basis points.
+    #[serde(default)]
+    fee_bps: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TickResponse {
+    success: bool,
+    message: String,
+    /// Derived tick parameters when the tick was added from `depeg_price`.
+    params: Option<DepegTickParams>,
 }
 
-#[post("/api/tick")]
+/// Add a tick, from a plane constant or a depeg price.
+#[utoipa::path(
+    post,
+    path = "/api/pools/{id}/tick",
+    params(("id" = String, Path, description = "Pool id")),
+    request_body = TickReq,
+    responses(
+        (status = 200, body = TickResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
 async fn post_tick(
-    amm: web::Data<Mutex<MultiTickAMM>>,
+    pool: PoolRef,
     json: web::Json<TickReq>
 ) -> impl Responder {
-    let mut amm_guard = match get_amm_safe(&amm) {
-        Ok(guard) => guard,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({"error": e}));
-        }
-    };
+    let mut amm_guard = pool.lock();
 
     if json.reserves.len() != amm_guard.token_names.len() {
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({
-            "success": false,
-            "message": "Reserve length mismatch"
-        })
-        );
+        return bad_request("Reserve length mismatch");
     }
 
-    amm_guard.add_tick(json.plane, json.reserves.clone());
-    amm_guard.save_state();
-
-    HttpResponse::Ok().json(
-        serde_json::json!({
-        "success": true,
-        "message": format!("Added tick with plane constant {}", json.plane)
-    })
-    )
+    let op = Operation::AddTick {
+        plane: json.plane,
+        depeg_price: json.depeg_price,
+        reserves: json.reserves.clone(),
+        fee_bps: json.fee_bps,
+    };
+    match commit(&mut amm_guard, &pool.live, op) {
+        Ok(output) =>
+            HttpResponse::Ok().json(TickResponse {
+                success: true,
+                message: format!("Added tick with plane constant {}", output["plane_constant"]),
+                params: serde_json::from_value(output["params"].clone()).unwrap_or(None),
+            }),
+        Err(e) => error_response(&e),
+    }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct PriceInfo {
     from: String,
     to: String,
     price: f64,
 }
 
-#[get("/api/prices")]
-async fn get_prices(amm: web::Data<Mutex<MultiTickAMM>>) -> impl Responder {
-    let state = match get_amm_safe(&amm) {
-        Ok(guard) => guard,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({"error": e}));
-        }
-    };
-
+fn price_infos(state: &MultiTickAMM) -> Vec<PriceInfo> {
     let mut prices = Vec::new();
 
     for i in 0..state.token_names.len() {
@@ -380,257 +633,958 @@ async fn get_prices(amm: web::Data<Mutex<MultiTickAMM>>) -> impl Responder {
         }
     }
 
-    HttpResponse::Ok().json(prices)
+    prices
 }
 
-#[derive(Deserialize)]
+/// Aggregated price of every token pair.
+#[utoipa::path(
+    get,
+    path = "/api/pools/{id}/prices",
+    params(("id" = String, Path, description = "Pool id")),
+    responses((status = 200, body = Vec<PriceInfo>))
+)]
+async fn get_prices(pool: PoolRef) -> impl Responder {
+    let state = pool.lock();
+
+    HttpResponse::Ok().json(price_infos(&state))
+}
+
+#[derive(Deserialize, ToSchema)]
 struct SetReservesReq {
     tick_index: usize,
     reserves: Vec<f64>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct AddLiquidityReq {
     tick_index: usize,
     lp_id: String,
     amounts: Vec<f64>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct RemoveLiquidityReq {
     tick_index: usize,
     lp_id: String,
     percentage: f64,
 }
 
-#[post("/api/set-reserves")]
+/// Overwrite a tick's reserves; its radius is re-solved to fit them.
+#[utoipa::path(
+    post,
+    path = "/api/pools/{id}/set-reserves",
+    params(("id" = String, Path, description = "Pool id")),
+    request_body = SetReservesReq,
+    responses(
+        (status = 200, body = MessageResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
 async fn set_reserves(
-    amm: web::Data<Mutex<MultiTickAMM>>,
+    pool: PoolRef,
     json: web::Json<SetReservesReq>
 ) -> impl Responder {
-    let mut amm_guard = match get_amm_safe(&amm) {
-        Ok(guard) => guard,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({"error": e}));
-        }
-    };
+    let mut amm_guard = pool.lock();
 
-    if json.tick_index >= amm_guard.ticks.len() {
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({
-            "success": false,
-            "message": "Invalid tick index"
-        })
-        );
+    // Directly set reserves; the radius is re-solved to keep the tick on its
+    // sphere, and reserves that lie on none are rejected.
+    let op = Operation::SetReserves {
+        tick_index: json.tick_index,
+        reserves: json.reserves.clone(),
+    };
+    if let Err(e) = commit(&mut amm_guard, &pool.live, op) {
+        return error_response(&e);
     }
 
-    if json.reserves.len() != amm_guard.token_names.len() {
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({
-            "success": false,
-            "message": "Reserve length mismatch"
-        })
-        );
+    message_response(format!("Set reserves for tick {}", json.tick_index))
+}
+
+#[derive(Serialize, ToSchema)]
+struct AddLiquidityResponse {
+    success: bool,
+    message: String,
+    /// Amount of each token taken into the tick.
+    deposited: Vec<f64>,
+    /// Part of the offered amounts not proportional to the tick's holdings.
+    refunded: Vec<f64>,
+    /// Shares minted for the LP.
+    shares: f64,
+}
+
+/// Deposit token amounts into a tick on behalf of an LP.
+#[utoipa::path(
+    post,
+    path = "/api/pools/{id}/add-liquidity",
+    params(("id" = String, Path, description = "Pool id")),
+    request_body = AddLiquidityReq,
+    responses(
+        (status = 200, body = AddLiquidityResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
+async fn add_liquidity(
+    pool: PoolRef,
+    json: web::Json<AddLiquidityReq>
+) -> impl Responder {
+    let mut amm_guard = pool.lock();
+
+    let op = Operation::AddLiquidity {
+        tick_index: json.tick_index,
+        lp_id: json.lp_id.clone(),
+        amounts: json.amounts.clone(),
+    };
+    match commit(&mut amm_guard, &pool.live, op) {
+        Ok(output) =>
+            HttpResponse::Ok().json(AddLiquidityResponse {
+                success: true,
+                message: format!("Added liquidity for LP {}", json.lp_id),
+                deposited: serde_json::from_value(output["deposited"].clone()).unwrap_or_default(),
+                refunded: serde_json::from_value(output["refunded"].clone()).unwrap_or_default(),
+                shares: output["shares"].as_f64().unwrap_or_default(),
+            }),
+        Err(e) => error_response(&e),
     }
+}
 
-    // Validation
-    if json.reserves.iter().any(|&r| r < 0.0) {
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({
-            "success": false,
-            "message": "All reserves must be non-negative"
-        })
-        );
+#[derive(Serialize, ToSchema)]
+struct RemoveLiquidityResponse {
+    success: bool,
+    message: String,
+    /// Amount of each token paid out to the LP.
+    withdrawn: Vec<f64>,
+}
+
+/// Withdraw a fraction of an LP's position in a tick.
+#[utoipa::path(
+    post,
+    path = "/api/pools/{id}/remove-liquidity",
+    params(("id" = String, Path, description = "Pool id")),
+    request_body = RemoveLiquidityReq,
+    responses(
+        (status = 200, body = RemoveLiquidityResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
+    )
+)]
+async fn remove_liquidity(
+    pool: PoolRef,
+    json: web::Json<RemoveLiquidityReq>
+) -> impl Responder {
+    let mut amm_guard = pool.lock();
+
+    let op = Operation::RemoveLiquidity {
+        tick_index: json.tick_index,
+        lp_id: json.lp_id.clone(),
+        percentage: json.percentage,
+    };
+    match commit(&mut amm_guard, &pool.live, op) {
+        Ok(output) =>
+            HttpResponse::Ok().json(RemoveLiquidityResponse {
+                success: true,
+                message: format!("Removed liquidity for LP {}", json.lp_id),
+                withdrawn: serde_json::from_value(output["withdrawn"].clone()).unwrap_or_default(),
+            }),
+        Err(e) => error_response(&e),
     }
+}
 
-    // Directly set reserves and recalculate radius
-    let tick = &mut amm_guard.ticks[json.tick_index];
-    tick.sphere_amm.reserves = json.reserves.clone();
+#[derive(Deserialize, IntoParams)]
+struct PriceQuery {
+    from: Option<String>,
+    to: Option<String>,
+}
 
-    // Recalculate radius to maintain sphere constraint
-    let n = tick.sphere_amm.reserves.len() as f64;
-    let sum_x: f64 = tick.sphere_amm.reserves.iter().sum();
-    let sum_x2: f64 = tick.sphere_amm.reserves
-        .iter()
-        .map(|x| x * x)
-        .sum();
-    let a = n - 1.0;
+#[derive(Serialize, ToSchema)]
+struct PriceResponse {
+    price: f64,
+    from: String,
+    to: String,
+}
 
-    tick.sphere_amm.radius = if a.abs() < 1e-12 {
-        sum_x
-    } else {
-        let b = -2.0 * sum_x;
-        let c = sum_x2;
-        let disc = (b * b - 4.0 * a * c).max(0.0);
-        let r1 = (-b + disc.sqrt()) / (2.0 * a);
-        if r1 > 0.0 {
-            r1
-        } else {
-            (-b - disc.sqrt()) / (2.0 * a)
+/// Aggregated price of `from` in units of `to`.
+#[utoipa::path(
+    get,
+    path = "/api/pools/{id}/price",
+    params(("id" = String, Path, description = "Pool id"), PriceQuery),
+    responses((status = 200, body = PriceResponse), (status = 400, body = QueryError))
+)]
+async fn get_price_single(
+    pool: PoolRef,
+    query: web::Query<PriceQuery>
+) -> impl Responder {
+    let state = pool.lock();
+
+    let from = match &query.from {
+        Some(f) => f,
+        None => {
+            return query_error("Missing from parameter".into(), None);
         }
     };
 
-    amm_guard.save_state();
+    let to = match &query.to {
+        Some(t) => t,
+        None => {
+            return query_error("Missing to parameter".into(), None);
+        }
+    };
 
-    HttpResponse::Ok().json(
-        serde_json::json!({
-        "success": true,
-        "message": format!("Set reserves for tick {}", json.tick_index)
-    })
+    match state.get_aggregated_price(from, to) {
+        Ok(price) =>
+            HttpResponse::Ok().json(PriceResponse { price, from: from.clone(), to: to.clone() }),
+        Err(e) => query_error(e.to_string(), Some(e.code())),
+    }
+}
+
+/// Replace the pool with the default single tick over the current tokens.
+#[utoipa::path(
+    post,
+    path = "/api/pools/{id}/reset",
+    params(("id" = String, Path, description = "Pool id")),
+    responses(
+        (status = 200, body = MessageResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 500, body = ErrorResponse)
     )
+)]
+async fn reset_state(pool: PoolRef) -> impl Responder {
+    let mut amm_guard = pool.lock();
+
+    // Reset to fresh state with the default tick
+    let op = Operation::Reset {
+        initial_reserves: vec![1000.0; amm_guard.token_names.len()],
+        initial_plane: 1800.0,
+    };
+    if let Err(e) = commit(&mut amm_guard, &pool.live, op) {
+        return error_response(&e);
+    }
+
+    message_response("AMM state reset with default tick".into())
 }
 
-#[post("/api/add-liquidity")]
-async fn add_liquidity(
-    amm: web::Data<Mutex<MultiTickAMM>>,
-    json: web::Json<AddLiquidityReq>
+/// Undo the last operation.
+#[utoipa::path(
+    post,
+    path = "/api/pools/{id}/undo",
+    params(("id" = String, Path, description = "Pool id")),
+    responses(
+        (status = 200, body = MessageResponse),
+        (status = 400, description = "Nothing to undo", body = ErrorResponse)
+    )
+)]
+async fn undo(pool: PoolRef) -> impl Responder {
+    let mut amm_guard = pool.lock();
+
+    let result = amm_guard
+        .history()
+        .and_then(|h| h.undo_op())
+        .and_then(|op| commit(&mut amm_guard, &pool.live, op));
+    match result {
+        Ok(_) => message_response("Undid last operation".into()),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Re-apply the last undone operation.
+#[utoipa::path(
+    post,
+    path = "/api/pools/{id}/redo",
+    params(("id" = String, Path, description = "Pool id")),
+    responses(
+        (status = 200, body = MessageResponse),
+        (status = 400, description = "Nothing to redo", body = ErrorResponse)
+    )
+)]
+async fn redo(pool: PoolRef) -> impl Responder {
+    let mut amm_guard = pool.lock();
+
+    let result = amm_guard
+        .history()
+        .and_then(|h| h.redo_op())
+        .and_then(|op| commit(&mut amm_guard, &pool.live, op));
+    match result {
+        Ok(_) => message_response("Redid last undone operation".into()),
+        Err(e) => error_response(&e),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct HistoryResponse {
+    entries: Vec<HistoryEntry>,
+    undo_depth: usize,
+    redo_depth: usize,
+    checkpoints: Vec<String>,
+}
+
+/// Journaled operations, undo/redo depth and checkpoint names.
+#[utoipa::path(
+    get,
+    path = "/api/pools/{id}/history",
+    params(("id" = String, Path, description = "Pool id")),
+    responses((status = 200, body = HistoryResponse), (status = 500, body = ErrorResponse))
+)]
+async fn get_history(pool: PoolRef) -> impl Responder {
+    let amm_guard = pool.lock();
+
+    match amm_guard.history() {
+        Ok(history) =>
+            HttpResponse::Ok().json(HistoryResponse {
+                undo_depth: history.undo_depth(),
+                redo_depth: history.redo_depth(),
+                checkpoints: history.checkpoint_names(),
+                entries: history.entries,
+            }),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Checkpoint name in the path. A struct rather than `String` because pool
+/// routes also carry `{id}`.
+#[derive(Deserialize)]
+struct CheckpointPath {
+    name: String,
+}
+
+/// Name the current state so it can be restored later.
+#[utoipa::path(
+    post,
+    path = "/api/pools/{id}/checkpoint/{name}",
+    params(
+        ("id" = String, Path, description = "Pool id"),
+        ("name" = String, Path, description = "Checkpoint name")
+    ),
+    responses((status = 200, body = MessageResponse), (status = 500, body = ErrorResponse))
+)]
+async fn post_checkpoint(
+    pool: PoolRef,
+    path: web::Path<CheckpointPath>
 ) -> impl Responder {
-    let mut amm_guard = match get_amm_safe(&amm) {
-        Ok(guard) => guard,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({"error": e}));
-        }
-    };
+    let mut amm_guard = pool.lock();
 
-    if json.tick_index >= amm_guard.ticks.len() {
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({
-            "success": false,
-            "message": "Invalid tick index"
-        })
-        );
+    let name = &path.name;
+    let op = Operation::Checkpoint { name: name.clone() };
+    match commit(&mut amm_guard, &pool.live, op) {
+        Ok(_) => message_response(format!("Saved checkpoint '{}'", name)),
+        Err(e) => error_response(&e),
     }
+}
 
-    let tick = &mut amm_guard.ticks[json.tick_index];
-    match tick.add_liquidity(&json.lp_id, &json.amounts) {
-        Ok(_) => {
-            amm_guard.save_state();
-            HttpResponse::Ok().json(
-                serde_json::json!({
-                "success": true,
-                "message": format!("Added liquidity for LP {}", json.lp_id)
-            })
-            )
-        }
-        Err(e) =>
-            HttpResponse::BadRequest().json(
-                serde_json::json!({
-            "success": false,
-            "message": e
-        })
-            ),
+/// Return to a named checkpoint.
+#[utoipa::path(
+    post,
+    path = "/api/pools/{id}/restore/{name}",
+    params(
+        ("id" = String, Path, description = "Pool id"),
+        ("name" = String, Path, description = "Checkpoint name")
+    ),
+    responses(
+        (status = 200, body = MessageResponse),
+        (status = 400, description = "Unknown checkpoint", body = ErrorResponse)
+    )
+)]
+async fn post_restore(
+    pool: PoolRef,
+    path: web::Path<CheckpointPath>
+) -> impl Responder {
+    let mut amm_guard = pool.lock();
+
+    let name = &path.name;
+    let result = amm_guard
+        .history()
+        .and_then(|h| h.restore_op(name))
+        .and_then(|op| commit(&mut amm_guard, &pool.live, op));
+    match result {
+        Ok(_) => message_response(format!("Restored checkpoint '{}'", name)),
+        Err(e) => error_response(&e),
     }
 }
 
-#[post("/api/remove-liquidity")]
-async fn remove_liquidity(
-    amm: web::Data<Mutex<MultiTickAMM>>,
-    json: web::Json<RemoveLiquidityReq>
+#[derive(Deserialize, ToSchema)]
+struct ArbitrageReq {
+    /// Reference price of every token in a common numeraire.
+    prices: Vec<f64>,
+    /// Execute the trades instead of only planning them.
+    #[serde(default)]
+    execute: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ArbitrageResponse {
+    executed: bool,
+    plan: ArbPlan,
+}
+
+/// Plan, and optionally execute, the trades that bring the pool's prices to
+/// external reference prices.
+#[utoipa::path(
+    post,
+    path = "/api/pools/{id}/arbitrage",
+    params(("id" = String, Path, description = "Pool id")),
+    request_body = ArbitrageReq,
+    responses((status = 200, body = ArbitrageResponse), (status = 400, body = ErrorResponse))
+)]
+async fn post_arbitrage(
+    pool: PoolRef,
+    json: web::Json<ArbitrageReq>
 ) -> impl Responder {
-    let mut amm_guard = match get_amm_safe(&amm) {
-        Ok(guard) => guard,
+    let mut amm_guard = pool.lock();
+
+    let plan = match amm_guard.arbitrage_plan(&json.prices) {
+        Ok(plan) => plan,
         Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({"error": e}));
+            return error_response(&e);
         }
     };
-
-    if json.tick_index >= amm_guard.ticks.len() {
-        return HttpResponse::BadRequest().json(
-            serde_json::json!({
-            "success": false,
-            "message": "Invalid tick index"
-        })
-        );
+    if json.execute {
+        if let Err(e) = commit(&mut amm_guard, &pool.live, plan.operation()) {
+            return error_response(&e);
+        }
     }
+    HttpResponse::Ok().json(ArbitrageResponse { executed: json.execute, plan })
+}
 
-    let tick = &mut amm_guard.ticks[json.tick_index];
-    match tick.withdraw_liquidity(&json.lp_id, json.percentage) {
-        Ok(withdrawn) => {
-            amm_guard.save_state();
-            HttpResponse::Ok().json(
-                serde_json::json!({
-                "success": true,
-                "message": format!("Removed liquidity for LP {}", json.lp_id),
-                "withdrawn": withdrawn
-            })
-            )
+#[derive(Deserialize)]
+struct LpPath {
+    lp_id: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+struct LpQuery {
+    /// Comma-separated price per token to value positions at; the pool's own
+    /// prices, in units of the first token, when absent.
+    prices: Option<String>,
+}
+
+/// An LP's positions across ticks: token amounts, value, value if held,
+/// impermanent loss and fee income.
+#[utoipa::path(
+    get,
+    path = "/api/pools/{id}/lp/{lp_id}",
+    params(
+        ("id" = String, Path, description = "Pool id"),
+        ("lp_id" = String, Path, description = "LP id"),
+        LpQuery
+    ),
+    responses((status = 200, body = LpReport), (status = 400, body = QueryError))
+)]
+async fn get_lp(
+    pool: PoolRef,
+    path: web::Path<LpPath>,
+    query: web::Query<LpQuery>
+) -> impl Responder {
+    let state = pool.lock();
+
+    let prices: Option<Vec<f64>> = match &query.prices {
+        Some(text) => {
+            let parsed: Result<Vec<f64>, _> = text
+                .split(',')
+                .map(|p| p.trim().parse::<f64>())
+                .collect();
+            match parsed {
+                Ok(prices) => Some(prices),
+                Err(_) => {
+                    return query_error(format!("Invalid prices '{}'", text), None);
+                }
+            }
         }
-        Err(e) =>
-            HttpResponse::BadRequest().json(
-                serde_json::json!({
-            "success": false,
-            "message": e
-        })
-            ),
+        None => None,
+    };
+    match state.lp_report(&path.lp_id, prices.as_deref()) {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => query_error(e.to_string(), Some(e.code())),
     }
 }
 
-#[get("/api/price")]
-async fn get_price_single(
-    amm: web::Data<Mutex<MultiTickAMM>>,
-    query: web::Query<HashMap<String, String>>
+/// Server-Sent Events: `snapshot`, then `trade`/`operation`, `state` and
+/// `prices` events for every change.
+#[utoipa::path(
+    get,
+    path = "/api/pools/{id}/stream",
+    params(("id" = String, Path, description = "Pool id")),
+    responses((status = 200, description = "Event stream", content_type = "text/event-stream"))
+)]
+async fn get_stream(pool: PoolRef) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(live::subscribe(pool.0))
+}
+
+#[derive(Serialize, ToSchema)]
+struct PoolInfo {
+    id: String,
+    token_names: Vec<String>,
+    tick_count: usize,
+    global_reserves: Vec<f64>,
+    state_path: String,
+}
+
+fn pool_info(id: &str, amm: &MultiTickAMM) -> PoolInfo {
+    PoolInfo {
+        id: id.to_string(),
+        token_names: amm.token_names.clone(),
+        tick_count: amm.ticks.len(),
+        global_reserves: amm.global_reserves.clone(),
+        state_path: amm.state_path().display().to_string(),
+    }
+}
+
+/// Every pool served, `default` included.
+#[utoipa::path(responses((status = 200, body = Vec<PoolInfo>)))]
+#[get("/api/pools")]
+async fn list_pools(registry: web::Data<Registry>) -> impl Responder {
+    let pools: Vec<PoolInfo> = registry
+        .list()
+        .iter()
+        .map(|(id, pool)| pool_info(id, &pool.lock()))
+        .collect();
+    HttpResponse::Ok().json(pools)
+}
+
+fn default_plane() -> f64 {
+    1800.0
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreatePoolReq {
+    /// Letters, digits, `-` and `_`; also the pool's directory name.
+    id: String,
+    token_names: Vec<String>,
+    /// Reserves of the pool's first tick.
+    initial_reserves: Vec<f64>,
+    #[serde(default = "default_plane")]
+    initial_plane: f64,
+    /// Derive the first tick's plane from this depeg price instead.
+    depeg_price: Option<f64>,
+    #[serde(default)]
+    fee_bps: u32,
+}
+
+/// Create a pool with a single tick, stored in `pools/<id>/`.
+#[utoipa::path(
+    request_body = CreatePoolReq,
+    responses(
+        (status = 200, body = PoolInfo),
+        (status = 400, body = ErrorResponse),
+        (status = 409, description = "Pool exists", body = ErrorResponse)
+    )
+)]
+#[post("/api/pools")]
+async fn create_pool(
+    registry: web::Data<Registry>,
+    json: web::Json<CreatePoolReq>
 ) -> impl Responder {
-    let state = match get_amm_safe(&amm) {
-        Ok(guard) => guard,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({"error": e}));
-        }
+    if json.token_names.is_empty() {
+        return bad_request("At least one token is required");
+    }
+
+    let op = Operation::AddTick {
+        plane: Some(json.initial_plane),
+        depeg_price: json.depeg_price,
+        reserves: json.initial_reserves.clone(),
+        fee_bps: json.fee_bps,
     };
+    let created = registry.create(&json.id, json.token_names.clone(), |amm| {
+        amm.commit(op).map(|_| ())
+    });
+    match created {
+        Ok(pool) => HttpResponse::Ok().json(pool_info(&json.id, &pool.lock())),
+        Err(e) => error_response(&e),
+    }
+}
 
-    let from = match query.get("from") {
-        Some(f) => f,
-        None => {
-            return HttpResponse::BadRequest().json(
-                serde_json::json!({"error": "Missing from parameter"})
-            );
+/// Stop serving a pool and delete its state file and journal.
+#[utoipa::path(
+    params(("id" = String, Path, description = "Pool id")),
+    responses(
+        (status = 200, body = MessageResponse),
+        (status = 400, description = "The default pool cannot be deleted", body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+#[delete("/api/pools/{id}")]
+async fn delete_pool(registry: web::Data<Registry>, id: web::Path<String>) -> impl Responder {
+    match registry.delete(&id) {
+        Ok(()) => message_response(format!("Deleted pool '{}'", id)),
+        Err(e) => error_response(&e),
+    }
+}
+
+fn default_max_hops() -> usize {
+    DEFAULT_MAX_HOPS
+}
+
+fn default_max_paths() -> usize {
+    1
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RouteReq {
+    from: String,
+    to: String,
+    amount: f64,
+    /// Pools to route through; every pool when absent.
+    pools: Option<Vec<String>>,
+    /// Longest path to consider, at most 4.
+    #[serde(default = "default_max_hops")]
+    max_hops: usize,
+    /// Split across up to this many paths that share no pool.
+    #[serde(default = "default_max_paths")]
+    max_paths: usize,
+    /// Execute the trades instead of only quoting them.
+    #[serde(default)]
+    execute: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+struct RouteResponse {
+    executed: bool,
+    quote: MultiHopQuote,
+}
+
+/// Route a trade through several pools, hopping over intermediate tokens.
+#[utoipa::path(
+    request_body = RouteReq,
+    responses(
+        (status = 200, body = RouteResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+#[post("/api/route")]
+async fn post_route(registry: web::Data<Registry>, json: web::Json<RouteReq>) -> impl Responder {
+    let mut pools: Vec<(String, Arc<Pool>)> = Vec::new();
+    let listed = match &json.pools {
+        Some(ids) => {
+            let found: Result<Vec<_>, _> = ids
+                .iter()
+                .map(|id| registry.get(id).map(|pool| (id.clone(), pool)))
+                .collect();
+            match found {
+                Ok(found) => found,
+                Err(e) => {
+                    return error_response(&e);
+                }
+            }
         }
+        None => registry.list(),
     };
+    // The default pool can also be listed under its directory name.
+    for (id, pool) in listed {
+        if !pools.iter().any(|(_, p)| Arc::ptr_eq(p, &pool)) {
+            pools.push((id, pool));
+        }
+    }
+    pools.sort_by(|a, b| a.0.cmp(&b.0));
 
-    let to = match query.get("to") {
-        Some(t) => t,
-        None => {
-            return HttpResponse::BadRequest().json(
-                serde_json::json!({"error": "Missing to parameter"})
-            );
+    let mut guards: Vec<_> = pools
+        .iter()
+        .map(|(_, pool)| pool.lock())
+        .collect();
+    let mut router = Router::new(
+        pools
+            .iter()
+            .zip(&guards)
+            .map(|((id, _), amm)| (id.as_str(), &**amm))
+    );
+    router.max_hops = json.max_hops;
+    let checked = router
+        .quote(&json.from, &json.to, json.amount, json.max_paths)
+        .and_then(|quote| {
+            if json.execute {
+                router.check_execution(&quote)?;
+            }
+            Ok(quote)
+        });
+    let quote = match checked {
+        Ok(quote) => quote,
+        Err(e) => {
+            return error_response(&e);
         }
     };
 
-    match state.get_aggregated_price(from, to) {
-        Ok(price) =>
-            HttpResponse::Ok().json(
-                serde_json::json!({
-            "price": price,
-            "from": from,
-            "to": to
-        })
-            ),
-        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({"error": e})),
+    if json.execute {
+        // Every hop was checked above against the locked pools, so a commit
+        // only fails on I/O, and then the hops before it are undone.
+        let mut targets: Vec<(&str, &mut MultiTickAMM)> = pools
+            .iter()
+            .zip(guards.iter_mut())
+            .map(|((id, _), amm)| (id.as_str(), &mut **amm))
+            .collect();
+        let executed = quote.execute(&mut targets, |id, amm, op| {
+            let live = &pools
+                .iter()
+                .find(|(pool_id, _)| pool_id == id)
+                .expect("target pools come from `pools`").1.live;
+            commit(amm, live, op)
+        });
+        if let Err(e) = executed {
+            return error_response(&e);
+        }
     }
+    HttpResponse::Ok().json(RouteResponse { executed: json.execute, quote })
 }
 
-#[post("/api/reset")]
-async fn reset_state(amm: web::Data<Mutex<MultiTickAMM>>) -> impl Responder {
-    let mut amm_guard = match get_amm_safe(&amm) {
-        Ok(guard) => guard,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({"error": e}));
+/// This document.
+#[utoipa::path(responses((status = 200, description = "OpenAPI 3 document")))]
+#[get("/api/openapi.json")]
+async fn get_openapi() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::MessageBody;
+    use actix_web::dev::{ Service, ServiceResponse };
+    use actix_web::http::StatusCode;
+    use actix_web::test::{ call_service, init_service, read_body_json, TestRequest };
+    use serde_json::{ json, Value };
+
+    /// Registry over `default` with named pools under a fresh temp directory.
+    fn registry(name: &str, default: MultiTickAMM) -> (PathBuf, web::Data<Registry>) {
+        let root = crate::testing::temp_dir(name);
+        let default = default.with_state_path(root.join("main.json"));
+        (root.clone(), web::Data::new(Registry::load(default, root.join("pools")).unwrap()))
+    }
+
+    async fn call<S, R, B>(app: &S, req: R) -> (StatusCode, Value)
+        where S: Service<R, Response = ServiceResponse<B>, Error = actix_web::Error>, B: MessageBody
+    {
+        let response = call_service(app, req).await;
+        let status = response.status();
+        (status, read_body_json(response).await)
+    }
+
+    #[test]
+    fn test_openapi_covers_the_api() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = doc["paths"].as_object().unwrap();
+        for path in [
+            "/api/pools",
+            "/api/pools/{id}",
+            "/api/pools/{id}/state",
+            "/api/pools/{id}/trade",
+            "/api/pools/{id}/restore/{name}",
+            "/api/route",
+            "/api/pools/{id}/arbitrage",
+            "/api/pools/{id}/lp/{lp_id}",
+        ] {
+            assert!(paths.contains_key(path), "missing {}", path);
         }
-    };
+        assert!(paths["/api/pools/{id}/trade"]["post"]["requestBody"].is_object());
+        let schemas = doc["components"]["schemas"].as_object().unwrap();
+        for schema in [
+            "TradeReq",
+            "TickReq",
+            "AddLiquidityReq",
+            "RemoveLiquidityReq",
+            "SetReservesReq",
+            "ReconfigureReq",
+            "StateResponse",
+            "TickInfo",
+            "PriceInfo",
+            "RouteQuote",
+            "ErrorResponse",
+            "CreatePoolReq",
+            "PoolInfo",
+            "MultiHopQuote",
+            "ArbPlan",
+            "LpReport",
+        ] {
+            assert!(schemas.contains_key(schema), "missing schema {}", schema);
+        }
+    }
 
-    let token_names = amm_guard.token_names.clone();
+    #[actix_web::test]
+    async fn test_pool_routes() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let (root, registry) = registry("routes", MultiTickAMM::new(names));
+        let app = init_service(App::new().app_data(registry).configure(api_routes)).await;
+        let call = |req: TestRequest| call(&app, req.to_request());
+        let create = |id: &str, fee_bps: u32| {
+            TestRequest::post().uri("/api/pools").set_json(
+                json!({
+                    "id": id,
+                    "token_names": ["USDC", "USDT"],
+                    "initial_reserves": [100.0, 100.0],
+                    "initial_plane": 150.0,
+                    "fee_bps": fee_bps,
+                })
+            )
+        };
+        let trade = |uri: &str| {
+            TestRequest::post()
+                .uri(uri)
+                .set_json(json!({ "from": "USDC", "to": "USDT", "amount": 10.0 }))
+        };
+        let state = |uri: &str| TestRequest::get().uri(uri);
+        let post = |uri: &str| TestRequest::post().uri(uri);
+        let delete = |uri: &str| TestRequest::delete().uri(uri);
+
+        let (status, body) = call(create("wide", 10_000)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_fee");
+        let (status, _) = call(state("/api/pools/wide/state")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, body) = call(create("default", 0)).await;
+        assert_eq!(body["code"], "invalid_pool_name", "{}", status);
+        let (status, _) = call(create("wide", 30)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Checkpoint, trade and restore through the scoped routes.
+        let (_, before) = call(state("/api/pools/wide/state")).await;
+        let (status, _) = call(post("/api/pools/wide/checkpoint/base")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(trade("/api/pools/wide/trade")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, traded) = call(state("/api/pools/wide/state")).await;
+        assert_ne!(traded["global_reserves"], before["global_reserves"]);
+        let (status, _) = call(post("/api/pools/wide/restore/base")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, restored) = call(state("/api/pools/wide/state")).await;
+        assert_eq!(restored["global_reserves"], before["global_reserves"]);
+
+        // The unscoped routes act on the default pool, which has no tick yet.
+        let (_, default) = call(state("/api/state")).await;
+        assert_eq!(default["tick_count"], 0);
+        let (status, _) = call(trade("/api/trade")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = call(delete("/api/pools/default")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "default_pool");
+        let (status, _) = call(delete("/api/pools/wide")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(state("/api/pools/wide/state")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        std::fs::remove_dir_all(root).unwrap();
+    }
 
-    // Reset to fresh state
-    *amm_guard = MultiTickAMM::new(token_names.clone());
+    #[actix_web::test]
+    async fn test_lp_route_reports_fee_income() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut amm = MultiTickAMM::new(names);
+        amm.add_tick_with_fee(150.0, vec![100.0, 100.0], 30).unwrap();
+        let (root, registry) = registry("lp-route", amm);
+        let app = init_service(App::new().app_data(registry).configure(api_routes)).await;
+
+        let get = |uri: &str| TestRequest::get().uri(uri).to_request();
+        let trade = json!({ "from": "USDC", "to": "USDT", "amount": 10.0 });
+        let request = TestRequest::post().uri("/api/trade").set_json(trade).to_request();
+        let (status, _) = call(&app, request).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = call(&app, get("/api/lp/creator")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["fee_income"].as_f64().unwrap() > 0.0);
+        assert_eq!(body["fee_income"], body["fee_value"]);
+        assert_eq!(body["positions"][0]["fee_income"], body["fee_income"]);
+
+        let (status, body) = call(&app, get("/api/lp/bob")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "unknown_lp");
+        std::fs::remove_dir_all(root).unwrap();
+    }
 
-    // Add default tick
-    let default_reserves = vec![1000.0; token_names.len()];
-    amm_guard.add_tick(600.0, default_reserves);
-    amm_guard.save_state();
+    #[actix_web::test]
+    async fn test_route_rejects_too_many_hops() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut amm = MultiTickAMM::new(names);
+        amm.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        let (root, registry) = registry("route-hops", amm);
+        let app = init_service(App::new().app_data(registry).configure(api_routes)).await;
+        let route = |max_hops: usize| {
+            let body = json!({ "from": "USDC", "to": "USDT", "amount": 1.0, "max_hops": max_hops });
+            TestRequest::post().uri("/api/route").set_json(body).to_request()
+        };
 
-    HttpResponse::Ok().json(
-        serde_json::json!({
-        "success": true,
-        "message": "AMM state reset with default tick"
-    })
-    )
+        let (status, _) = call(&app, route(crate::router::MAX_HOPS)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = call(&app, route(1_000)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "too_many_hops");
+        // Quotes write nothing, so there may be nothing to clean up.
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[actix_web::test]
+    async fn test_trade_maps_storage_failures_to_500() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut amm = MultiTickAMM::new(names);
+        amm.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        let (root, registry) = registry("trade-errors", amm.clone());
+        let app = init_service(App::new().app_data(registry).configure(api_routes)).await;
+        let trade = |to: &str| {
+            let body = json!({ "from": "USDC", "to": to, "amount": 1.0 });
+            TestRequest::post().uri("/api/trade").set_json(body).to_request()
+        };
+
+        let (status, body) = call(&app, trade("DAI")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["success"], false);
+        assert_eq!(body["code"], "unknown_token");
+
+        // A journal that cannot be written is the server's fault, not the
+        // caller's.
+        let journal = amm.with_state_path(root.join("main.json")).journal_path();
+        std::fs::create_dir_all(&journal).unwrap();
+        let (status, body) = call(&app, trade("USDT")).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "io_error");
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_arbitrage_rejects_bad_prices_without_trading() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut amm = MultiTickAMM::new(names);
+        amm.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        let (root, registry) = registry("arbitrage", amm);
+        let app = init_service(App::new().app_data(registry).configure(api_routes)).await;
+        let arbitrage = |prices: &[f64]| {
+            let body = json!({ "prices": prices, "execute": true });
+            TestRequest::post().uri("/api/arbitrage").set_json(body).to_request()
+        };
+        let state = || TestRequest::get().uri("/api/state").to_request();
+        let (_, before) = call(&app, state()).await;
+
+        let (status, body) = call(&app, arbitrage(&[1.0, 0.0])).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_price");
+        let (status, body) = call(&app, arbitrage(&[1.0])).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "length_mismatch");
+        let (_, after) = call(&app, state()).await;
+        assert_eq!(after["global_reserves"], before["global_reserves"]);
+        // Nothing was committed, so there may be nothing to clean up.
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[actix_web::test]
+    async fn test_lp_route_rejects_bad_prices() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut amm = MultiTickAMM::new(names);
+        amm.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        let (root, registry) = registry("lp-prices", amm);
+        let app = init_service(App::new().app_data(registry).configure(api_routes)).await;
+        let get = |prices: &str| {
+            let uri = format!("/api/lp/creator?prices={}", prices);
+            TestRequest::get().uri(&uri).to_request()
+        };
+
+        let (status, _) = call(&app, get("1,0.99")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = call(&app, get("1,x")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "Invalid prices '1,x'");
+        let (status, body) = call(&app, get("1,0")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_price");
+        let (status, body) = call(&app, get("1")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "length_mismatch");
+        // Reports write nothing, so there may be nothing to clean up.
+        let _ = std::fs::remove_dir_all(root);
+    }
 }
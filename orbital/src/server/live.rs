@@ -0,0 +1,191 @@
+//! Server-Sent Events feed for `GET /api/pools/{id}/stream`, one per pool.
+//!
+//! Every subscriber first gets a `snapshot` event with the full state and
+//! prices. After that each committed operation produces, in order:
+//!
+//! * `trade` (`from`, `to`, `amount_in`, `amount_out`) for trades, or
+//!   `operation` (`op`, `params`, `output`) for everything else;
+//! * `state` with the fields of `/api/state` that changed, `ticks` holding
+//!   only the changed ticks and `tick_count` the new number of ticks;
+//! * `prices` with the full `/api/prices` list, if any price moved.
+//!
+//! A subscriber that falls too far behind gets a fresh `snapshot` instead of
+//! the events it missed.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::web::Bytes;
+use futures_util::{ stream, Stream };
+use serde::Serialize;
+use serde_json::{ json, Map, Value };
+use tokio::sync::broadcast::{ self, error::RecvError };
+
+use super::registry::Pool;
+use super::{ price_infos, state_response };
+use crate::journal::Operation;
+use crate::ticks::MultiTickAMM;
+
+/// Events buffered per subscriber before it is considered lagging.
+const CAPACITY: usize = 256;
+/// Comment line sent to idle subscribers so proxies keep the connection open.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+fn frame(event: &str, data: &impl Serialize) -> Bytes {
+    let data = serde_json::to_string(data).unwrap_or_else(|_| "null".into());
+    Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+fn snapshot(amm: &MultiTickAMM) -> Bytes {
+    frame("snapshot", &json!({ "state": state_response(amm), "prices": price_infos(amm) }))
+}
+
+/// Fields of the `/api/state` body that differ between `before` and `after`.
+fn state_diff(before: &MultiTickAMM, after: &MultiTickAMM) -> Map<String, Value> {
+    let to_map = |amm: &MultiTickAMM| match serde_json::to_value(state_response(amm)) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    };
+    let (old, mut new) = (to_map(before), to_map(after));
+    let mut diff = Map::new();
+    if let (Some(Value::Array(old_ticks)), Some(Value::Array(new_ticks))) = (
+        old.get("ticks"),
+        new.remove("ticks"),
+    ) {
+        let changed: Vec<Value> = new_ticks
+            .into_iter()
+            .enumerate()
+            .filter(|(i, tick)| old_ticks.get(*i) != Some(tick))
+            .map(|(_, tick)| tick)
+            .collect();
+        if !changed.is_empty() {
+            diff.insert("ticks".into(), Value::Array(changed));
+        }
+    }
+    for (key, value) in new {
+        if old.get(&key) != Some(&value) {
+            diff.insert(key, value);
+        }
+    }
+    if !diff.is_empty() {
+        diff.insert("tick_count".into(), after.ticks.len().into());
+    }
+    diff
+}
+
+/// Broadcasts pool changes to every open `/api/stream` connection.
+pub struct LiveFeed {
+    tx: broadcast::Sender<Bytes>,
+}
+
+impl Default for LiveFeed {
+    fn default() -> Self {
+        LiveFeed { tx: broadcast::channel(CAPACITY).0 }
+    }
+}
+
+impl LiveFeed {
+    /// Publish the events for `op`, which turned `before` into `after`.
+    pub fn publish(
+        &self,
+        op: &Operation,
+        output: &Value,
+        before: &MultiTickAMM,
+        after: &MultiTickAMM
+    ) {
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+        self.send_operation(op, output);
+        let diff = state_diff(before, after);
+        if !diff.is_empty() {
+            let _ = self.tx.send(frame("state", &diff));
+        }
+        let prices = serde_json::to_value(price_infos(after)).unwrap_or(Value::Null);
+        if serde_json::to_value(price_infos(before)).ok().as_ref() != Some(&prices) {
+            let _ = self.tx.send(frame("prices", &prices));
+        }
+    }
+
+    /// Send the event for `op` itself; a batch sends one per operation.
+    fn send_operation(&self, op: &Operation, output: &Value) {
+        let event = match op {
+            Operation::Trade { from, to, .. } =>
+                frame(
+                    "trade",
+                    &json!({
+                    "from": from,
+                    "to": to,
+                    "amount_in": output["amount_in"],
+                    "amount_out": output["amount_out"],
+                })
+                ),
+            Operation::Batch { ops } => {
+                for (i, op) in ops.iter().enumerate() {
+                    self.send_operation(op, &output[i]);
+                }
+                return;
+            }
+            _ => {
+                let (name, params) = op.summary();
+                frame("operation", &json!({ "op": name, "params": params, "output": output }))
+            }
+        };
+        let _ = self.tx.send(event);
+    }
+}
+
+/// Event stream for a new subscriber to `pool`, starting with a snapshot.
+pub fn subscribe(pool: Arc<Pool>) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    // Subscribe while holding the lock so no event falls between the
+    // snapshot and the first message received.
+    let (first, rx) = {
+        let guard = pool.lock();
+        (Some(snapshot(&guard)), pool.live.tx.subscribe())
+    };
+    stream::unfold((first, rx, pool), |(pending, mut rx, pool)| async move {
+        if let Some(bytes) = pending {
+            return Some((Ok(bytes), (None, rx, pool)));
+        }
+        let bytes = tokio::select! {
+            received = rx.recv() => match received {
+                Ok(bytes) => bytes,
+                Err(RecvError::Lagged(_)) => snapshot(&pool.lock()),
+                Err(RecvError::Closed) => {
+                    return None;
+                }
+            },
+            _ = tokio::time::sleep(KEEP_ALIVE) => Bytes::from_static(b": keep-alive\n\n"),
+        };
+        Some((Ok(bytes), (None, rx, pool)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_diff_lists_changed_ticks() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut before = MultiTickAMM::new(names);
+        before.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        before.add_tick(200.0, vec![100.0, 100.0]).unwrap();
+        assert!(state_diff(&before, &before).is_empty());
+
+        let mut after = before.clone();
+        after.ticks[1].sphere_amm.reserves = vec![90.0, 110.0];
+        after.recompute_global_reserves();
+        let diff = state_diff(&before, &after);
+        let ticks = diff["ticks"].as_array().unwrap();
+        assert_eq!(ticks.len(), 1);
+        assert_eq!(ticks[0]["index"], 1);
+        assert_eq!(diff["tick_count"], 2);
+        assert!(diff.contains_key("global_reserves"));
+        assert!(!diff.contains_key("token_names"));
+
+        after.remove_tick(0).unwrap();
+        let diff = state_diff(&before, &after);
+        assert_eq!(diff["tick_count"], 1);
+    }
+}
@@ -0,0 +1,261 @@
+//! Pools served by one server, keyed by id.
+//!
+//! The pool the server was started with is `default` and keeps its state file;
+//! every other pool lives in `pools/<id>/multi_tick.json`, the same layout the
+//! CLI's `--pool` flag uses, so both can work on the same pools.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::future::{ ready, Ready };
+use std::ops::Deref;
+use std::path::{ Path, PathBuf };
+use std::sync::{ Arc, Mutex, MutexGuard, RwLock };
+
+use actix_web::{ dev::Payload, error::InternalError, web, FromRequest, HttpRequest };
+
+use super::error_response;
+use super::live::LiveFeed;
+use crate::error::OrbitalError;
+use crate::journal;
+use crate::persist::{ validate_pool_name, DEFAULT_POOL };
+use crate::ticks::{ MultiTickAMM, DEFAULT_STATE_FILE };
+
+/// A pool and the feed its changes are published on.
+pub struct Pool {
+    amm: Mutex<MultiTickAMM>,
+    pub live: LiveFeed,
+}
+
+impl Pool {
+    fn new(amm: MultiTickAMM) -> Arc<Self> {
+        Arc::new(Pool { amm: Mutex::new(amm), live: LiveFeed::default() })
+    }
+
+    /// Lock the pool, recovering from a poisoned mutex.
+    pub fn lock(&self) -> MutexGuard<'_, MultiTickAMM> {
+        match self.amm.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                eprintln!("Mutex was poisoned, recovering...");
+                poisoned.into_inner()
+            }
+        }
+    }
+}
+
+pub struct Registry {
+    root: PathBuf,
+    pools: RwLock<BTreeMap<String, Arc<Pool>>>,
+}
+
+fn read<T>(lock: &RwLock<T>) -> std::sync::RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> std::sync::RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Registry {
+    /// Registry with `default` as the default pool plus every pool already
+    /// stored under `root`. A pool under `root` that is the default pool's own
+    /// file is the same pool under both ids.
+    pub fn load(default: MultiTickAMM, root: impl Into<PathBuf>) -> Result<Self, OrbitalError> {
+        let root = root.into();
+        let default_path = default.state_path().to_path_buf();
+        let default = Pool::new(default);
+        let mut pools = BTreeMap::new();
+        pools.insert(DEFAULT_POOL.to_string(), default.clone());
+        if let Ok(entries) = fs::read_dir(&root) {
+            for entry in entries.flatten() {
+                let id = entry.file_name().to_string_lossy().to_string();
+                let path = entry.path().join(DEFAULT_STATE_FILE);
+                if validate_pool_name(&id).is_err() || !path.exists() {
+                    continue;
+                }
+                let pool = if same_file(&path, &default_path) {
+                    default.clone()
+                } else {
+                    Pool::new(MultiTickAMM::load_state(&path, Vec::new())?)
+                };
+                pools.insert(id, pool);
+            }
+        }
+        Ok(Registry { root, pools: RwLock::new(pools) })
+    }
+
+    pub fn get(&self, id: &str) -> Result<Arc<Pool>, OrbitalError> {
+        read(&self.pools)
+            .get(id)
+            .cloned()
+            .ok_or_else(|| OrbitalError::UnknownPool(id.to_string()))
+    }
+
+    /// Ids and pools in id order.
+    pub fn list(&self) -> Vec<(String, Arc<Pool>)> {
+        read(&self.pools)
+            .iter()
+            .map(|(id, pool)| (id.clone(), pool.clone()))
+            .collect()
+    }
+
+    /// Register a new pool stored in `root/<id>/`; `init` sets it up and has
+    /// its changes journaled there.
+    pub fn create(
+        &self,
+        id: &str,
+        token_names: Vec<String>,
+        init: impl FnOnce(&mut MultiTickAMM) -> Result<(), OrbitalError>
+    ) -> Result<Arc<Pool>, OrbitalError> {
+        validate_pool_name(id)?;
+        let mut pools = write(&self.pools);
+        let path = self.root.join(id).join(DEFAULT_STATE_FILE);
+        if pools.contains_key(id) || path.exists() {
+            return Err(OrbitalError::PoolExists(id.to_string()));
+        }
+        let mut amm = MultiTickAMM::new(token_names).with_state_path(&path);
+        init(&mut amm)?;
+        if !path.exists() {
+            amm.save_state()?;
+        }
+        let pool = Pool::new(amm);
+        pools.insert(id.to_string(), pool.clone());
+        Ok(pool)
+    }
+
+    /// Unregister a pool and delete its state file and journals. The default
+    /// pool cannot be deleted, nor can another id that serves its file.
+    pub fn delete(&self, id: &str) -> Result<(), OrbitalError> {
+        let mut pools = write(&self.pools);
+        let pool = pools.get(id).ok_or_else(|| OrbitalError::UnknownPool(id.to_string()))?;
+        if let Some(default) = pools.get(DEFAULT_POOL) {
+            let is_default =
+                Arc::ptr_eq(pool, default) ||
+                same_file(pool.lock().state_path(), default.lock().state_path());
+            if is_default {
+                return Err(OrbitalError::DefaultPool(id.to_string()));
+            }
+        }
+        let pool = pools.remove(id).expect("pool checked above");
+        drop(pools);
+        let amm = pool.lock();
+        let io = |path: &Path, e: std::io::Error| {
+            OrbitalError::Io(format!("{}: {}", path.display(), e))
+        };
+        let journal = amm.journal_path();
+        let mut paths = vec![amm.state_path().to_path_buf()];
+        paths.extend(journal::archived_journals(&journal));
+        paths.push(journal);
+        for path in paths {
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(io(&path, e));
+                }
+                _ => {}
+            }
+        }
+        if let Some(dir) = amm.state_path().parent() {
+            // Only succeeds if nothing else was stored next to the pool.
+            let _ = fs::remove_dir(dir);
+        }
+        Ok(())
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Extracts the pool named by the `{id}` path segment, or the default pool on
+/// the unscoped `/api/...` routes.
+pub struct PoolRef(pub Arc<Pool>);
+
+impl Deref for PoolRef {
+    type Target = Pool;
+
+    fn deref(&self) -> &Pool {
+        &self.0
+    }
+}
+
+impl FromRequest for PoolRef {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let id = req.match_info().get("id").unwrap_or(DEFAULT_POOL);
+        let pool = match req.app_data::<web::Data<Registry>>() {
+            Some(registry) => registry.get(id),
+            None => Err(OrbitalError::UnknownPool(id.to_string())),
+        };
+        ready(
+            pool
+                .map(PoolRef)
+                .map_err(|e| {
+                    let response = error_response(&e);
+                    InternalError::from_response(e, response).into()
+                })
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::Operation;
+
+    #[test]
+    fn test_create_list_and_delete_pools() {
+        let root = crate::testing::temp_dir("registry");
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let default = MultiTickAMM::new(names.clone()).with_state_path(root.join("main.json"));
+        let registry = Registry::load(default, &root).unwrap();
+
+        let add_tick = |amm: &mut MultiTickAMM| {
+            let op = Operation::AddTick {
+                plane: Some(150.0),
+                depeg_price: None,
+                reserves: vec![100.0, 100.0],
+                fee_bps: 0,
+            };
+            amm.commit(op).map(|_| ())
+        };
+        registry.create("wide", names.clone(), add_tick).unwrap();
+        let err = registry.create("wide", names.clone(), |_| Ok(())).err().unwrap();
+        assert_eq!(err, OrbitalError::PoolExists("wide".into()));
+        let err = registry.create("../x", names.clone(), |_| Ok(())).err().unwrap();
+        assert_eq!(err.code(), "invalid_pool_name");
+        let ids: Vec<String> = registry
+            .list()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, ["default", "wide"]);
+
+        // Pools on disk are picked up again by a new registry.
+        let default = MultiTickAMM::new(names).with_state_path(root.join("main.json"));
+        let reloaded = Registry::load(default, &root).unwrap();
+        assert_eq!(reloaded.get("wide").unwrap().lock().ticks.len(), 1);
+
+        reloaded.delete("wide").unwrap();
+        assert!(!root.join("wide").exists());
+        assert_eq!(reloaded.get("wide").err(), Some(OrbitalError::UnknownPool("wide".into())));
+        let err = reloaded.delete(DEFAULT_POOL).unwrap_err();
+        assert_eq!(err, OrbitalError::DefaultPool(DEFAULT_POOL.into()));
+
+        // A pool directory holding the default pool's own file is an alias
+        // and cannot be deleted either.
+        let alias = root.join("alias").join(DEFAULT_STATE_FILE);
+        let default = MultiTickAMM::new(vec!["USDC".into()]).with_state_path(&alias);
+        default.save_state().unwrap();
+        let aliased = Registry::load(default, &root).unwrap();
+        assert!(Arc::ptr_eq(&aliased.get("alias").unwrap(), &aliased.get(DEFAULT_POOL).unwrap()));
+        let err = aliased.delete("alias").unwrap_err();
+        assert_eq!(err, OrbitalError::DefaultPool("alias".into()));
+        assert!(alias.exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,593 @@
+//! Agent-based market simulation over a `MultiTickAMM`.
+//!
+//! A simulation runs a copy of a pool through a number of steps. Each step the
+//! external market moves (a log-normal random walk per token), then every
+//! agent in turn looks at the pool and the market and submits operations,
+//! which are applied like journaled ones but never written to disk. The pool
+//! state after each step is recorded as a [`StepRecord`].
+//!
+//! All randomness comes from one [`Rng`] seeded from the config, so a run is
+//! fully reproducible from its config and starting pool.
+
+use serde::{ Deserialize, Serialize };
+
+use crate::arbitrage;
+use crate::error::OrbitalError;
+use crate::journal::Operation;
+use crate::ticks::MultiTickAMM;
+
+/// SplitMix64 generator. Small and fast, and its output is fixed by the seed
+/// alone, independent of any dependency's version.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) as f64) / ((1u64 << 53) as f64)
+    }
+
+    /// Uniform index in `0..n`; `n` must be positive.
+    pub fn index(&mut self, n: usize) -> usize {
+        (self.next_f64() * (n as f64)) as usize % n
+    }
+
+    /// Standard normal sample (Box–Muller).
+    pub fn normal(&mut self) -> f64 {
+        let u1 = 1.0 - self.next_f64();
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
+
+/// Reference prices outside the pool, in units of a common numeraire.
+#[derive(Clone, Debug)]
+pub struct Market {
+    pub prices: Vec<f64>,
+    /// Standard deviation of each price's log return per step.
+    pub volatility: f64,
+}
+
+impl Market {
+    fn step(&mut self, rng: &mut Rng) {
+        for price in &mut self.prices {
+            *price *= (self.volatility * rng.normal()).exp();
+        }
+    }
+}
+
+/// A market participant. Agents only propose operations; the engine applies
+/// them in order and an operation that fails is counted and skipped.
+pub trait Agent {
+    fn act(&mut self, amm: &MultiTickAMM, market: &Market, rng: &mut Rng) -> Vec<Operation>;
+
+    /// Called with each of this agent's operations after it was applied.
+    fn applied(&mut self, _op: &Operation) {}
+}
+
+/// Trades a random amount between a random pair of tokens.
+pub struct NoiseTrader {
+    pub max_amount: f64,
+    pub trades_per_step: usize,
+}
+
+impl Agent for NoiseTrader {
+    fn act(&mut self, amm: &MultiTickAMM, _: &Market, rng: &mut Rng) -> Vec<Operation> {
+        let n = amm.token_names.len();
+        if n < 2 {
+            return Vec::new();
+        }
+        (0..self.trades_per_step)
+            .map(|_| {
+                let from = rng.index(n);
+                let to = (from + 1 + rng.index(n - 1)) % n;
+                Operation::Trade {
+                    from: amm.token_names[from].clone(),
+                    to: amm.token_names[to].clone(),
+                    amount: rng.next_f64() * self.max_amount,
+                    exact_out: false,
+                    max_amount_in: None,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Trades the most mispriced pair back towards the external market, sizing
+/// the trade to maximise its profit at market prices.
+pub struct Arbitrageur {
+    pub max_amount: f64,
+    /// Minimum relative mispricing worth acting on.
+    pub threshold: f64,
+}
+
+impl Agent for Arbitrageur {
+    fn act(&mut self, amm: &MultiTickAMM, market: &Market, _: &mut Rng) -> Vec<Operation> {
+        // Pool price of `to` in `from` against the market's; buying `to` in
+        // the pool pays off when it is cheaper there.
+        let mut best: Option<(usize, usize, f64)> = None;
+        for from in 0..amm.token_names.len() {
+            for to in 0..amm.token_names.len() {
+                let (a, b) = (&amm.token_names[from], &amm.token_names[to]);
+                if from == to {
+                    continue;
+                }
+                let Ok(pool_price) = amm.get_aggregated_price(a, b) else {
+                    continue;
+                };
+                let gap = market.prices[to] / market.prices[from] / pool_price - 1.0;
+                if gap > self.threshold && best.is_none_or(|(_, _, g)| gap > g) {
+                    best = Some((from, to, gap));
+                }
+            }
+        }
+        let Some((from, to, _)) = best else {
+            return Vec::new();
+        };
+        arbitrage::optimal_trade(amm, from, to, &market.prices, self.max_amount)
+            .map(|trade| trade.operation())
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Enters a tick with a deposit in proportion to its reserves, and later
+/// withdraws it completely, each with a fixed probability per step.
+pub struct LiquidityProvider {
+    pub id: String,
+    pub tick_index: usize,
+    /// Total deposit, summed over tokens.
+    pub amount: f64,
+    pub enter_probability: f64,
+    pub exit_probability: f64,
+    active: bool,
+}
+
+impl LiquidityProvider {
+    pub fn new(id: String, tick_index: usize, amount: f64, enter: f64, exit: f64) -> Self {
+        LiquidityProvider {
+            id,
+            tick_index,
+            amount,
+            enter_probability: enter,
+            exit_probability: exit,
+            active: false,
+        }
+    }
+}
+
+impl Agent for LiquidityProvider {
+    fn act(&mut self, amm: &MultiTickAMM, _: &Market, rng: &mut Rng) -> Vec<Operation> {
+        let Some(tick) = amm.ticks.get(self.tick_index) else {
+            return Vec::new();
+        };
+        if self.active {
+            if rng.next_f64() >= self.exit_probability {
+                return Vec::new();
+            }
+            return vec![Operation::RemoveLiquidity {
+                tick_index: self.tick_index,
+                lp_id: self.id.clone(),
+                percentage: 1.0,
+            }];
+        }
+        let total = tick.liquidity();
+        if rng.next_f64() >= self.enter_probability || total <= 0.0 {
+            return Vec::new();
+        }
+        let amounts = tick.sphere_amm.reserves
+            .iter()
+            .map(|r| (r / total) * self.amount)
+            .collect();
+        vec![Operation::AddLiquidity {
+            tick_index: self.tick_index,
+            lp_id: self.id.clone(),
+            amounts,
+        }]
+    }
+
+    fn applied(&mut self, op: &Operation) {
+        match op {
+            Operation::AddLiquidity { .. } => {
+                self.active = true;
+            }
+            Operation::RemoveLiquidity { .. } => {
+                self.active = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Serializable description of an agent, as found in a simulation config.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AgentConfig {
+    Noise {
+        max_amount: f64,
+        #[serde(default = "one")]
+        trades_per_step: usize,
+    },
+    Arbitrageur {
+        max_amount: f64,
+        #[serde(default)]
+        threshold: f64,
+    },
+    Lp {
+        id: String,
+        #[serde(default)]
+        tick_index: usize,
+        amount: f64,
+        enter_probability: f64,
+        exit_probability: f64,
+    },
+}
+
+fn one() -> usize {
+    1
+}
+
+impl AgentConfig {
+    pub fn build(&self) -> Box<dyn Agent> {
+        match self.clone() {
+            AgentConfig::Noise { max_amount, trades_per_step } => {
+                Box::new(NoiseTrader { max_amount, trades_per_step })
+            }
+            AgentConfig::Arbitrageur { max_amount, threshold } => {
+                Box::new(Arbitrageur { max_amount, threshold })
+            }
+            AgentConfig::Lp { id, tick_index, amount, enter_probability, exit_probability } => {
+                Box::new(
+                    LiquidityProvider::new(
+                        id,
+                        tick_index,
+                        amount,
+                        enter_probability,
+                        exit_probability
+                    )
+                )
+            }
+        }
+    }
+}
+
+/// Everything that determines a run besides the starting pool.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SimConfig {
+    pub steps: usize,
+    pub seed: u64,
+    /// Starting external prices; all 1.0 when absent.
+    #[serde(default)]
+    pub initial_prices: Option<Vec<f64>>,
+    #[serde(default)]
+    pub volatility: f64,
+    pub agents: Vec<AgentConfig>,
+}
+
+impl Default for SimConfig {
+    /// A noise trader, an arbitrageur and an LP on tick 0 around a market
+    /// that drifts by 0.1% per step.
+    fn default() -> Self {
+        SimConfig {
+            steps: 100,
+            seed: 1,
+            initial_prices: None,
+            volatility: 0.001,
+            agents: vec![
+                AgentConfig::Noise { max_amount: 20.0, trades_per_step: 1 },
+                AgentConfig::Arbitrageur { max_amount: 200.0, threshold: 0.0005 },
+                AgentConfig::Lp {
+                    id: "sim-lp".into(),
+                    tick_index: 0,
+                    amount: 100.0,
+                    enter_probability: 0.1,
+                    exit_probability: 0.05,
+                }
+            ],
+        }
+    }
+}
+
+/// State of one tick after a step.
+#[derive(Clone, Debug, Serialize)]
+pub struct TickRecord {
+    pub reserves: Vec<f64>,
+    pub boundary: bool,
+    pub fees_accrued: Vec<f64>,
+}
+
+/// Pool and market after a step; step 0 is the starting state.
+#[derive(Clone, Debug, Serialize)]
+pub struct StepRecord {
+    pub step: usize,
+    pub reserves: Vec<f64>,
+    /// Pool price of each token in units of the first one.
+    pub prices: Vec<f64>,
+    pub external_prices: Vec<f64>,
+    /// Fees accrued per token over all ticks.
+    pub fees: Vec<f64>,
+    pub trades: usize,
+    /// Sum of trade inputs, valued at external prices.
+    pub volume: f64,
+    /// Operations that failed and were skipped.
+    pub failed: usize,
+    pub ticks: Vec<TickRecord>,
+}
+
+impl StepRecord {
+    fn new(step: usize, amm: &MultiTickAMM, market: &Market) -> Self {
+        let base = &amm.token_names[0];
+        StepRecord {
+            step,
+            reserves: amm.global_reserves.clone(),
+            prices: amm.token_names
+                .iter()
+                .map(|t| amm.get_aggregated_price(base, t).unwrap_or(f64::NAN))
+                .collect(),
+            external_prices: market.prices.clone(),
+            fees: amm.total_fees(),
+            trades: 0,
+            volume: 0.0,
+            failed: 0,
+            ticks: amm.ticks
+                .iter()
+                .map(|tick| TickRecord {
+                    reserves: tick.sphere_amm.reserves.clone(),
+                    boundary: tick.is_boundary(),
+                    fees_accrued: tick.fees_accrued.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Time series produced by [`simulate`].
+#[derive(Clone, Debug, Serialize)]
+pub struct Simulation {
+    pub token_names: Vec<String>,
+    pub seed: u64,
+    pub steps: Vec<StepRecord>,
+    /// The pool after the last step.
+    #[serde(skip)]
+    pub pool: MultiTickAMM,
+}
+
+/// Run `config` against a copy of `amm`.
+pub fn simulate(amm: &MultiTickAMM, config: &SimConfig) -> Result<Simulation, OrbitalError> {
+    let n = amm.token_names.len();
+    if n == 0 {
+        return Err(OrbitalError::LengthMismatch { expected: 1, got: 0 });
+    }
+    let prices = config.initial_prices.clone().unwrap_or_else(|| vec![1.0; n]);
+    if prices.len() != n {
+        return Err(OrbitalError::LengthMismatch { expected: n, got: prices.len() });
+    }
+    for agent in &config.agents {
+        if let AgentConfig::Lp { tick_index, .. } = agent {
+            if *tick_index >= amm.ticks.len() {
+                return Err(OrbitalError::UnknownTick(*tick_index));
+            }
+        }
+    }
+
+    let mut rng = Rng::new(config.seed);
+    let mut market = Market { prices, volatility: config.volatility };
+    let mut agents: Vec<Box<dyn Agent>> = config.agents
+        .iter()
+        .map(|a| a.build())
+        .collect();
+    let mut pool = amm.clone();
+    let mut steps = vec![StepRecord::new(0, &pool, &market)];
+
+    for step in 1..=config.steps {
+        market.step(&mut rng);
+        let (mut trades, mut volume, mut failed) = (0, 0.0, 0);
+        for agent in agents.iter_mut() {
+            for op in agent.act(&pool, &market, &mut rng) {
+                let mut next = pool.clone();
+                if op.apply(&mut next).is_err() {
+                    failed += 1;
+                    continue;
+                }
+                pool = next;
+                if let Operation::Trade { from, amount, .. } = &op {
+                    trades += 1;
+                    if let Some(i) = pool.token_names.iter().position(|t| t == from) {
+                        volume += amount * market.prices[i];
+                    }
+                }
+                agent.applied(&op);
+            }
+        }
+        let mut record = StepRecord::new(step, &pool, &market);
+        record.trades = trades;
+        record.volume = volume;
+        record.failed = failed;
+        steps.push(record);
+    }
+    Ok(Simulation { token_names: amm.token_names.clone(), seed: config.seed, steps, pool })
+}
+
+impl Simulation {
+    /// One row per step: totals first, then per tick, with per-token columns
+    /// suffixed by the token name.
+    pub fn to_csv(&self) -> String {
+        let tick_count = self.steps
+            .iter()
+            .map(|s| s.ticks.len())
+            .max()
+            .unwrap_or(0);
+        let per_token = |prefix: &str| -> Vec<String> {
+            self.token_names
+                .iter()
+                .map(|t| format!("{}_{}", prefix, t))
+                .collect()
+        };
+        let mut header = vec!["step".to_string()];
+        header.extend(per_token("reserve"));
+        header.extend(per_token("price"));
+        header.extend(per_token("external_price"));
+        header.extend(per_token("fees"));
+        header.extend(["trades", "volume", "failed"].map(String::from));
+        for i in 0..tick_count {
+            header.extend(per_token(&format!("tick{}_reserve", i)));
+            header.push(format!("tick{}_boundary", i));
+            header.extend(per_token(&format!("tick{}_fees", i)));
+        }
+
+        let mut csv = header.join(",") + "\n";
+        for step in &self.steps {
+            let mut row = vec![step.step.to_string()];
+            for values in [&step.reserves, &step.prices, &step.external_prices, &step.fees] {
+                row.extend(values.iter().map(f64::to_string));
+            }
+            row.extend([step.trades.to_string(), step.volume.to_string(), step.failed.to_string()]);
+            for i in 0..tick_count {
+                match step.ticks.get(i) {
+                    Some(tick) => {
+                        row.extend(tick.reserves.iter().map(f64::to_string));
+                        row.push(tick.boundary.to_string());
+                        row.extend(tick.fees_accrued.iter().map(f64::to_string));
+                    }
+                    None => {
+                        let blanks = 2 * self.token_names.len() + 1;
+                        row.extend(std::iter::repeat_n(String::new(), blanks));
+                    }
+                }
+            }
+            csv += &(row.join(",") + "\n");
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ stable_names, stable_pool };
+
+    fn config(steps: usize, seed: u64) -> SimConfig {
+        SimConfig { steps, seed, ..SimConfig::default() }
+    }
+
+    #[test]
+    fn test_same_seed_reproduces_the_run() {
+        let amm = stable_pool(0.95, 5);
+        let a = simulate(&amm, &config(30, 7)).unwrap();
+        let b = simulate(&amm, &config(30, 7)).unwrap();
+        assert_eq!(a.to_csv(), b.to_csv());
+    }
+
+    #[test]
+    fn test_other_seed_changes_the_run() {
+        let amm = stable_pool(0.95, 5);
+        let a = simulate(&amm, &config(30, 7)).unwrap();
+        let b = simulate(&amm, &config(30, 8)).unwrap();
+        assert_ne!(a.to_csv(), b.to_csv());
+    }
+
+    #[test]
+    fn test_records_every_step_and_trades_for_fees() {
+        let amm = stable_pool(0.95, 5);
+        let sim = simulate(&amm, &config(30, 7)).unwrap();
+        assert_eq!(sim.steps.len(), 31);
+        assert!(sim.steps.iter().map(|s| s.trades).sum::<usize>() >= 30);
+        assert!(sim.steps.last().unwrap().fees.iter().sum::<f64>() > 0.0);
+    }
+
+    #[test]
+    fn test_starting_pool_is_untouched() {
+        let amm = stable_pool(0.95, 5);
+        simulate(&amm, &config(30, 7)).unwrap();
+        assert_eq!(amm.global_reserves, vec![1000.0; 3]);
+    }
+
+    #[test]
+    fn test_csv_has_a_header_and_a_row_per_step() {
+        let sim = simulate(&stable_pool(0.95, 5), &config(30, 7)).unwrap();
+        let csv = sim.to_csv();
+        let header = csv.lines().next().unwrap();
+        assert!(header.starts_with("step,reserve_USDC,reserve_USDT,reserve_DAI,price_USDC"));
+        assert!(header.ends_with("tick0_boundary,tick0_fees_USDC,tick0_fees_USDT,tick0_fees_DAI"));
+        assert_eq!(csv.lines().count(), 32);
+    }
+
+    #[test]
+    fn test_pool_without_ticks_counts_every_operation_as_failed() {
+        let amm = MultiTickAMM::new(stable_names());
+        let noise = AgentConfig::Noise { max_amount: 10.0, trades_per_step: 2 };
+        let sim = simulate(&amm, &SimConfig { agents: vec![noise], ..config(3, 1) }).unwrap();
+        for step in &sim.steps[1..] {
+            assert_eq!((step.trades, step.failed), (0, 2));
+        }
+    }
+
+    #[test]
+    fn test_rejects_a_pool_without_tokens() {
+        let err = simulate(&MultiTickAMM::new(Vec::new()), &config(3, 1)).unwrap_err();
+        assert_eq!(err.code(), "length_mismatch");
+    }
+
+    #[test]
+    fn test_rejects_initial_prices_of_the_wrong_length() {
+        let config = SimConfig { initial_prices: Some(vec![1.0, 1.0]), ..config(3, 1) };
+        let err = simulate(&stable_pool(0.95, 5), &config).unwrap_err();
+        assert_eq!(err, OrbitalError::LengthMismatch { expected: 3, got: 2 });
+    }
+
+    #[test]
+    fn test_rejects_an_lp_on_a_missing_tick() {
+        let lp = AgentConfig::Lp {
+            id: "lp".into(),
+            tick_index: 1,
+            amount: 10.0,
+            enter_probability: 1.0,
+            exit_probability: 0.0,
+        };
+        let config = SimConfig { agents: vec![lp], ..config(3, 1) };
+        let err = simulate(&stable_pool(0.95, 5), &config).unwrap_err();
+        assert_eq!(err, OrbitalError::UnknownTick(1));
+    }
+
+    #[test]
+    fn test_csv_carries_every_json_field() {
+        fn leaves(value: &serde_json::Value, out: &mut Vec<String>) {
+            match value {
+                serde_json::Value::Array(items) => items.iter().for_each(|v| leaves(v, out)),
+                serde_json::Value::Object(fields) => fields.values().for_each(|v| leaves(v, out)),
+                serde_json::Value::Number(n) if n.is_u64() => out.push(n.to_string()),
+                serde_json::Value::Number(n) => out.push(n.as_f64().unwrap().to_string()),
+                other => out.push(other.to_string()),
+            }
+        }
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut amm = MultiTickAMM::new(names);
+        amm.add_tick_from_depeg(vec![1000.0; 2], 0.95, 5).unwrap();
+        amm.add_tick_from_depeg(vec![500.0; 2], 0.99, 30).unwrap();
+        let sim = simulate(&amm, &SimConfig { steps: 5, ..SimConfig::default() }).unwrap();
+
+        // Every value of a step's JSON record is in its CSV row, and nothing
+        // else is.
+        let csv = sim.to_csv();
+        for (step, line) in sim.steps.iter().zip(csv.lines().skip(1)) {
+            let mut json = Vec::new();
+            leaves(&serde_json::to_value(step).unwrap(), &mut json);
+            let mut row: Vec<String> = line.split(',').map(String::from).collect();
+            json.sort();
+            row.sort();
+            assert_eq!(json, row);
+        }
+    }
+}
@@ -1,19 +1,27 @@
-use std::fs;
+use std::path::Path;
 use serde::{ Deserialize, Serialize };
 
+use crate::error::OrbitalError;
+use crate::persist;
+use crate::scalar::{ Rounding, Scalar };
+
 /// SphereAMM is the minimal Orbital AMM primitive that keeps *n* token reserves
 /// on the surface of a hypersphere with radius `r`. All state-transitions must
 /// satisfy the invariant Σ (r − xᵢ)² = r².
-#[derive(Clone, Serialize, Deserialize)]
-pub struct SphereAMM {
+///
+/// The scalar type defaults to `f64`; the swap math also runs on
+/// `scalar::Fixed18` and `scalar::Exact`, rounding in the pool's favour.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SphereAMM<T = f64> {
     /// Hypersphere radius `r`.
-    pub radius: f64,
+    pub radius: T,
     /// Reserves `[x₁, x₂, …, xₙ]` for each token, same order as `token_names`.
-    pub reserves: Vec<f64>,
+    pub reserves: Vec<T>,
     /// Human-readable token identifiers.
     pub token_names: Vec<String>,
 }
 #[derive(Serialize, Clone)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
 pub struct PhasePoint {
     pub x1: f64,
     pub x2: f64,
@@ -21,10 +29,24 @@ pub struct PhasePoint {
     pub distance_from_equilibrium: f64,
     pub is_valid: bool,
 }
-impl SphereAMM {
+/// Result of a dry-run swap against a single `SphereAMM`.
+#[derive(Serialize, Clone)]
+pub struct SwapQuote {
+    pub amount_in: f64,
+    pub amount_out: f64,
+    /// Input paid per unit of output.
+    pub effective_price: f64,
+    /// `get_spot_price(from, to)` before the swap.
+    pub spot_price: f64,
+    /// Relative deviation of `effective_price` from `spot_price`.
+    pub price_impact: f64,
+    pub reserves_after: Vec<f64>,
+}
+
+impl<T: Scalar> SphereAMM<T> {
     /// Construct a new SphereAMM from initial reserves. The radius is solved so
     /// that the invariant is satisfied at genesis.
-    pub fn new(token_names: Vec<String>, initial_reserves: Vec<f64>) -> Self {
+    pub fn new(token_names: Vec<String>, initial_reserves: Vec<T>) -> Self {
         assert_eq!(
             token_names.len(),
             initial_reserves.len(),
@@ -38,104 +60,248 @@ impl SphereAMM {
 
     /// Calculate the AMM radius `r` that satisfies the invariant for the given
     /// reserves.
-    fn solve_radius(reserves: &[f64]) -> f64 {
+    pub fn solve_radius(reserves: &[T]) -> T {
         if reserves.is_empty() {
-            return 0.0;
+            return T::zero();
         }
-        let n = reserves.len() as f64;
-        let sum_x: f64 = reserves.iter().copied().sum();
-        let sum_x2: f64 = reserves
-            .iter()
-            .map(|x| x * x)
-            .sum();
-        let a = n - 1.0;
-        if a.abs() < 1e-12 {
+        let down = Rounding::Down;
+        let two = T::one() + T::one();
+        let sum_x = reserves.iter().fold(T::zero(), |acc, x| acc + x.clone());
+        let sum_x2 = reserves.iter().fold(T::zero(), |acc, x| acc + x.mul_round(x, down));
+        if reserves.len() == 1 {
             // Degenerate n=1 case – radius equals the single reserve.
             return sum_x;
         }
-        let b = -2.0 * sum_x;
+        let a = T::from_usize(reserves.len() - 1);
+        let b = -two.mul_round(&sum_x, down);
         let c = sum_x2;
-        let disc = b * b - 4.0 * a * c;
+        let four = two.clone() + two.clone();
+        let disc = b.mul_round(&b, down) - four.mul_round(&a, down).mul_round(&c, down);
         // Numerical robustness – treat tiny negatives as 0.
-        let disc_clamped = if disc < 0.0 && disc > -1e-12 { 0.0 } else { disc };
-        let sqrt_disc = disc_clamped.sqrt();
+        let disc_clamped = if disc < T::zero() && disc > T::from_f64(-1e-12) {
+            T::zero()
+        } else {
+            disc
+        };
+        let sqrt_disc = disc_clamped.sqrt_round(down);
         // Only the positive root is meaningful for `r`.
-        let r1 = (-b + sqrt_disc) / (2.0 * a);
-        let r2 = (-b - sqrt_disc) / (2.0 * a);
-        if r1 > 0.0 {
+        let two_a = two.mul_round(&a, down);
+        let r1 = (-b.clone() + sqrt_disc.clone()).div_round(&two_a, down);
+        let r2 = (-b - sqrt_disc).div_round(&two_a, down);
+        if r1 > T::zero() {
             r1
         } else {
             r2
         }
     }
 
-    /// Verify the hypersphere invariant within a small tolerance.
+    /// Verify the hypersphere invariant within the scalar type's tolerance.
     pub fn check_invariant(&self) -> bool {
-        let lhs: f64 = self.reserves
-            .iter()
-            .map(|&x| {
-                let diff = self.radius - x;
-                diff * diff
-            })
-            .sum();
-        (lhs - self.radius * self.radius).abs() < 1e-6
+        let down = Rounding::Down;
+        let lhs = self.reserves.iter().fold(T::zero(), |acc, x| {
+            let diff = self.radius.clone() - x.clone();
+            acc + diff.mul_round(&diff, down)
+        });
+        (lhs - self.radius.mul_round(&self.radius, down)).abs() < T::tolerance()
     }
 
-    /// Return the index of a token by name, or an error string if it is absent.
-    pub fn index_of(&self, token: &str) -> Result<usize, String> {
+    /// Return the index of a token by name, or `UnknownToken` if it is absent.
+    pub fn index_of(&self, token: &str) -> Result<usize, OrbitalError> {
         self.token_names
             .iter()
             .position(|t| t == token)
-            .ok_or_else(|| format!("Token '{}' not found in pool", token))
+            .ok_or_else(|| OrbitalError::UnknownToken(token.to_string()))
     }
 
     /// Spot price of `to` in units of `from` given by (r − x_to)/(r − x_from).
-    pub fn get_spot_price(&self, from: &str, to: &str) -> Result<f64, String> {
+    pub fn get_spot_price(&self, from: &str, to: &str) -> Result<T, OrbitalError> {
         let i = self.index_of(from)?;
         let j = self.index_of(to)?;
-        let denom = self.radius - self.reserves[i];
-        if denom.abs() < 1e-12 {
-            return Err("Division by zero – from-token is at radius".into());
+        let denom = self.radius.clone() - self.reserves[i].clone();
+        if denom.abs() < T::from_f64(1e-12) {
+            return Err(OrbitalError::PriceUndefined);
         }
-        Ok((self.radius - self.reserves[j]) / denom)
+        Ok((self.radius.clone() - self.reserves[j].clone()).div_round(&denom, Rounding::Down))
     }
 
-    /// Execute a swap from `from` → `to`, returning the output amount while
-    /// keeping the invariant intact.
-    pub fn swap(&mut self, from: &str, to: &str, amount_in: f64) -> Result<f64, String> {
-        if amount_in <= 0.0 {
-            return Err("Swap amount must be positive".into());
-        }
-        let i = self.index_of(from)?;
-        let j = self.index_of(to)?;
-
-        let a = self.reserves[i];
-        let b = self.reserves[j];
+    /// Output of `to` (index `j`) received for `amount_in` of `from` (index `i`),
+    /// without applying it. Rounded down.
+    pub fn output_for_input(&self, i: usize, j: usize, amount_in: T) -> Result<T, OrbitalError> {
+        let (down, up) = (Rounding::Down, Rounding::Up);
+        let a = self.reserves[i].clone();
+        let b = self.reserves[j].clone();
 
         // Analytic solution derived from:
         //  (r − (a + Δx))² + (r − (b − Δy))² = (r − a)² + (r − b)²
         //  ⇒ Δy² + 2B Δy + (Δx² − 2A Δx) = 0, where
         //     A = r − a,  B = r − b, Δx = amount_in, Δy = output_amount
-        let r = self.radius;
-        let A = r - a;
-        let B = r - b;
-        let c = amount_in * amount_in - 2.0 * A * amount_in;
-        let disc = B * B - c;
-        if disc < 0.0 {
-            return Err("Swap leads to complex solution – probably too large input amount".into());
+        // Every rounding step shrinks the discriminant, and with it Δy.
+        let r = self.radius.clone();
+        let two = T::one() + T::one();
+        let gap_in = r.clone() - a;
+        let gap_out = r - b.clone();
+        let c =
+            amount_in.mul_round(&amount_in, up) -
+            two.mul_round(&gap_in, down).mul_round(&amount_in, down);
+        let disc = gap_out.mul_round(&gap_out, down) - c;
+        if disc < T::zero() {
+            return Err(OrbitalError::ComplexSolution);
         }
-        let output = -B + disc.sqrt();
-        if output <= 0.0 || output > b {
-            return Err("Insufficient liquidity for the requested swap".into());
+        let output = -gap_out + disc.sqrt_round(down);
+        if output <= T::zero() || output > b {
+            return Err(OrbitalError::InsufficientLiquidity);
         }
+        Ok(output)
+    }
+
+    /// Input of `from` (index `i`) required to receive exactly `amount_out` of
+    /// `to` (index `j`), without applying it. Rounded up.
+    pub fn input_for_output(&self, i: usize, j: usize, amount_out: T) -> Result<T, OrbitalError> {
+        let (down, up) = (Rounding::Down, Rounding::Up);
+        let a = self.reserves[i].clone();
+        let b = self.reserves[j].clone();
+        if amount_out >= b {
+            return Err(OrbitalError::InsufficientLiquidity);
+        }
+
+        // Same invariant as `output_for_input`, solved for the input instead:
+        //  Δx² − 2A Δx + (Δy² + 2B Δy) = 0
+        //  ⇒ Δx = A − √(A² − Δy² − 2B Δy)   (smaller root stays on our side)
+        let r = self.radius.clone();
+        let two = T::one() + T::one();
+        let gap_in = r.clone() - a;
+        let gap_out = r - b;
+        let disc =
+            gap_in.mul_round(&gap_in, down) -
+            amount_out.mul_round(&amount_out, up) -
+            two.mul_round(&gap_out, up).mul_round(&amount_out, up);
+        if disc < T::zero() {
+            return Err(OrbitalError::ComplexSolution);
+        }
+        let input = gap_in - disc.sqrt_round(down);
+        if input <= T::zero() {
+            return Err(OrbitalError::InsufficientLiquidity);
+        }
+        Ok(input)
+    }
+
+    /// Execute a swap from `from` → `to`, returning the output amount while
+    /// keeping the invariant intact.
+    pub fn swap(&mut self, from: &str, to: &str, amount_in: T) -> Result<T, OrbitalError> {
+        if amount_in <= T::zero() {
+            return Err(OrbitalError::NonPositiveAmount);
+        }
+        let i = self.index_of(from)?;
+        let j = self.index_of(to)?;
+        let output = self.output_for_input(i, j, amount_in.clone())?;
 
         // Apply state changes.
-        self.reserves[i] += amount_in;
-        self.reserves[j] -= output;
+        self.reserves[i] = self.reserves[i].clone() + amount_in;
+        self.reserves[j] = self.reserves[j].clone() - output.clone();
         debug_assert!(self.check_invariant(), "Invariant broken after swap");
         Ok(output)
     }
 
+    /// Execute an exact-output swap from `from` → `to`: withdraw exactly
+    /// `amount_out` of `to` and return the input amount charged. Fails without
+    /// touching state if the required input exceeds `max_amount_in`.
+    pub fn swap_exact_out(
+        &mut self,
+        from: &str,
+        to: &str,
+        amount_out: T,
+        max_amount_in: T
+    ) -> Result<T, OrbitalError> {
+        if amount_out <= T::zero() {
+            return Err(OrbitalError::NonPositiveAmount);
+        }
+        let i = self.index_of(from)?;
+        let j = self.index_of(to)?;
+        let input = self.input_for_output(i, j, amount_out.clone())?;
+        if input > max_amount_in {
+            return Err(OrbitalError::SlippageExceeded {
+                required: input.to_f64(),
+                max: max_amount_in.to_f64(),
+            });
+        }
+
+        self.reserves[i] = self.reserves[i].clone() + input.clone();
+        self.reserves[j] = self.reserves[j].clone() - amount_out;
+        debug_assert!(self.check_invariant(), "Invariant broken after swap");
+        Ok(input)
+    }
+
+    /// Convert the pool to another scalar backend, e.g. to replay an `f64`
+    /// state on fixed point. The radius is converted as-is, not re-solved.
+    /// Fails if the radius or a reserve is not finite.
+    pub fn convert<U: Scalar>(&self) -> Result<SphereAMM<U>, OrbitalError> {
+        Ok(SphereAMM {
+            radius: U::try_from_f64(self.radius.to_f64())?,
+            reserves: self.reserves
+                .iter()
+                .map(|x| U::try_from_f64(x.to_f64()))
+                .collect::<Result<_, _>>()?,
+            token_names: self.token_names.clone(),
+        })
+    }
+}
+
+impl SphereAMM {
+    /// Radius of the sphere through `reserves`, which must be finite and
+    /// non-negative. Fails when no sphere of positive radius passes through
+    /// them, e.g. one token funded and every other at zero.
+    pub fn checked_radius(reserves: &[f64]) -> Result<f64, OrbitalError> {
+        if let Some(&x) = reserves.iter().find(|x| !(x.is_finite() && **x >= 0.0)) {
+            return Err(OrbitalError::InvalidReserves(format!("{} is not a valid reserve", x)));
+        }
+        let radius = Self::solve_radius(reserves);
+        if !(radius.is_finite() && radius > 0.0) {
+            return Err(
+                OrbitalError::InvalidReserves(format!("{:?} lie on no sphere", reserves))
+            );
+        }
+        Ok(radius)
+    }
+
+    /// Quote an exact-input swap without mutating the pool.
+    pub fn quote(&self, from: &str, to: &str, amount_in: f64) -> Result<SwapQuote, OrbitalError> {
+        let mut sim = self.clone();
+        let amount_out = sim.swap(from, to, amount_in)?;
+        self.build_quote(from, to, amount_in, amount_out, sim.reserves)
+    }
+
+    /// Quote an exact-output swap without mutating the pool.
+    pub fn quote_exact_out(
+        &self,
+        from: &str,
+        to: &str,
+        amount_out: f64
+    ) -> Result<SwapQuote, OrbitalError> {
+        let mut sim = self.clone();
+        let amount_in = sim.swap_exact_out(from, to, amount_out, f64::INFINITY)?;
+        self.build_quote(from, to, amount_in, amount_out, sim.reserves)
+    }
+
+    fn build_quote(
+        &self,
+        from: &str,
+        to: &str,
+        amount_in: f64,
+        amount_out: f64,
+        reserves_after: Vec<f64>
+    ) -> Result<SwapQuote, OrbitalError> {
+        let spot_price = self.get_spot_price(from, to)?;
+        let effective_price = amount_in / amount_out;
+        Ok(SwapQuote {
+            amount_in,
+            amount_out,
+            effective_price,
+            spot_price,
+            price_impact: effective_price / spot_price - 1.0,
+            reserves_after,
+        })
+    }
+
     /* ---------- Persistence helpers (CLI convenience) ---------- */
     /// Pretty-print the current pool state.
     pub fn print_state(&self) {
@@ -146,20 +312,33 @@ impl SphereAMM {
         println!("  invariant: {}", if self.check_invariant() { "✓" } else { "✗" });
     }
 
-    pub fn save_state(&self) {
-        let json = serde_json::to_string_pretty(self).expect("serialize state");
-        fs::write("orbital_pool.json", json).expect("write state");
+    /// Atomically save the pool to `path`.
+    pub fn save_state(&self, path: &Path) -> Result<(), OrbitalError> {
+        persist::save_json(self, path)
     }
 
-    pub fn load_state() -> Self {
-        match fs::read_to_string("orbital_pool.json") {
-            Ok(json) =>
-                serde_json::from_str(&json).unwrap_or_else(|_| panic!("invalid state file")),
-            Err(_) => panic!("No existing state – initialise first with `init`"),
-        }
+    /// Load a pool saved with `save_state`. Unlike the multi-tick pool there
+    /// is no empty default, so a missing file is an error.
+    pub fn load_state(path: &Path) -> Result<Self, OrbitalError> {
+        persist
+            ::load_json(path, migrate_sphere)?
+            .ok_or_else(|| OrbitalError::MissingState(path.display().to_string()))
     }
 }
 
+/// Schema migrations for `SphereAMM` state files.
+fn migrate_sphere(_: &mut serde_json::Value, from: u32) -> Result<(), String> {
+    match from {
+        // v1 only added `schema_version`; v2 only changed multi-tick LP
+        // shares.
+        0 | 1 => Ok(()),
+        v => Err(format!("no migration from schema version {}", v)),
+    }
+}
+
+/// Single-sphere pool file used when no other path is configured.
+pub const DEFAULT_POOL_FILE: &str = "orbital_pool.json";
+
 /* ---------- Stand-alone math helpers ---------- */
 /// Generate phase space data for visualization
 pub fn generate_phase_data(radius: f64, n_tokens: usize, resolution: usize) -> Vec<PhasePoint> {
@@ -199,6 +378,80 @@ pub fn equal_price_point(radius: f64, n_tokens: usize) -> f64 {
     radius * (1.0 - 1.0 / (n_tokens as f64).sqrt())
 }
 
+/// Plane constant of a full-range tick, whose boundary is only reached when
+/// one token's reserve runs dry: k = r(n − 1)/√n.
+pub fn full_range_plane(radius: f64, n_tokens: usize) -> f64 {
+    if n_tokens == 0 {
+        return 0.0;
+    }
+    let n = n_tokens as f64;
+    (radius * (n - 1.0)) / n.sqrt()
+}
+
+/// Plane constant `k` of the tick whose boundary is reached when one token
+/// depegs to `depeg_price` against all others:
+/// k = r√n − r(p + n − 1)/√(n(p² + n − 1)).
+pub fn plane_for_depeg_price(radius: f64, n_tokens: usize, depeg_price: f64) -> f64 {
+    let n = n_tokens as f64;
+    let p = depeg_price;
+    radius * n.sqrt() - (radius * (p + n - 1.0)) / (n * (p * p + n - 1.0)).sqrt()
+}
+
+/// Minimum and maximum reserve a single token can reach inside a tick with
+/// plane constant `plane`, i.e. the extreme coordinates of the sphere ∩ plane.
+pub fn tick_reserve_bounds(radius: f64, n_tokens: usize, plane: f64) -> (f64, f64) {
+    let n = n_tokens as f64;
+    // Distance of the plane from the sphere centre, measured along v.
+    let s = radius * n.sqrt() - plane;
+    let spread = (n * (n - 1.0) * (radius * radius - s * s)).max(0.0).sqrt();
+    let y_max = (s * n.sqrt() + spread) / n;
+    let y_min = ((s * n.sqrt() - spread) / n).max(0.0);
+    ((radius - y_max).max(0.0), radius - y_min)
+}
+
+/// Capital efficiency of a tick versus a full-range sphere of the same radius:
+/// LPs only need to fund reserves above `x_min`, so q / (q − x_min).
+pub fn capital_efficiency(radius: f64, n_tokens: usize, plane: f64) -> f64 {
+    let q = equal_price_point(radius, n_tokens);
+    let (x_min, _) = tick_reserve_bounds(radius, n_tokens, plane);
+    if q - x_min <= 1e-12 {
+        return f64::INFINITY;
+    }
+    q / (q - x_min)
+}
+
+/// Tick parameters derived from a radius and a depeg price.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct DepegTickParams {
+    pub depeg_price: f64,
+    pub plane_constant: f64,
+    pub min_reserve: f64,
+    pub max_reserve: f64,
+    pub capital_efficiency: f64,
+}
+
+/// Derive the plane constant, reserve bounds and capital efficiency of a tick
+/// that covers prices down to `depeg_price` (0 < p < 1).
+pub fn depeg_tick_params(
+    radius: f64,
+    n_tokens: usize,
+    depeg_price: f64
+) -> Result<DepegTickParams, OrbitalError> {
+    if !(depeg_price > 0.0 && depeg_price < 1.0) {
+        return Err(OrbitalError::InvalidDepegPrice(depeg_price));
+    }
+    let plane = plane_for_depeg_price(radius, n_tokens, depeg_price);
+    let (min_reserve, max_reserve) = tick_reserve_bounds(radius, n_tokens, plane);
+    Ok(DepegTickParams {
+        depeg_price,
+        plane_constant: plane,
+        min_reserve,
+        max_reserve,
+        capital_efficiency: capital_efficiency(radius, n_tokens, plane),
+    })
+}
+
 /// Hypersphere invariant value Σ (r − xᵢ)² – r² (should equal 0 when satisfied).
 pub fn sphere_invariant(reserves: &[f64], radius: f64) -> f64 {
     let lhs: f64 = reserves
@@ -240,6 +493,17 @@ pub fn decompose_reserves(reserves: &[f64]) -> (f64, Vec<f64>) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::scalar::{ Exact, Fixed18 };
+
+    /// (r − x₁, r − x₂) = (600, 800) is a Pythagorean triple, so every backend
+    /// starts from exactly the same point on the r = 1000 circle.
+    fn pythagorean_pool<T: Scalar>() -> SphereAMM<T> {
+        SphereAMM {
+            radius: T::from_usize(1000),
+            reserves: vec![T::from_usize(400), T::from_usize(200)],
+            token_names: vec!["USDC".into(), "USDT".into()],
+        }
+    }
 
     #[test]
     fn test_invariant_after_swap() {
@@ -250,4 +514,88 @@ mod tests {
         assert!(out > 0.0);
         assert!(amm.check_invariant());
     }
+
+    #[test]
+    fn test_exact_out_inverts_exact_in() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into(), "DAI".into()];
+        let mut exact_in = SphereAMM::new(names.clone(), vec![1000.0, 1000.0, 1000.0]);
+        let mut exact_out = exact_in.clone();
+        let out = exact_in.swap("USDC", "USDT", 50.0).unwrap();
+        let input = exact_out.swap_exact_out("USDC", "USDT", out, f64::INFINITY).unwrap();
+        assert!((input - 50.0).abs() < 1e-9);
+        assert!(exact_out.check_invariant());
+    }
+
+    #[test]
+    fn test_exact_out_respects_max_amount_in() {
+        let names = vec!["USDC".into(), "USDT".into()];
+        let mut amm = SphereAMM::new(names, vec![100.0, 100.0]);
+        let before = amm.reserves.clone();
+        let err = amm.swap_exact_out("USDC", "USDT", 10.0, 1.0).unwrap_err();
+        assert_eq!(err.code(), "slippage_exceeded");
+        assert_eq!(amm.reserves, before);
+    }
+
+    #[test]
+    fn test_quote_does_not_mutate() {
+        let names = vec!["USDC".into(), "USDT".into()];
+        let amm = SphereAMM::new(names, vec![100.0, 100.0]);
+        let quote = amm.quote("USDC", "USDT", 10.0).unwrap();
+        assert_eq!(amm.reserves, vec![100.0, 100.0]);
+        assert!(quote.effective_price >= quote.spot_price);
+        assert!((quote.reserves_after[0] - 110.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_depeg_plane_hits_price_at_boundary() {
+        let n = 3;
+        let radius = 1000.0;
+        let params = depeg_tick_params(radius, n, 0.99).unwrap();
+        assert!(params.plane_constant > radius * ((n as f64).sqrt() - 1.0));
+        assert!(params.min_reserve < equal_price_point(radius, n));
+        assert!(params.capital_efficiency > 1.0);
+
+        // One token depegged, the others equal: the reference point the
+        // plane was derived from.
+        let y_other = radius / (0.99f64 * 0.99 + 2.0).sqrt();
+        let reserves = vec![radius - 0.99 * y_other, radius - y_other, radius - y_other];
+        let amm = SphereAMM {
+            radius,
+            reserves: reserves.clone(),
+            token_names: vec!["A".into(), "B".into(), "C".into()],
+        };
+        assert!(amm.check_invariant());
+        let (parallel, _) = decompose_reserves(&reserves);
+        assert!((parallel - params.plane_constant).abs() < 1e-9);
+        assert!((amm.get_spot_price("B", "A").unwrap() - 0.99).abs() < 1e-12);
+        assert_eq!(
+            depeg_tick_params(radius, n, 1.0).unwrap_err(),
+            OrbitalError::InvalidDepegPrice(1.0)
+        );
+    }
+
+    #[test]
+    fn test_backends_agree_and_round_for_the_pool() {
+        let mut float = pythagorean_pool::<f64>();
+        let mut fixed = pythagorean_pool::<Fixed18>();
+        let mut exact = pythagorean_pool::<Exact>();
+        assert!(float.check_invariant() && fixed.check_invariant() && exact.check_invariant());
+
+        let out_float = float.swap("USDT", "USDC", 30.0).unwrap();
+        let out_fixed = fixed.swap("USDT", "USDC", Fixed18::from_usize(30)).unwrap();
+        let out_exact = exact.swap("USDT", "USDC", Exact::from_usize(30)).unwrap();
+        assert!((out_fixed.to_f64() - out_exact.to_f64()).abs() < 1e-12);
+        assert!((out_float - out_exact.to_f64()).abs() < 1e-9);
+        assert!(out_fixed.to_f64() <= out_exact.to_f64());
+        assert!(fixed.check_invariant() && exact.check_invariant());
+
+        let in_fixed = fixed
+            .swap_exact_out("USDC", "USDT", Fixed18::from_usize(10), Fixed18::from_usize(100))
+            .unwrap();
+        let in_exact = exact
+            .swap_exact_out("USDC", "USDT", Exact::from_usize(10), Exact::from_usize(100))
+            .unwrap();
+        assert!(in_fixed.to_f64() >= in_exact.to_f64());
+        assert!((in_fixed.to_f64() - in_exact.to_f64()).abs() < 1e-12);
+    }
 }
@@ -0,0 +1,27 @@
+//! Fixtures shared by the unit tests.
+
+use std::path::PathBuf;
+
+use crate::ticks::MultiTickAMM;
+
+/// A directory under the system temp dir for the test `name`, unique to this
+/// test process and emptied of anything an earlier run left behind. It is
+/// not created.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("orbital-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// USDC, USDT and DAI, in that order.
+pub fn stable_names() -> Vec<String> {
+    vec!["USDC".into(), "USDT".into(), "DAI".into()]
+}
+
+/// A pool over [`stable_names`] with one tick of 1000 of each token, sized
+/// for `depeg_price` and charging `fee_bps`.
+pub fn stable_pool(depeg_price: f64, fee_bps: u32) -> MultiTickAMM {
+    let mut amm = MultiTickAMM::new(stable_names());
+    amm.add_tick_from_depeg(vec![1000.0; 3], depeg_price, fee_bps).unwrap();
+    amm
+}
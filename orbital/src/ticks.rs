@@ -1,25 +1,155 @@
 use std::collections::HashMap;
-use std::fs;
+use std::path::{ Path, PathBuf };
 
 use serde::{ Deserialize, Serialize };
 
-use crate::sphere::{ decompose_reserves, SphereAMM };
+use crate::error::OrbitalError;
+use crate::persist;
+use crate::sphere::{
+    decompose_reserves,
+    capital_efficiency,
+    depeg_tick_params,
+    equal_price_point,
+    full_range_plane,
+    tick_reserve_bounds,
+    DepegTickParams,
+    SphereAMM,
+};
+
+/// LP id that owns the reserves a tick is created with.
+pub const CREATOR_LP: &str = "creator";
+
+/// Fee tiers are strictly below 100%, so part of every input is traded.
+pub const MAX_FEE_BPS: u32 = 10_000;
+
+/// Check that a fee tier leaves something of the input to trade with.
+pub fn check_fee(fee_bps: u32) -> Result<(), OrbitalError> {
+    if fee_bps >= MAX_FEE_BPS {
+        return Err(OrbitalError::InvalidFee(fee_bps));
+    }
+    Ok(())
+}
 
 /// A single liquidity band ("tick") of the Orbital AMM.
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct OrbitalTick {
     pub sphere_amm: SphereAMM,
-    /// Constant `c` defining the plane r_parallel = c that bounds this tick.
+    /// Constant `k` defining the plane r_parallel = k that bounds this tick. The
+    /// tick is the spherical cap r_parallel ≤ k around the equal-price point.
     pub plane_constant: f64,
     /// LP ownership mapping – **not** optimized, but fine for simulation.
     pub lp_shares: HashMap<String, f64>,
+    /// Swap fee charged on the input amount, in basis points.
+    #[serde(default)]
+    pub fee_bps: u32,
+    /// Fees collected per token, held outside the sphere until LPs withdraw.
+    #[serde(default)]
+    pub fees_accrued: Vec<f64>,
+    /// Token amounts each LP deposited, scaled down with partial withdrawals.
+    /// What the LP would hold had it never provided liquidity.
+    #[serde(default)]
+    pub lp_deposits: HashMap<String, Vec<f64>>,
+    /// The part of each LP's deposit that bought into fees accrued before it,
+    /// scaled down with partial withdrawals. Fee income is the LP's slice of
+    /// `fees_accrued` less this.
+    #[serde(default)]
+    pub lp_fee_buy_in: HashMap<String, Vec<f64>>,
+}
+
+/// Outcome of `OrbitalTick::add_liquidity`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deposit {
+    /// Amounts taken into the tick, in proportion to its holdings.
+    pub deposited: Vec<f64>,
+    /// What was left of the offered amounts and handed back.
+    pub refunded: Vec<f64>,
+    /// Shares minted for the LP.
+    pub shares: f64,
 }
 
 impl OrbitalTick {
     /// Convenience constructor from raw reserves and plane constant.
     pub fn new(token_names: Vec<String>, reserves: Vec<f64>, plane_constant: f64) -> Self {
+        Self::new_with_fee(token_names, reserves, plane_constant, 0)
+    }
+
+    /// Same as `new` but with a swap fee tier in basis points.
+    pub fn new_with_fee(
+        token_names: Vec<String>,
+        reserves: Vec<f64>,
+        plane_constant: f64,
+        fee_bps: u32
+    ) -> Self {
+        let n = token_names.len();
+        let initial: f64 = reserves.iter().sum();
+        let mut lp_shares = HashMap::new();
+        let mut lp_deposits = HashMap::new();
+        if initial > 0.0 {
+            lp_shares.insert(CREATOR_LP.to_string(), initial);
+            lp_deposits.insert(CREATOR_LP.to_string(), reserves.clone());
+        }
         let amm = SphereAMM::new(token_names, reserves);
-        Self { sphere_amm: amm, plane_constant, lp_shares: HashMap::new() }
+        Self {
+            sphere_amm: amm,
+            plane_constant,
+            lp_shares,
+            fee_bps,
+            fees_accrued: vec![0.0; n],
+            lp_deposits,
+            lp_fee_buy_in: HashMap::new(),
+        }
+    }
+
+    /// Build a tick of radius `radius` sitting at the equal-price point whose
+    /// boundary is reached when one token trades at `depeg_price` against the
+    /// others (e.g. 0.99).
+    pub fn from_depeg_price(
+        token_names: Vec<String>,
+        radius: f64,
+        depeg_price: f64,
+        fee_bps: u32
+    ) -> Result<Self, OrbitalError> {
+        check_fee(fee_bps)?;
+        let n = token_names.len();
+        let params = depeg_tick_params(radius, n, depeg_price)?;
+        let reserves = vec![equal_price_point(radius, n); n];
+        Ok(Self::new_with_fee(token_names, reserves, params.plane_constant, fee_bps))
+    }
+
+    /// Fee rate as a fraction of the input amount.
+    pub fn fee_rate(&self) -> f64 {
+        (self.fee_bps as f64) / 10_000.0
+    }
+
+    fn accrue_fee(&mut self, token_idx: usize, fee: f64) {
+        // State files written before fees existed deserialize with an empty vec.
+        self.fees_accrued.resize(self.sphere_amm.reserves.len(), 0.0);
+        self.fees_accrued[token_idx] += fee;
+    }
+
+    /// Exact-input swap through this tick. The fee is taken from `amount_in`
+    /// before solving the invariant. Returns `(amount_out, fee)`.
+    pub fn swap(&mut self, from: &str, to: &str, amount_in: f64) -> Result<(f64, f64), OrbitalError> {
+        let i = self.sphere_amm.index_of(from)?;
+        let fee = amount_in * self.fee_rate();
+        let out = self.sphere_amm.swap(from, to, amount_in - fee)?;
+        self.accrue_fee(i, fee);
+        Ok((out, fee))
+    }
+
+    /// Exact-output swap through this tick. Returns `(gross amount_in, fee)`.
+    pub fn swap_exact_out(
+        &mut self,
+        from: &str,
+        to: &str,
+        amount_out: f64
+    ) -> Result<(f64, f64), OrbitalError> {
+        let i = self.sphere_amm.index_of(from)?;
+        let net_in = self.sphere_amm.swap_exact_out(from, to, amount_out, f64::INFINITY)?;
+        let gross_in = net_in / (1.0 - self.fee_rate());
+        let fee = gross_in - net_in;
+        self.accrue_fee(i, fee);
+        Ok((gross_in, fee))
     }
 
     /// Parallel component magnitude of the current reserves vector.
@@ -28,71 +158,155 @@ impl OrbitalTick {
         mag
     }
 
+    /// Strictly inside the cap r_parallel ≤ k. Releases before schema v1
+    /// used the opposite inequality; `migrate_multi_tick` converts their planes.
     pub fn is_interior(&self) -> bool {
-        self.parallel_magnitude() > self.plane_constant + 1e-6
+        self.parallel_magnitude() < self.plane_constant - 1e-6
     }
 
     pub fn is_boundary(&self) -> bool {
         (self.parallel_magnitude() - self.plane_constant).abs() < 1e-6
     }
 
-    /// Add liquidity amounts for an LP. Very simplified: shares are proportional
-    /// to the sum of the deposited token amounts.
-    pub fn add_liquidity(&mut self, lp_id: &str, amounts: &[f64]) -> Result<(), String> {
-        if amounts.len() != self.sphere_amm.reserves.len() {
-            return Err("Amounts length mismatch".into());
+    /// Plane constant normalised by the radius. Ticks that sit at the same
+    /// price share the same r_parallel / r, so this orders boundary crossings.
+    pub fn normalized_plane(&self) -> f64 {
+        self.plane_constant / self.sphere_amm.radius
+    }
+
+    /// Radius of the (n−1)-sphere left when the tick is pinned to its plane:
+    /// s = √(r² − (r√n − k)²).
+    pub fn boundary_radius(&self) -> f64 {
+        let r = self.sphere_amm.radius;
+        let sqrt_n = (self.sphere_amm.reserves.len() as f64).sqrt();
+        let d = r * sqrt_n - self.plane_constant;
+        (r * r - d * d).max(0.0).sqrt()
+    }
+
+    /// Capital efficiency figures for this tick. See `TickEfficiency`.
+    pub fn efficiency(&self) -> TickEfficiency {
+        let n = self.sphere_amm.reserves.len();
+        let r = self.sphere_amm.radius;
+        let (min_reserve, max_reserve) = tick_reserve_bounds(r, n, self.plane_constant);
+        TickEfficiency {
+            min_reserve,
+            max_reserve,
+            virtual_reserves: vec![min_reserve; n],
+            real_reserves: self.sphere_amm.reserves
+                .iter()
+                .map(|x| (x - min_reserve).max(0.0))
+                .collect(),
+            capital_efficiency: capital_efficiency(r, n, self.plane_constant),
         }
-        for (r, a) in self.sphere_amm.reserves.iter_mut().zip(amounts.iter()) {
-            *r += *a;
+    }
+
+    /// Outside its own cap, e.g. after reserves were overwritten by hand.
+    pub fn is_exterior(&self) -> bool {
+        self.parallel_magnitude() > self.plane_constant + 1e-6
+    }
+
+    /// Add liquidity for an LP. Only the largest part of `amounts` that is
+    /// proportional to the tick's holdings (reserves plus accrued fees) is
+    /// taken; the rest is refunded. Shares are minted in proportion to the
+    /// growth of the holdings, so existing LPs keep the value they own.
+    pub fn add_liquidity(&mut self, lp_id: &str, amounts: &[f64]) -> Result<Deposit, OrbitalError> {
+        let n = self.sphere_amm.reserves.len();
+        if amounts.len() != n {
+            return Err(OrbitalError::LengthMismatch { expected: n, got: amounts.len() });
+        }
+        if amounts.iter().any(|a| !(a.is_finite() && *a >= 0.0)) {
+            return Err(OrbitalError::NonPositiveAmount);
+        }
+        self.fees_accrued.resize(n, 0.0);
+        let holdings: Vec<f64> = self.sphere_amm.reserves
+            .iter()
+            .zip(&self.fees_accrued)
+            .map(|(r, f)| r + f)
+            .collect();
+        let total_shares = self.total_shares();
+        if total_shares <= 0.0 || holdings.iter().all(|h| *h <= 0.0) {
+            return Err(OrbitalError::EmptyTick);
+        }
+        // Growth of the holdings the deposit can pay for in every token.
+        let growth = holdings
+            .iter()
+            .zip(amounts)
+            .filter(|(h, _)| **h > 0.0)
+            .map(|(h, a)| a / h)
+            .fold(f64::INFINITY, f64::min);
+        if growth <= 0.0 {
+            return Err(OrbitalError::NonPositiveAmount);
+        }
+
+        let deposited: Vec<f64> = holdings
+            .iter()
+            .map(|h| h * growth)
+            .collect();
+        let refunded = amounts
+            .iter()
+            .zip(&deposited)
+            .map(|(a, d)| (a - d).max(0.0))
+            .collect();
+        // The part of the deposit that pays for the fees already accrued.
+        let fee_buy_in: Vec<f64> = self.fees_accrued
+            .iter()
+            .map(|f| f * growth)
+            .collect();
+        for r in self.sphere_amm.reserves.iter_mut() {
+            *r *= 1.0 + growth;
+        }
+        for f in self.fees_accrued.iter_mut() {
+            *f *= 1.0 + growth;
+        }
+        // The invariant is homogeneous: scaled reserves sit on the scaled
+        // sphere, at the same position relative to the scaled plane.
+        self.sphere_amm.radius *= 1.0 + growth;
+        self.plane_constant *= 1.0 + growth;
+
+        let shares = total_shares * growth;
+        *self.lp_shares.entry(lp_id.to_string()).or_default() += shares;
+        let basis = self.lp_deposits.entry(lp_id.to_string()).or_default();
+        basis.resize(n, 0.0);
+        for (b, d) in basis.iter_mut().zip(&deposited) {
+            *b += *d;
+        }
+        let buy_in = self.lp_fee_buy_in.entry(lp_id.to_string()).or_default();
+        buy_in.resize(n, 0.0);
+        for (b, f) in buy_in.iter_mut().zip(&fee_buy_in) {
+            *b += *f;
+        }
+        Ok(Deposit { deposited, refunded, shares })
+    }
+
+    fn total_shares(&self) -> f64 {
+        self.lp_shares.values().copied().sum()
+    }
+
+    /// Fraction of the tick's reserves and accrued fees that `lp_id` can
+    /// withdraw; zero for unknown LPs.
+    pub fn lp_fraction(&self, lp_id: &str) -> f64 {
+        let total_shares = self.total_shares();
+        match self.lp_shares.get(lp_id) {
+            Some(shares) if total_shares > 0.0 => shares / total_shares,
+            _ => 0.0,
         }
-        // Re-solve radius to respect the invariant (keeping deposits on sphere).
-        let radius = crate::sphere::sphere_invariant(&self.sphere_amm.reserves, 0.0); // placeholder call just to access fn
-        let _ = radius; // avoid warning
-        self.sphere_amm.radius = {
-            // duplicate logic of solve_radius – small DRY violation for privacy.
-            let n = self.sphere_amm.reserves.len() as f64;
-            let sum_x: f64 = self.sphere_amm.reserves.iter().copied().sum();
-            let sum_x2: f64 = self.sphere_amm.reserves
-                .iter()
-                .map(|x| x * x)
-                .sum();
-            let a = n - 1.0;
-            if a.abs() < 1e-12 {
-                sum_x
-            } else {
-                let b = -2.0 * sum_x;
-                let c = sum_x2;
-                let disc = (b * b - 4.0 * a * c).max(0.0);
-                let r1 = (-b + disc.sqrt()) / (2.0 * a);
-                if r1 > 0.0 {
-                    r1
-                } else {
-                    (-b - disc.sqrt()) / (2.0 * a)
-                }
-            }
-        };
-        // Update shares
-        let share_delta: f64 = amounts.iter().sum();
-        *self.lp_shares.entry(lp_id.to_string()).or_default() += share_delta;
-        Ok(())
     }
 
     /// Withdraw a percentage (0..=1) of the LP's position. Returns withdrawn
-    /// amounts per token.
-    pub fn withdraw_liquidity(&mut self, lp_id: &str, percentage: f64) -> Result<Vec<f64>, String> {
+    /// amounts per token, including the LP's pro-rata slice of accrued fees.
+    pub fn withdraw_liquidity(&mut self, lp_id: &str, percentage: f64) -> Result<Vec<f64>, OrbitalError> {
         if !(0.0..=1.0).contains(&percentage) {
-            return Err("percentage must be in [0,1]".into());
+            return Err(OrbitalError::BadPercentage(percentage));
         }
         let user_shares = self.lp_shares
             .get(lp_id)
-            .ok_or_else(|| "LP id not found".to_string())?
+            .ok_or_else(|| OrbitalError::UnknownLp(lp_id.to_string()))?
             .to_owned();
         if user_shares == 0.0 {
-            return Err("LP has no shares".into());
+            return Err(OrbitalError::NoShares(lp_id.to_string()));
         }
-        let total_shares: f64 = self.lp_shares.values().copied().sum();
         let shares_to_remove = user_shares * percentage;
-        let ratio = shares_to_remove / total_shares;
+        let ratio = self.lp_fraction(lp_id) * percentage;
         // Withdraw proportional amounts
         let mut withdrawn = Vec::with_capacity(self.sphere_amm.reserves.len());
         for r in self.sphere_amm.reserves.iter_mut() {
@@ -100,34 +314,26 @@ impl OrbitalTick {
             *r -= amt;
             withdrawn.push(amt);
         }
-        // Recompute radius
-        self.sphere_amm.radius = {
-            let n = self.sphere_amm.reserves.len() as f64;
-            let sum_x: f64 = self.sphere_amm.reserves.iter().copied().sum();
-            let sum_x2: f64 = self.sphere_amm.reserves
-                .iter()
-                .map(|x| x * x)
-                .sum();
-            let a = n - 1.0;
-            if a.abs() < 1e-12 {
-                sum_x
-            } else {
-                let b = -2.0 * sum_x;
-                let c = sum_x2;
-                let disc = (b * b - 4.0 * a * c).max(0.0);
-                let r1 = (-b + disc.sqrt()) / (2.0 * a);
-                if r1 > 0.0 {
-                    r1
-                } else {
-                    (-b - disc.sqrt()) / (2.0 * a)
-                }
-            }
-        };
+        self.fees_accrued.resize(withdrawn.len(), 0.0);
+        for (w, f) in withdrawn.iter_mut().zip(self.fees_accrued.iter_mut()) {
+            let fee_share = *f * ratio;
+            *f -= fee_share;
+            *w += fee_share;
+        }
+        // Shrink the sphere and the plane with the reserves.
+        self.sphere_amm.radius *= 1.0 - ratio;
+        self.plane_constant *= 1.0 - ratio;
         // Update shares bookkeeping
         if percentage >= 1.0 - 1e-12 {
             self.lp_shares.remove(lp_id);
+            self.lp_deposits.remove(lp_id);
+            self.lp_fee_buy_in.remove(lp_id);
         } else {
             *self.lp_shares.get_mut(lp_id).unwrap() -= shares_to_remove;
+            let bases = [&mut self.lp_deposits, &mut self.lp_fee_buy_in];
+            for d in bases.into_iter().filter_map(|b| b.get_mut(lp_id)).flatten() {
+                *d *= 1.0 - percentage;
+            }
         }
         Ok(withdrawn)
     }
@@ -140,21 +346,414 @@ impl OrbitalTick {
 
 /* ------------------------------------------------------------- */
 
-#[derive(Clone, Serialize, Deserialize)]
+/// Portion of a routed trade executed inside a single tick during one segment.
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct TickFill {
+    pub tick_index: usize,
+    /// Segment of the trade, bumped each time a tick reaches its boundary.
+    pub segment: usize,
+    /// Gross input, fee included.
+    pub amount_in: f64,
+    pub amount_out: f64,
+    pub fee: f64,
+}
+
+/// Result of a dry-run trade against a `MultiTickAMM`.
+#[derive(Clone, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct RouteQuote {
+    pub amount_in: f64,
+    pub amount_out: f64,
+    /// Input paid per unit of output.
+    pub effective_price: f64,
+    /// Aggregated spot price before the trade, in the same units.
+    pub spot_price: f64,
+    /// Relative deviation of `effective_price` from `spot_price`.
+    pub price_impact: f64,
+    /// Total fee paid across all fills, in units of the input token.
+    pub fee: f64,
+    /// Global reserves after the trade would execute.
+    pub reserves_after: Vec<f64>,
+    pub fills: Vec<TickFill>,
+}
+
+/// Capital efficiency of a tick. Inside its cap no token ever drops below
+/// `min_reserve`, so that part of every reserve is virtual and LPs only fund
+/// the rest.
+#[derive(Clone, Serialize)]
+pub struct TickEfficiency {
+    pub min_reserve: f64,
+    pub max_reserve: f64,
+    /// Per-token reserves that never need to be deposited.
+    pub virtual_reserves: Vec<f64>,
+    /// Per-token reserves LPs actually have to fund right now.
+    pub real_reserves: Vec<f64>,
+    /// Capital a full-range sphere of the same radius needs at the
+    /// equal-price point, divided by what this tick needs there.
+    pub capital_efficiency: f64,
+}
+
+/// Capital efficiency across all ticks of a pool.
+#[derive(Clone, Serialize)]
+pub struct PoolEfficiency {
+    pub ticks: Vec<TickEfficiency>,
+    /// Same ratio as `TickEfficiency::capital_efficiency`, summed over ticks.
+    pub capital_efficiency: f64,
+}
+
+/// Consolidated torus invariant of a set of ticks sitting at a common price.
+///
+/// Interior ticks behave as one sphere of radius `R = Σ rᵢ`; boundary ticks
+/// are pinned to their planes and behave as one (n−1)-sphere of radius
+/// `S = Σ sᵢ` in the subspace orthogonal to v = (1, …, 1)/√n, offset along v
+/// by `K = Σ kᵢ`. For total reserves `x` with α = x·v and w = x − αv:
+///
+///   ((α − K) − R√n)² + (‖w‖ − S)² = R²
+#[derive(Clone, Serialize)]
+pub struct TorusInvariant {
+    /// Tick indices currently inside their caps.
+    pub interior: Vec<usize>,
+    /// Tick indices pinned to their planes.
+    pub boundary: Vec<usize>,
+    pub interior_radius: f64,
+    pub boundary_plane: f64,
+    pub boundary_radius: f64,
+    n: usize,
+}
+
+impl TorusInvariant {
+    /// Consolidate `members` of `ticks` for total reserves `reserves`. Ticks
+    /// are sorted by `normalized_plane` and the split point is chosen so that
+    /// every boundary tick has k/r ≤ δ < k/r of every interior tick, where
+    /// δ = (α − K)/R. Exact ties go to the boundary when the upcoming trade
+    /// raises δ (`rising`), to the interior otherwise. `None` if no tick would
+    /// be interior.
+    pub fn from_ticks(
+        ticks: &[OrbitalTick],
+        members: &[usize],
+        reserves: &[f64],
+        rising: bool
+    ) -> Option<Self> {
+        let n = reserves.len();
+        let (alpha, _) = decompose_reserves(reserves);
+        let mut order = members.to_vec();
+        order.sort_by(|&a, &b| ticks[a].normalized_plane().total_cmp(&ticks[b].normalized_plane()));
+        let theta: Vec<f64> = order
+            .iter()
+            .map(|&t| ticks[t].normalized_plane())
+            .collect();
+
+        let mut chosen = None;
+        for m in 0..order.len() {
+            let plane: f64 = order[..m]
+                .iter()
+                .map(|&t| ticks[t].plane_constant)
+                .sum();
+            let radius: f64 = order[m..]
+                .iter()
+                .map(|&t| ticks[t].sphere_amm.radius)
+                .sum();
+            let depth = (alpha - plane) / radius;
+            let valid = (m == 0 || theta[m - 1] <= depth + 1e-9) && depth < theta[m] + 1e-9;
+            if valid {
+                chosen = Some((m, depth));
+                if !rising {
+                    break;
+                }
+            }
+        }
+        let (m, depth) = chosen?;
+        // A rising trade tied with the last interior tick would pin it too.
+        if rising && m + 1 == order.len() && depth >= theta[m] - 1e-9 {
+            return None;
+        }
+        let boundary = order[..m].to_vec();
+        let interior = order[m..].to_vec();
+        Some(Self {
+            interior_radius: interior
+                .iter()
+                .map(|&t| ticks[t].sphere_amm.radius)
+                .sum(),
+            boundary_plane: boundary
+                .iter()
+                .map(|&t| ticks[t].plane_constant)
+                .sum(),
+            boundary_radius: boundary
+                .iter()
+                .map(|&t| ticks[t].boundary_radius())
+                .sum(),
+            interior,
+            boundary,
+            n,
+        })
+    }
+
+    fn sqrt_n(&self) -> f64 {
+        (self.n as f64).sqrt()
+    }
+
+    /// Invariant residual; zero when `reserves` lie on the torus.
+    pub fn value(&self, reserves: &[f64]) -> f64 {
+        let (alpha, orth) = decompose_reserves(reserves);
+        let norm = orth
+            .iter()
+            .map(|w| w * w)
+            .sum::<f64>()
+            .sqrt();
+        let r = self.interior_radius;
+        let par = alpha - self.boundary_plane - r * self.sqrt_n();
+        let perp = norm - self.boundary_radius;
+        par * par + perp * perp - r * r
+    }
+
+    /// ∂value/∂xₖ.
+    fn partial(&self, reserves: &[f64], k: usize) -> f64 {
+        let (alpha, orth) = decompose_reserves(reserves);
+        let norm = orth
+            .iter()
+            .map(|w| w * w)
+            .sum::<f64>()
+            .sqrt();
+        let r = self.interior_radius;
+        let par = alpha - self.boundary_plane - r * self.sqrt_n();
+        let perp = if norm > 1e-12 {
+            ((norm - self.boundary_radius) * orth[k]) / norm
+        } else {
+            0.0
+        };
+        (2.0 * par) / self.sqrt_n() + 2.0 * perp
+    }
+
+    /// Normalised depth δ = (α − K)/R of the consolidated interior sphere.
+    pub fn depth(&self, reserves: &[f64]) -> f64 {
+        let (alpha, _) = decompose_reserves(reserves);
+        (alpha - self.boundary_plane) / self.interior_radius
+    }
+
+    /// Whether an infinitesimal `i` → `j` trade raises δ.
+    fn is_rising(&self, reserves: &[f64], i: usize, j: usize) -> bool {
+        // Along the trade dΔy/dΔx = Fᵢ/Fⱼ, and α moves by (dΔx − dΔy)/√n.
+        1.0 - self.partial(reserves, i) / self.partial(reserves, j) > 0.0
+    }
+
+    /// Solve a trade against the torus. Returns `(net input, output)`.
+    pub fn solve(
+        &self,
+        reserves: &[f64],
+        i: usize,
+        j: usize,
+        amount: f64,
+        exact_out: bool
+    ) -> Result<(f64, f64), OrbitalError> {
+        let mut x = reserves.to_vec();
+        if exact_out {
+            if amount >= reserves[j] {
+                return Err(OrbitalError::InsufficientLiquidity);
+            }
+            x[j] -= amount;
+            // The residual is convex and decreasing in Δx up to the root, so
+            // Newton from zero approaches it monotonically from the left.
+            let f = |dx: f64| {
+                let mut y = x.clone();
+                y[i] += dx;
+                (self.value(&y), self.partial(&y, i))
+            };
+            let input = newton(f, 0.0, false).ok_or(OrbitalError::ComplexSolution)?;
+            Ok((input, amount))
+        } else {
+            x[i] += amount;
+            let f = |dy: f64| {
+                let mut y = x.clone();
+                y[j] -= dy;
+                (self.value(&y), -self.partial(&y, j))
+            };
+            if f(0.0).0 > 0.0 {
+                return Err(OrbitalError::ComplexSolution);
+            }
+            if f(reserves[j]).0 < 0.0 {
+                return Err(OrbitalError::InsufficientLiquidity);
+            }
+            let output = newton(f, reserves[j], true).ok_or(
+                OrbitalError::InsufficientLiquidity
+            )?;
+            Ok((amount, output))
+        }
+    }
+
+    /// First point along an `i` → `j` trade at which δ reaches the k/r of a
+    /// tick about to change state, if it comes before `remaining` (measured as
+    /// net input, or output when `exact_out`). Returns `(net input, output)`.
+    fn next_crossing(
+        &self,
+        ticks: &[OrbitalTick],
+        reserves: &[f64],
+        i: usize,
+        j: usize,
+        remaining: f64,
+        exact_out: bool
+    ) -> Option<(f64, f64)> {
+        let exit = self.interior
+            .iter()
+            .map(|&t| ticks[t].normalized_plane())
+            .fold(f64::INFINITY, f64::min);
+        let entry = self.boundary
+            .iter()
+            .map(|&t| ticks[t].normalized_plane())
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        let (alpha, orth) = decompose_reserves(reserves);
+        let n = self.n as f64;
+        let r = self.interior_radius;
+        let mut best: Option<(f64, f64)> = None;
+        for theta in [exit, entry] {
+            if !theta.is_finite() {
+                continue;
+            }
+            // At δ = θ the sum must change by D = (θR − (α − K))·√n and ‖w‖ must
+            // equal T = S + √(R² − (R√n − θR)²). With Δx = Δy + D the new
+            // orthogonal part is w₀ + Δy (eᵢ − eⱼ), which gives
+            //  2Δy² + 2(w₀ᵢ − w₀ⱼ) Δy + ‖w₀‖² − T² = 0.
+            let headroom = (theta * r - (alpha - self.boundary_plane)) * n.sqrt();
+            let par = r * n.sqrt() - theta * r;
+            let target = self.boundary_radius + (r * r - par * par).max(0.0).sqrt();
+            let mut w0 = orth.clone();
+            for (k, w) in w0.iter_mut().enumerate() {
+                *w += headroom * ((if k == i { 1.0 } else { 0.0 }) - 1.0 / n);
+            }
+            let b = 2.0 * (w0[i] - w0[j]);
+            let c =
+                w0
+                    .iter()
+                    .map(|w| w * w)
+                    .sum::<f64>() -
+                target * target;
+            let disc = b * b - 8.0 * c;
+            if disc < 0.0 {
+                continue;
+            }
+            for dy in [(-b - disc.sqrt()) / 4.0, (-b + disc.sqrt()) / 4.0] {
+                let dx = dy + headroom;
+                let along = if exact_out { dy } else { dx };
+                if dy < 0.0 || dx <= 0.0 || along <= 1e-9 || along >= remaining {
+                    continue;
+                }
+                // Discard intersections on the far side of the torus.
+                match self.solve(reserves, i, j, dx, false) {
+                    Ok((_, out)) if (out - dy).abs() <= 1e-6 * (1.0 + dy) => {}
+                    _ => {
+                        continue;
+                    }
+                }
+                let better = match best {
+                    Some((bx, by)) => along < (if exact_out { by } else { bx }),
+                    None => true,
+                };
+                if better {
+                    best = Some((dx, dy));
+                }
+            }
+        }
+        best
+    }
+
+    /// Write the consolidated state back into the member ticks: boundary ticks
+    /// sit on their plane along the common orthogonal direction, interior
+    /// ticks are scaled copies of what remains.
+    fn distribute(&self, ticks: &mut [OrbitalTick], reserves: &[f64]) {
+        let (_, orth) = decompose_reserves(reserves);
+        let norm = orth
+            .iter()
+            .map(|w| w * w)
+            .sum::<f64>()
+            .sqrt();
+        let sqrt_n = self.sqrt_n();
+        let mut rest = reserves.to_vec();
+        for &b in &self.boundary {
+            let s = ticks[b].boundary_radius();
+            let k = ticks[b].plane_constant;
+            let pinned: Vec<f64> = orth
+                .iter()
+                .map(|w| k / sqrt_n + (if norm > 1e-12 { (s * w) / norm } else { 0.0 }))
+                .collect();
+            for (r, p) in rest.iter_mut().zip(&pinned) {
+                *r -= *p;
+            }
+            ticks[b].sphere_amm.reserves = pinned;
+        }
+        for &t in &self.interior {
+            let scale = ticks[t].sphere_amm.radius / self.interior_radius;
+            ticks[t].sphere_amm.reserves = rest
+                .iter()
+                .map(|x| x * scale)
+                .collect();
+        }
+    }
+}
+
+/// Safeguarded Newton iteration for a monotone residual. Starts at `x0` and
+/// keeps a bracket of the sign change it has seen; `increasing` tells which
+/// side of the root a positive residual lies on.
+fn newton(f: impl Fn(f64) -> (f64, f64), x0: f64, increasing: bool) -> Option<f64> {
+    let mut x = x0;
+    let mut lo = if increasing { 0.0 } else { x0 };
+    let mut hi = if increasing { x0 } else { f64::INFINITY };
+    for _ in 0..200 {
+        let (v, dv) = f(x);
+        if v == 0.0 {
+            return Some(x);
+        }
+        if (v > 0.0) == increasing {
+            hi = x;
+        } else {
+            lo = x;
+        }
+        let mut next = x - v / dv;
+        if !next.is_finite() || next <= lo || next >= hi {
+            if !hi.is_finite() {
+                return None;
+            }
+            next = 0.5 * (lo + hi);
+        }
+        if (next - x).abs() <= 1e-13 * (1.0 + x.abs()) {
+            return Some(next);
+        }
+        x = next;
+    }
+    None
+}
+
+/// State file used when no other path is configured.
+pub const DEFAULT_STATE_FILE: &str = "multi_tick.json";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MultiTickAMM {
     pub ticks: Vec<OrbitalTick>,
     pub global_reserves: Vec<f64>,
     pub token_names: Vec<String>,
+    /// Where `save_state` writes to. Not part of the persisted state.
+    #[serde(skip)]
+    state_path: Option<PathBuf>,
 }
 
 impl MultiTickAMM {
     pub fn new(token_names: Vec<String>) -> Self {
         let m = token_names.len();
-        Self { ticks: Vec::new(), global_reserves: vec![0.0; m], token_names }
+        Self { ticks: Vec::new(), global_reserves: vec![0.0; m], token_names, state_path: None }
+    }
+
+    /// Persist this pool to `path` instead of `DEFAULT_STATE_FILE`.
+    pub fn with_state_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state_path = Some(path.into());
+        self
+    }
+
+    pub fn state_path(&self) -> &Path {
+        self.state_path.as_deref().unwrap_or(Path::new(DEFAULT_STATE_FILE))
     }
 
     /// Recompute the global reserve vector from constituent ticks.
-    fn recompute_global_reserves(&mut self) {
+    pub fn recompute_global_reserves(&mut self) {
         self.global_reserves.fill(0.0);
         for tick in &self.ticks {
             for (g, r) in self.global_reserves.iter_mut().zip(&tick.sphere_amm.reserves) {
@@ -163,11 +762,112 @@ impl MultiTickAMM {
         }
     }
 
-    pub fn add_tick(&mut self, plane_constant: f64, reserves: Vec<f64>) {
-        assert_eq!(reserves.len(), self.token_names.len(), "reserve length mismatch");
-        let tick = OrbitalTick::new(self.token_names.clone(), reserves, plane_constant);
+    pub fn add_tick(
+        &mut self,
+        plane_constant: f64,
+        reserves: Vec<f64>
+    ) -> Result<(), OrbitalError> {
+        self.add_tick_with_fee(plane_constant, reserves, 0)
+    }
+
+    /// Add a tick over `reserves`, which must be finite, non-negative and
+    /// lie on a sphere of positive radius.
+    pub fn add_tick_with_fee(
+        &mut self,
+        plane_constant: f64,
+        reserves: Vec<f64>,
+        fee_bps: u32
+    ) -> Result<(), OrbitalError> {
+        if reserves.len() != self.token_names.len() {
+            return Err(OrbitalError::LengthMismatch {
+                expected: self.token_names.len(),
+                got: reserves.len(),
+            });
+        }
+        check_fee(fee_bps)?;
+        SphereAMM::checked_radius(&reserves)?;
+        if !plane_constant.is_finite() {
+            return Err(OrbitalError::NotFinite(plane_constant));
+        }
+        let tick = OrbitalTick::new_with_fee(
+            self.token_names.clone(),
+            reserves,
+            plane_constant,
+            fee_bps
+        );
         self.ticks.push(tick);
         self.recompute_global_reserves();
+        Ok(())
+    }
+
+    /// Remove tick `index` and return it, reserves and LP shares included.
+    /// Later ticks shift down by one.
+    pub fn remove_tick(&mut self, index: usize) -> Result<OrbitalTick, OrbitalError> {
+        if index >= self.ticks.len() {
+            return Err(OrbitalError::UnknownTick(index));
+        }
+        let tick = self.ticks.remove(index);
+        self.recompute_global_reserves();
+        Ok(tick)
+    }
+
+    /// Add a tick from raw reserves whose plane constant is derived from a
+    /// depeg price instead of given directly. Returns the derived parameters.
+    pub fn add_tick_from_depeg(
+        &mut self,
+        reserves: Vec<f64>,
+        depeg_price: f64,
+        fee_bps: u32
+    ) -> Result<DepegTickParams, OrbitalError> {
+        if reserves.len() != self.token_names.len() {
+            return Err(OrbitalError::LengthMismatch {
+                expected: self.token_names.len(),
+                got: reserves.len(),
+            });
+        }
+        let radius = SphereAMM::solve_radius(&reserves);
+        let params = depeg_tick_params(radius, reserves.len(), depeg_price)?;
+        let (parallel, _) = decompose_reserves(&reserves);
+        if parallel > params.plane_constant + 1e-6 {
+            return Err(OrbitalError::OutsideTick);
+        }
+        self.add_tick_with_fee(params.plane_constant, reserves, fee_bps)?;
+        Ok(params)
+    }
+
+    /// Capital efficiency of every tick and of the pool as a whole.
+    pub fn efficiency_report(&self) -> PoolEfficiency {
+        let n = self.token_names.len();
+        let ticks: Vec<TickEfficiency> = self.ticks
+            .iter()
+            .map(|t| t.efficiency())
+            .collect();
+        let mut full_range = 0.0;
+        let mut required = 0.0;
+        for (tick, eff) in self.ticks.iter().zip(&ticks) {
+            let q = equal_price_point(tick.sphere_amm.radius, n);
+            full_range += q;
+            required += (q - eff.min_reserve).max(0.0);
+        }
+        let capital_efficiency = if required > 1e-12 {
+            full_range / required
+        } else if full_range > 0.0 {
+            f64::INFINITY
+        } else {
+            1.0
+        };
+        PoolEfficiency { ticks, capital_efficiency }
+    }
+
+    /// Fees accrued per token, summed over all ticks.
+    pub fn total_fees(&self) -> Vec<f64> {
+        let mut totals = vec![0.0; self.token_names.len()];
+        for tick in &self.ticks {
+            for (t, f) in totals.iter_mut().zip(&tick.fees_accrued) {
+                *t += *f;
+            }
+        }
+        totals
     }
 
     /// Classify ticks into interior and boundary indices.
@@ -184,41 +884,264 @@ impl MultiTickAMM {
         (interior, boundary)
     }
 
-    /// Very naive routing: route through ticks in ascending plane_constant order
-    /// until the amount is fully executed.
-    pub fn route_trade(&mut self, from: &str, to: &str, mut amount: f64) -> Result<f64, String> {
-        let mut total_output = 0.0;
-        // Sort tick indices by plane_constant
-        let mut idxs: Vec<usize> = (0..self.ticks.len()).collect();
-        idxs.sort_unstable_by(|&a, &b|
-            self.ticks[a].plane_constant.partial_cmp(&self.ticks[b].plane_constant).unwrap()
-        );
-        for idx in idxs {
-            if amount <= 0.0 {
-                break;
+    /// Route an exact-input trade against the consolidated torus invariant
+    /// and return the total output. State is left untouched on failure.
+    pub fn route_trade(&mut self, from: &str, to: &str, amount: f64) -> Result<f64, OrbitalError> {
+        let mut next = self.clone();
+        let fills = next.fill_segments(from, to, amount, false)?;
+        *self = next;
+        Ok(fills.iter().map(|f| f.amount_out).sum())
+    }
+
+    /// Exact-output counterpart of `route_trade`: pull `amount_out` of `to`
+    /// through the ticks and return the total input charged. State is only
+    /// committed if the whole fill succeeds within `max_amount_in`.
+    pub fn route_trade_exact_out(
+        &mut self,
+        from: &str,
+        to: &str,
+        amount_out: f64,
+        max_amount_in: f64
+    ) -> Result<f64, OrbitalError> {
+        let mut next = self.clone();
+        let fills = next.fill_segments(from, to, amount_out, true)?;
+        let total_input: f64 = fills.iter().map(|f| f.amount_in).sum();
+        if total_input > max_amount_in {
+            return Err(OrbitalError::SlippageExceeded {
+                required: total_input,
+                max: max_amount_in,
+            });
+        }
+        *self = next;
+        Ok(total_input)
+    }
+
+    /// Quote an exact-input trade without touching state.
+    pub fn quote_trade(&self, from: &str, to: &str, amount: f64) -> Result<RouteQuote, OrbitalError> {
+        let mut sim = self.clone();
+        let fills = sim.fill_segments(from, to, amount, false)?;
+        self.build_quote(from, to, &sim, fills)
+    }
+
+    /// Quote an exact-output trade without touching state.
+    pub fn quote_trade_exact_out(
+        &self,
+        from: &str,
+        to: &str,
+        amount_out: f64
+    ) -> Result<RouteQuote, OrbitalError> {
+        let mut sim = self.clone();
+        let fills = sim.fill_segments(from, to, amount_out, true)?;
+        self.build_quote(from, to, &sim, fills)
+    }
+
+    fn build_quote(
+        &self,
+        from: &str,
+        to: &str,
+        after: &MultiTickAMM,
+        fills: Vec<TickFill>
+    ) -> Result<RouteQuote, OrbitalError> {
+        let spot_price = self.get_aggregated_price(from, to)?;
+        let amount_in: f64 = fills.iter().map(|f| f.amount_in).sum();
+        let amount_out: f64 = fills.iter().map(|f| f.amount_out).sum();
+        let effective_price = amount_in / amount_out;
+        Ok(RouteQuote {
+            amount_in,
+            amount_out,
+            effective_price,
+            spot_price,
+            price_impact: effective_price / spot_price - 1.0,
+            fee: fills.iter().map(|f| f.fee).sum(),
+            reserves_after: after.global_reserves.clone(),
+            fills,
+        })
+    }
+
+    fn token_index(&self, token: &str) -> Result<usize, OrbitalError> {
+        self.token_names
+            .iter()
+            .position(|t| t == token)
+            .ok_or_else(|| OrbitalError::UnknownToken(token.to_string()))
+    }
+
+    /// Ticks that take part in consolidated trading. Ticks left outside their
+    /// own cap (e.g. by `/api/set-reserves`) are frozen until fixed by hand.
+    fn member_ticks(&self) -> Vec<usize> {
+        (0..self.ticks.len())
+            .filter(|&t| {
+                let tick = &self.ticks[t];
+                tick.sphere_amm.radius > 0.0 && !tick.is_exterior()
+            })
+            .collect()
+    }
+
+    fn member_reserves(&self, members: &[usize]) -> Vec<f64> {
+        let mut total = vec![0.0; self.token_names.len()];
+        for &t in members {
+            for (g, r) in total.iter_mut().zip(&self.ticks[t].sphere_amm.reserves) {
+                *g += *r;
             }
-            let tick = &mut self.ticks[idx];
-            let available = tick.sphere_amm.reserves[tick.sphere_amm.index_of(from)?];
-            if available <= 1e-12 {
-                continue;
+        }
+        total
+    }
+
+    /// Consolidated torus invariant for the current state.
+    pub fn torus_invariant(&self) -> Result<TorusInvariant, OrbitalError> {
+        let members = self.member_ticks();
+        let reserves = self.member_reserves(&members);
+        TorusInvariant::from_ticks(&self.ticks, &members, &reserves, false).ok_or(
+            OrbitalError::NoInteriorLiquidity
+        )
+    }
+
+    /// Execute a trade against the consolidated torus invariant, in segments.
+    /// Within a segment the interior/boundary split is fixed; a segment ends
+    /// when a tick reaches its plane or leaves it again, after which the torus
+    /// is rebuilt and the remainder is solved. Tick reserves are written back
+    /// after every segment. Each tick charges its own fee on the net input it
+    /// takes, so the rate the trader pays depends on how the fill splits;
+    /// for an exact input the net amount of a segment is refined until the
+    /// ticks' gross inputs add up to what is left. `amount` is the gross
+    /// input, or the exact output when `exact_out` is set.
+    fn fill_segments(
+        &mut self,
+        from: &str,
+        to: &str,
+        amount: f64,
+        exact_out: bool
+    ) -> Result<Vec<TickFill>, OrbitalError> {
+        if amount <= 0.0 {
+            return Err(OrbitalError::NonPositiveAmount);
+        }
+        let i = self.token_index(from)?;
+        let j = self.token_index(to)?;
+        if i == j {
+            return Err(OrbitalError::SameToken);
+        }
+        let members = self.member_ticks();
+        if members.is_empty() {
+            return Err(OrbitalError::NoInteriorLiquidity);
+        }
+        let total_radius: f64 = members
+            .iter()
+            .map(|&t| self.ticks[t].sphere_amm.radius)
+            .sum();
+        // First guess at the blended rate; refined from each actual split.
+        let mut fee_rate: f64 = members
+            .iter()
+            .map(|&t| (self.ticks[t].sphere_amm.radius / total_radius) * self.ticks[t].fee_rate())
+            .sum();
+        // Net input each member tick took between two sets of tick reserves,
+        // with the fee it charges on top: fee / (net + fee) is its own rate.
+        let split = |before: &[OrbitalTick], after: &[OrbitalTick]| -> Vec<(f64, f64)> {
+            members
+                .iter()
+                .map(|&t| {
+                    let net = after[t].sphere_amm.reserves[i] - before[t].sphere_amm.reserves[i];
+                    let rate = after[t].fee_rate();
+                    (net, (net.max(0.0) * rate) / (1.0 - rate))
+                })
+                .collect()
+        };
+
+        let mut reserves = self.member_reserves(&members);
+        let mut remaining = amount;
+        if !exact_out && amount * (1.0 - fee_rate) <= 1e-12 {
+            // Nothing is left to trade once the fee is taken.
+            return Err(OrbitalError::NonPositiveAmount);
+        }
+        let mut fills = Vec::new();
+        let mut segment = 0;
+        while remaining > 1e-12 {
+            if segment > 2 * members.len() + 2 {
+                return Err(OrbitalError::NotConverged);
             }
-            let trade_in = amount.min(available * 0.9); // keep small buffer
-            if trade_in <= 0.0 {
-                continue;
+            let probe = TorusInvariant::from_ticks(&self.ticks, &members, &reserves, false)
+                .ok_or(OrbitalError::NoInteriorLiquidity)?;
+            let rising = probe.is_rising(&reserves, i, j);
+            let torus = TorusInvariant::from_ticks(&self.ticks, &members, &reserves, rising)
+                .ok_or(OrbitalError::NoInteriorLiquidity)?;
+
+            let mut attempts = 0;
+            let (ticks, next, out, gross, crossed) = loop {
+                let budget = if exact_out { remaining } else { remaining * (1.0 - fee_rate) };
+                let crossing = torus.next_crossing(&self.ticks, &reserves, i, j, budget, exact_out);
+                let crossed = crossing.is_some();
+                let (net_in, out) = match crossing {
+                    Some(crossing) => crossing,
+                    None => torus.solve(&reserves, i, j, budget, exact_out)?,
+                };
+                let mut next = reserves.clone();
+                next[i] += net_in;
+                next[j] -= out;
+                let mut ticks = self.ticks.clone();
+                torus.distribute(&mut ticks, &next);
+                let gross: f64 = split(&self.ticks, &ticks)
+                    .iter()
+                    .map(|(net, fee)| net + fee)
+                    .sum();
+                if gross > 0.0 {
+                    fee_rate = 1.0 - net_in / gross;
+                }
+                let tolerance = 1e-12 * amount.max(1.0);
+                let fits = if crossed {
+                    gross <= remaining + tolerance
+                } else {
+                    (gross - remaining).abs() <= tolerance
+                };
+                if exact_out || fits {
+                    break (ticks, next, out, gross, crossed);
+                }
+                attempts += 1;
+                if attempts > 50 {
+                    return Err(OrbitalError::NotConverged);
+                }
+            };
+
+            let fees = split(&self.ticks, &ticks);
+            let before: Vec<f64> = members
+                .iter()
+                .map(|&t| self.ticks[t].sphere_amm.reserves[j])
+                .collect();
+            self.ticks = ticks;
+            reserves = next;
+            if
+                members
+                    .iter()
+                    .any(|&t| self.ticks[t].sphere_amm.reserves.iter().any(|&x| x < -1e-9))
+            {
+                return Err(OrbitalError::InsufficientLiquidity);
+            }
+            if members.iter().any(|&t| !self.ticks[t].sphere_amm.check_invariant()) {
+                return Err(OrbitalError::InvariantViolation);
+            }
+
+            for ((&t, (net, fee)), out_before) in members.iter().zip(fees).zip(before) {
+                let tick = &mut self.ticks[t];
+                tick.accrue_fee(i, fee);
+                fills.push(TickFill {
+                    tick_index: t,
+                    segment,
+                    amount_in: net + fee,
+                    amount_out: out_before - tick.sphere_amm.reserves[j],
+                    fee,
+                });
             }
-            let out = tick.sphere_amm.swap(from, to, trade_in)?;
-            amount -= trade_in;
-            total_output += out;
+            remaining = match (exact_out, crossed) {
+                (true, _) => remaining - out,
+                (false, true) => remaining - gross,
+                // The segment was sized to take everything that was left.
+                (false, false) => 0.0,
+            };
+            segment += 1;
         }
         self.recompute_global_reserves();
-        if amount > 1e-8 {
-            return Err("Not enough liquidity across ticks to satisfy trade".into());
-        }
-        Ok(total_output)
+        Ok(fills)
     }
 
     /// Aggregated spot price across ticks weighted by token liquidity.
-    pub fn get_aggregated_price(&self, from: &str, to: &str) -> Result<f64, String> {
+    pub fn get_aggregated_price(&self, from: &str, to: &str) -> Result<f64, OrbitalError> {
         let mut num = 0.0;
         let mut denom = 0.0;
         for tick in &self.ticks {
@@ -231,35 +1154,143 @@ impl MultiTickAMM {
             denom += weight;
         }
         if denom == 0.0 {
-            return Err("No liquidity for base token across ticks".into());
+            return Err(OrbitalError::InsufficientLiquidity);
         }
         Ok(num / denom)
     }
 
-    /// Save state to disk in `multi_tick.json`.
-    pub fn save_state(&self) {
-        let json = serde_json::to_string_pretty(self).expect("serialize");
-        fs::write("multi_tick.json", json).expect("write file");
+    /// Atomically save state to `state_path()`, creating parent directories.
+    pub fn save_state(&self) -> Result<(), OrbitalError> {
+        persist::save_json(self, self.state_path())
+    }
+
+    /// Load state from `path`, or create an empty pool if the file does not
+    /// exist. Older schema versions are migrated; unreadable files are an
+    /// error. The returned pool saves back to `path`.
+    pub fn load_state(
+        path: impl AsRef<Path>,
+        token_names: Vec<String>
+    ) -> Result<Self, OrbitalError> {
+        let path = path.as_ref();
+        let amm: Self = persist
+            ::load_json(path, migrate_multi_tick)?
+            .unwrap_or_else(|| Self::new(token_names));
+        // Routing divides by radii and orders ticks by k/r; a hand-edited
+        // file must not smuggle in a tick where either is meaningless.
+        for (i, tick) in amm.ticks.iter().enumerate() {
+            let radius = tick.sphere_amm.radius;
+            if !(radius.is_finite() && radius > 0.0 && tick.plane_constant.is_finite()) {
+                return Err(
+                    OrbitalError::CorruptState(
+                        format!(
+                            "{}: tick {} has radius {} and plane {}",
+                            path.display(),
+                            i,
+                            radius,
+                            tick.plane_constant
+                        )
+                    )
+                );
+            }
+        }
+        Ok(amm.with_state_path(path))
     }
+}
 
-    /// Load state or create empty.
-    pub fn load_state(token_names: Vec<String>) -> Self {
-        match fs::read_to_string("multi_tick.json") {
-            Ok(bytes) => serde_json::from_str(&bytes).unwrap_or_else(|_| Self::new(token_names)),
-            Err(_) => Self::new(token_names),
+/// Schema migrations for `MultiTickAMM` state files.
+fn migrate_multi_tick(doc: &mut serde_json::Value, from: u32) -> Result<(), String> {
+    match from {
+        // v0 predates fees; `global_reserves` may be stale after hand edits.
+        // Its ticks counted as interior while r_parallel was *above* the
+        // plane, so the planes bound nothing and every tick is migrated to a
+        // full-range one that keeps trading where it did.
+        0 => {
+            let ticks = doc
+                .get_mut("ticks")
+                .and_then(|t| t.as_array_mut())
+                .ok_or("missing ticks")?;
+            let mut global: Vec<f64> = Vec::new();
+            for tick in ticks.iter_mut() {
+                let reserves: Vec<f64> = tick
+                    .pointer("/sphere_amm/reserves")
+                    .and_then(|r| serde_json::from_value(r.clone()).ok())
+                    .ok_or("tick without reserves")?;
+                global.resize(reserves.len().max(global.len()), 0.0);
+                for (g, r) in global.iter_mut().zip(&reserves) {
+                    *g += r;
+                }
+                let radius = tick
+                    .pointer("/sphere_amm/radius")
+                    .and_then(|r| r.as_f64())
+                    .unwrap_or_else(|| SphereAMM::solve_radius(&reserves));
+                let (parallel, _) = decompose_reserves(&reserves);
+                let plane = full_range_plane(radius, reserves.len()).max(parallel);
+                let tick = tick.as_object_mut().ok_or("tick is not an object")?;
+                tick.insert("plane_constant".into(), plane.into());
+                tick.entry("fee_bps").or_insert(0.into());
+                tick.entry("fees_accrued").or_insert(serde_json::json!(vec![0.0; reserves.len()]));
+            }
+            if !global.is_empty() {
+                doc["global_reserves"] = serde_json::json!(global);
+            }
+            Ok(())
+        }
+        // v1 minted one share per deposited token and left the initial
+        // reserves unowned. Give what the shares don't cover to the creator
+        // and take the current slice as the deposit basis of any LP without
+        // a recorded one.
+        1 => {
+            let ticks = doc
+                .get_mut("ticks")
+                .and_then(|t| t.as_array_mut())
+                .ok_or("missing ticks")?;
+            for tick in ticks.iter_mut() {
+                let field = |name: &str| tick.get(name).cloned().unwrap_or_default();
+                let reserves: Vec<f64> = serde_json
+                    ::from_value(field("sphere_amm")["reserves"].clone())
+                    .map_err(|e| format!("tick reserves: {}", e))?;
+                let mut shares: HashMap<String, f64> = serde_json
+                    ::from_value(field("lp_shares"))
+                    .map_err(|e| format!("tick lp_shares: {}", e))?;
+                let mut deposits: HashMap<String, Vec<f64>> = match field("lp_deposits") {
+                    serde_json::Value::Null => HashMap::new(),
+                    v => serde_json::from_value(v).map_err(|e| format!("tick lp_deposits: {}", e))?,
+                };
+                let unowned = reserves.iter().sum::<f64>() - shares.values().sum::<f64>();
+                if unowned > 0.0 {
+                    *shares.entry(CREATOR_LP.to_string()).or_default() += unowned;
+                }
+                let total: f64 = shares.values().sum();
+                if total > 0.0 {
+                    for (lp_id, lp_shares) in &shares {
+                        deposits.entry(lp_id.clone()).or_insert_with(|| {
+                            reserves
+                                .iter()
+                                .map(|r| (r * lp_shares) / total)
+                                .collect()
+                        });
+                    }
+                }
+                let tick = tick.as_object_mut().ok_or("tick is not an object")?;
+                tick.insert("lp_shares".into(), serde_json::json!(shares));
+                tick.insert("lp_deposits".into(), serde_json::json!(deposits));
+            }
+            Ok(())
         }
+        v => Err(format!("no migration from schema version {}", v)),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::journal::Operation;
 
     #[test]
     fn test_tick_state() {
         let names = vec!["USDC".into(), "USDT".into()];
         let reserves = vec![100.0, 100.0];
-        let plane_constant = 50.0;
+        let plane_constant = 150.0;
         let tick = OrbitalTick::new(names, reserves, plane_constant);
         assert!(tick.is_interior());
     }
@@ -268,9 +1299,507 @@ mod tests {
     fn test_multi_tick_routing() {
         let names = vec!["USDC".into(), "USDT".into()];
         let mut multi = MultiTickAMM::new(names.clone());
-        multi.add_tick(50.0, vec![100.0, 100.0]);
-        multi.add_tick(70.0, vec![50.0, 50.0]);
+        multi.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        multi.add_tick(75.0, vec![50.0, 50.0]).unwrap();
         let out = multi.route_trade("USDC", "USDT", 30.0).unwrap();
         assert!(out > 0.0);
     }
+
+    #[test]
+    fn test_add_tick_rejects_invalid_reserves() {
+        let mut multi = MultiTickAMM::new(vec!["USDC".into(), "USDT".into(), "DAI".into()]);
+        let code = |r: Result<(), OrbitalError>| r.unwrap_err().code();
+        // No real radius puts one token at 1000 and the others at zero.
+        assert_eq!(code(multi.add_tick(1800.0, vec![1000.0, 0.0, 0.0])), "invalid_reserves");
+        assert_eq!(code(multi.add_tick(1800.0, vec![1000.0, -1.0, 1.0])), "invalid_reserves");
+        assert_eq!(code(multi.add_tick(1800.0, vec![f64::NAN; 3])), "invalid_reserves");
+        assert_eq!(code(multi.add_tick(1800.0, vec![1000.0; 2])), "length_mismatch");
+        assert!(multi.ticks.is_empty());
+    }
+
+    #[test]
+    fn test_multi_tick_exact_out() {
+        let names = vec!["USDC".into(), "USDT".into()];
+        let mut multi = MultiTickAMM::new(names);
+        multi.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        multi.add_tick(75.0, vec![50.0, 50.0]).unwrap();
+        let before = multi.global_reserves.clone();
+        let input = multi.route_trade_exact_out("USDC", "USDT", 30.0, 1000.0).unwrap();
+        assert!(input > 0.0);
+        assert!((before[1] - multi.global_reserves[1] - 30.0).abs() < 1e-9);
+        assert!((multi.global_reserves[0] - before[0] - input).abs() < 1e-9);
+
+        let snapshot = multi.global_reserves.clone();
+        assert!(multi.route_trade_exact_out("USDC", "USDT", 10.0, 0.5).is_err());
+        assert_eq!(multi.global_reserves, snapshot);
+    }
+
+    #[test]
+    fn test_quote_matches_execution() {
+        let names = vec!["USDC".into(), "USDT".into()];
+        let mut multi = MultiTickAMM::new(names);
+        multi.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        multi.add_tick(75.0, vec![50.0, 50.0]).unwrap();
+        let before = multi.global_reserves.clone();
+        let quote = multi.quote_trade("USDC", "USDT", 30.0).unwrap();
+        assert_eq!(multi.global_reserves, before);
+        assert!(quote.price_impact > 0.0);
+        assert!(!quote.fills.is_empty());
+
+        let out = multi.route_trade("USDC", "USDT", 30.0).unwrap();
+        assert!((quote.amount_out - out).abs() < 1e-12);
+        assert_eq!(quote.reserves_after, multi.global_reserves);
+    }
+
+    #[test]
+    fn test_fees_accrue_and_pay_out_to_lps() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut tick = OrbitalTick::new_with_fee(names.clone(), vec![100.0, 100.0], 150.0, 30);
+        tick.add_liquidity("alice", &[10.0, 10.0]).unwrap();
+        let mut no_fee = OrbitalTick::new(names, tick.sphere_amm.reserves.clone(), 150.0);
+
+        let (out, fee) = tick.swap("USDC", "USDT", 10.0).unwrap();
+        let (out_no_fee, _) = no_fee.swap("USDC", "USDT", 10.0).unwrap();
+        assert!((fee - 0.03).abs() < 1e-12);
+        assert!(out < out_no_fee);
+        assert!((tick.fees_accrued[0] - 0.03).abs() < 1e-12);
+
+        // Alice owns a tenth of what the creator owns, and so of the fees.
+        let withdrawn = tick.withdraw_liquidity("alice", 1.0).unwrap();
+        assert!(withdrawn[0] > 0.03);
+        assert!((tick.fees_accrued[0] - 0.03 * 10.0 / 11.0).abs() < 1e-12);
+        tick.withdraw_liquidity(CREATOR_LP, 1.0).unwrap();
+        assert!(tick.fees_accrued[0].abs() < 1e-12);
+
+        // A fee tier must leave some input to trade with.
+        let mut multi = MultiTickAMM::new(vec!["USDC".into(), "USDT".into()]);
+        let add = |fee_bps| Operation::AddTick {
+            plane: Some(150.0),
+            depeg_price: None,
+            reserves: vec![100.0, 100.0],
+            fee_bps,
+        };
+        assert_eq!(add(MAX_FEE_BPS).apply(&mut multi), Err(OrbitalError::InvalidFee(MAX_FEE_BPS)));
+        add(MAX_FEE_BPS - 1).apply(&mut multi).unwrap();
+        let err = multi.route_trade("USDC", "USDT", 1e-9).unwrap_err();
+        assert_eq!(err, OrbitalError::NonPositiveAmount);
+    }
+
+    #[test]
+    fn test_each_tick_charges_its_own_fee_tier() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into(), "DAI".into()];
+        let mut multi = MultiTickAMM::new(names);
+        multi.add_tick_from_depeg(vec![1000.0; 3], 0.95, 5).unwrap();
+        multi.add_tick_from_depeg(vec![1000.0; 3], 0.99, 100).unwrap();
+        let before = multi.clone();
+
+        let quote = multi.quote_trade("USDC", "USDT", 20.0).unwrap();
+        multi.route_trade("USDC", "USDT", 20.0).unwrap();
+        assert!((quote.amount_in - 20.0).abs() < 1e-9);
+        for (tick, old) in multi.ticks.iter().zip(&before.ticks) {
+            // Fees sit outside the reserves, so the reserve change is what
+            // the tick took net of its own fee.
+            let net = tick.sphere_amm.reserves[0] - old.sphere_amm.reserves[0];
+            let expected = (net * tick.fee_rate()) / (1.0 - tick.fee_rate());
+            assert!(net > 0.0);
+            assert!((tick.fees_accrued[0] - expected).abs() < 1e-12);
+        }
+        let fees: f64 = multi.ticks
+            .iter()
+            .map(|t| t.fees_accrued[0])
+            .sum();
+        assert!((quote.fee - fees).abs() < 1e-12);
+    }
+
+    /// A 30 bps tick over three tokens that has already charged a fee.
+    fn traded_tick() -> OrbitalTick {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into(), "DAI".into()];
+        let mut tick = OrbitalTick::new_with_fee(names, vec![100.0; 3], 200.0, 30);
+        tick.swap("USDC", "USDT", 10.0).unwrap();
+        tick
+    }
+
+    #[test]
+    fn test_creator_owns_the_initial_reserves() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into(), "DAI".into()];
+        let tick = OrbitalTick::new_with_fee(names, vec![100.0; 3], 200.0, 30);
+        assert_eq!(tick.lp_shares[CREATOR_LP], 300.0);
+        assert_eq!(tick.lp_fraction(CREATOR_LP), 1.0);
+    }
+
+    #[test]
+    fn test_unbalanced_deposit_is_trimmed_to_the_holdings() {
+        let mut tick = traded_tick();
+        let deposit = tick.add_liquidity("alice", &[50.0, 50.0, 50.0]).unwrap();
+        assert!(deposit.refunded.iter().any(|r| *r > 0.0));
+        for ((d, r), a) in deposit.deposited.iter().zip(&deposit.refunded).zip([50.0; 3]) {
+            assert!((d + r - a).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_add_withdraw_cycles_cannot_extract_value() {
+        let mut tick = traded_tick();
+        let creator_before = tick.clone().withdraw_liquidity(CREATOR_LP, 1.0).unwrap();
+        let deposit = tick.add_liquidity("alice", &[50.0, 50.0, 50.0]).unwrap();
+
+        // Cycling in and out never pays out more than was put in.
+        for _ in 0..3 {
+            let withdrawn = tick.withdraw_liquidity("alice", 1.0).unwrap();
+            for (w, d) in withdrawn.iter().zip(&deposit.deposited) {
+                assert!(*w <= d + 1e-9);
+            }
+            tick.add_liquidity("alice", &deposit.deposited).unwrap();
+        }
+        tick.withdraw_liquidity("alice", 1.0).unwrap();
+        let creator_after = tick.clone().withdraw_liquidity(CREATOR_LP, 1.0).unwrap();
+        for (a, b) in creator_after.iter().zip(&creator_before) {
+            assert!((a - b).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_deposit_scales_the_cap_so_trading_goes_on() {
+        let mut tick = traded_tick();
+        tick.add_liquidity("bob", &[60.0; 3]).unwrap();
+        assert!(tick.is_interior());
+        tick.swap("USDT", "DAI", 5.0).unwrap();
+        assert!(tick.sphere_amm.check_invariant());
+    }
+
+    #[test]
+    fn test_deposit_into_a_tick_without_liquidity_is_rejected() {
+        let mut empty = OrbitalTick::new(vec!["A".into(), "B".into()], vec![0.0, 0.0], 1.0);
+        assert_eq!(empty.add_liquidity("bob", &[1.0, 1.0]), Err(OrbitalError::EmptyTick));
+    }
+
+    #[test]
+    fn test_deposit_rejects_bad_amounts() {
+        let mut tick = traded_tick();
+        let err = tick.add_liquidity("bob", &[1.0, 1.0]).unwrap_err();
+        assert_eq!(err, OrbitalError::LengthMismatch { expected: 3, got: 2 });
+        for bad in [[1.0, -1.0, 1.0], [1.0, f64::NAN, 1.0], [0.0, 1.0, 1.0]] {
+            let err = tick.add_liquidity("bob", &bad).unwrap_err();
+            assert_eq!(err, OrbitalError::NonPositiveAmount, "{:?}", bad);
+        }
+        assert!(!tick.lp_shares.contains_key("bob"));
+    }
+
+    #[test]
+    fn test_withdrawal_rejects_unknown_lps_and_bad_percentages() {
+        let mut tick = traded_tick();
+        let err = tick.withdraw_liquidity("bob", 0.5).unwrap_err();
+        assert_eq!(err, OrbitalError::UnknownLp("bob".into()));
+        for bad in [-0.1, 1.5, f64::NAN] {
+            let err = tick.withdraw_liquidity(CREATOR_LP, bad).unwrap_err();
+            assert_eq!(err.code(), "bad_percentage", "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_pool_deposit_into_a_missing_tick_is_rejected() {
+        let mut multi = MultiTickAMM::new(vec!["USDC".into(), "USDT".into()]);
+        let deposit = Operation::AddLiquidity {
+            tick_index: 0,
+            lp_id: "bob".into(),
+            amounts: vec![1.0, 1.0],
+        };
+        assert_eq!(deposit.apply(&mut multi).unwrap_err(), OrbitalError::UnknownTick(0));
+    }
+
+    #[test]
+    fn test_tick_reaches_boundary_mid_trade() {
+        let names = vec!["USDC".into(), "USDT".into()];
+        let mut multi = MultiTickAMM::new(names);
+        multi.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        multi.add_tick(72.0, vec![50.0, 50.0]).unwrap();
+        let quote = multi.quote_trade("USDC", "USDT", 60.0).unwrap();
+        multi.route_trade("USDC", "USDT", 60.0).unwrap();
+
+        // The narrow tick fills up first and is pinned to its plane, the wide
+        // tick keeps trading in a second segment.
+        assert!(quote.fills.iter().any(|f| f.segment > 0));
+        assert!(multi.ticks[1].is_boundary());
+        assert!(multi.ticks[0].is_interior());
+        assert!(multi.ticks.iter().all(|t| t.sphere_amm.check_invariant()));
+        let (interior, boundary) = multi.classify_ticks();
+        assert_eq!((interior, boundary), (vec![0], vec![1]));
+
+        // Trading back releases the pinned tick into the interior again.
+        multi.route_trade("USDT", "USDC", 20.0).unwrap();
+        assert!(multi.ticks[1].is_interior());
+    }
+
+    #[test]
+    fn test_trade_fails_once_every_tick_is_pinned() {
+        let names = vec!["USDC".into(), "USDT".into()];
+        let mut multi = MultiTickAMM::new(names);
+        multi.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        assert!(multi.route_trade("USDC", "USDT", 500.0).is_err());
+
+        // A trade that only just runs past the plane must not push the last
+        // interior tick beyond it either.
+        for step in 1..=100 {
+            let mut next = multi.clone();
+            if next.route_trade("USDC", "USDT", f64::from(step)).is_ok() {
+                assert!(!next.ticks[0].is_exterior(), "exterior after {}", step);
+            }
+        }
+    }
+
+    #[test]
+    fn test_torus_reduces_to_sphere_for_interior_ticks() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into(), "DAI".into()];
+        let mut single = SphereAMM::new(names.clone(), vec![1500.0; 3]);
+        let mut multi = MultiTickAMM::new(names);
+        multi.add_tick(5000.0, vec![1000.0; 3]).unwrap();
+        multi.add_tick(5000.0, vec![500.0; 3]).unwrap();
+        let torus = multi.torus_invariant().unwrap();
+        assert!(torus.boundary.is_empty());
+        assert!((torus.interior_radius - single.radius).abs() < 1e-9);
+
+        let expected = single.swap("USDC", "USDT", 100.0).unwrap();
+        let out = multi.route_trade("USDC", "USDT", 100.0).unwrap();
+        assert!((out - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_boundary_tick_stays_on_its_plane() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into(), "DAI".into()];
+        let mut multi = MultiTickAMM::new(names);
+        multi.add_tick(1800.0, vec![1000.0; 3]).unwrap();
+        multi.add_tick(880.0, vec![500.0; 3]).unwrap();
+        multi.route_trade("USDC", "USDT", 400.0).unwrap();
+        multi.route_trade("USDT", "DAI", 300.0).unwrap();
+        multi.route_trade("USDT", "USDC", 600.0).unwrap();
+
+        let torus = multi.torus_invariant().unwrap();
+        assert_eq!((torus.interior.clone(), torus.boundary.clone()), (vec![0], vec![1]));
+        let scale = torus.interior_radius * torus.interior_radius;
+        assert!(torus.value(&multi.global_reserves).abs() < 1e-9 * scale);
+        let pinned = &multi.ticks[1];
+        assert!(pinned.is_boundary());
+        assert!((pinned.parallel_magnitude() - pinned.plane_constant).abs() < 1e-9);
+        assert!(multi.ticks.iter().all(|t| t.sphere_amm.check_invariant()));
+    }
+
+    #[test]
+    fn test_tick_from_depeg_price() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into(), "DAI".into()];
+        let tick = OrbitalTick::from_depeg_price(names.clone(), 1000.0, 0.99, 0).unwrap();
+        assert!(tick.is_interior());
+        assert!(tick.sphere_amm.check_invariant());
+
+        let mut multi = MultiTickAMM::new(names);
+        let params = multi.add_tick_from_depeg(vec![1000.0; 3], 0.95, 5).unwrap();
+        assert!((multi.ticks[0].plane_constant - params.plane_constant).abs() < 1e-12);
+        assert!(multi.add_tick_from_depeg(vec![1000.0, 100.0, 1000.0], 0.999, 5).is_err());
+    }
+
+    #[test]
+    fn test_efficiency_report() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into(), "DAI".into()];
+        let mut multi = MultiTickAMM::new(names);
+        multi.add_tick_from_depeg(vec![1000.0; 3], 0.99, 0).unwrap();
+        multi.add_tick_from_depeg(vec![1000.0; 3], 0.9, 0).unwrap();
+        let report = multi.efficiency_report();
+        let (tight, wide) = (&report.ticks[0], &report.ticks[1]);
+        assert!(tight.capital_efficiency > wide.capital_efficiency);
+        assert!(wide.capital_efficiency > 1.0);
+        assert!(
+            report.capital_efficiency > wide.capital_efficiency &&
+                report.capital_efficiency < tight.capital_efficiency
+        );
+        let total: f64 = tight.virtual_reserves[0] + tight.real_reserves[0];
+        assert!((total - 1000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_remove_tick() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut multi = MultiTickAMM::new(names);
+        multi.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        multi.add_tick(75.0, vec![50.0, 50.0]).unwrap();
+        let removed = multi.remove_tick(0).unwrap();
+        assert_eq!(removed.plane_constant, 150.0);
+        assert_eq!(multi.global_reserves, vec![50.0, 50.0]);
+        assert_eq!(multi.remove_tick(1).err(), Some(OrbitalError::UnknownTick(1)));
+    }
+
+    #[test]
+    fn test_state_file_path() {
+        use std::fs;
+        let dir = crate::testing::temp_dir("state");
+        let path = dir.join("pools").join("stable3.json");
+        let mut multi = MultiTickAMM::new(vec!["USDC".into(), "USDT".into()]);
+        multi.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        multi.with_state_path(&path).save_state().unwrap();
+
+        let loaded = MultiTickAMM::load_state(&path, Vec::new()).unwrap();
+        assert_eq!(loaded.state_path(), path.as_path());
+        assert_eq!(loaded.global_reserves, vec![100.0, 100.0]);
+        assert_eq!(MultiTickAMM::new(Vec::new()).state_path(), Path::new(DEFAULT_STATE_FILE));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_migrates_v0_and_rejects_corrupt_files() {
+        use std::fs;
+        let dir = crate::testing::temp_dir("migrate");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("multi_tick.json");
+
+        // Written before fees and schema_version existed.
+        let v0 =
+            r#"{
+            "ticks": [{
+                "sphere_amm": { "radius": 341.42, "reserves": [100.0, 100.0],
+                                "token_names": ["USDC", "USDT"] },
+                "plane_constant": 150.0,
+                "lp_shares": {}
+            }],
+            "global_reserves": [0.0, 0.0],
+            "token_names": ["USDC", "USDT"]
+        }"#;
+        fs::write(&path, v0).unwrap();
+        let multi = MultiTickAMM::load_state(&path, Vec::new()).unwrap();
+        assert_eq!(multi.global_reserves, vec![100.0, 100.0]);
+        assert_eq!(multi.ticks[0].fees_accrued, vec![0.0, 0.0]);
+        // Reserves no LP paid for belong to the creator.
+        assert_eq!(multi.ticks[0].lp_shares[CREATOR_LP], 200.0);
+        assert_eq!(multi.ticks[0].lp_deposits[CREATOR_LP], vec![100.0, 100.0]);
+        // The v0 plane is replaced by a full-range one and the tick trades.
+        let full_range = crate::sphere::full_range_plane(341.42, 2);
+        assert!((multi.ticks[0].plane_constant - full_range).abs() < 1e-9);
+        assert!(multi.ticks[0].is_interior());
+        let mut traded = multi.clone();
+        assert!(traded.route_trade("USDC", "USDT", 10.0).unwrap() > 0.0);
+        multi.save_state().unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("\"schema_version\": 2"));
+
+        fs::write(&path, "{ \"ticks\": [").unwrap();
+        let err = MultiTickAMM::load_state(&path, Vec::new()).err().unwrap();
+        assert_eq!(err.code(), "corrupt_state");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Load a v1 state file written with one share per deposited token:
+    /// alice put 10 of each into a tick created with 100 of each, which
+    /// nobody owned. Returns the pool and the file's path.
+    fn load_v1_pool(name: &str) -> (MultiTickAMM, PathBuf) {
+        let dir = crate::testing::temp_dir(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("multi_tick.json");
+        let v1 =
+            r#"{
+            "schema_version": 1,
+            "ticks": [{
+                "sphere_amm": { "radius": 375.56, "reserves": [110.0, 110.0],
+                                "token_names": ["USDC", "USDT"] },
+                "plane_constant": 165.0,
+                "lp_shares": { "alice": 20.0 },
+                "fee_bps": 0,
+                "fees_accrued": [0.0, 0.0],
+                "lp_deposits": { "alice": [10.0, 10.0] }
+            }],
+            "global_reserves": [110.0, 110.0],
+            "token_names": ["USDC", "USDT"]
+        }"#;
+        std::fs::write(&path, v1).unwrap();
+        (MultiTickAMM::load_state(&path, Vec::new()).unwrap(), path)
+    }
+
+    #[test]
+    fn test_load_migrates_v1_shares() {
+        let (multi, path) = load_v1_pool("migrate-v1");
+        let tick = &multi.ticks[0];
+        assert_eq!(tick.lp_shares[CREATOR_LP], 200.0);
+        assert!((tick.lp_fraction("alice") - 1.0 / 11.0).abs() < 1e-12);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_v1_migration_keeps_deposits_and_bases_the_creator() {
+        let (multi, path) = load_v1_pool("migrate-v1-deposits");
+        let tick = &multi.ticks[0];
+        assert_eq!(tick.lp_deposits["alice"], vec![10.0, 10.0]);
+        assert_eq!(tick.lp_deposits[CREATOR_LP], vec![100.0, 100.0]);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_v2_file_is_not_converted_again() {
+        let (multi, path) = load_v1_pool("migrate-v1-reload");
+        multi.save_state().unwrap();
+        let reloaded = MultiTickAMM::load_state(&path, Vec::new()).unwrap();
+        assert_eq!(reloaded.ticks[0].lp_shares, multi.ticks[0].lp_shares);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_torus_orders_nan_planes_without_panicking() {
+        let names: Vec<String> = vec!["USDC".into(), "USDT".into()];
+        let mut ticks = vec![
+            OrbitalTick::new(names.clone(), vec![100.0, 100.0], 150.0),
+            OrbitalTick::new(names, vec![100.0, 100.0], 150.0)
+        ];
+        ticks[1].plane_constant = f64::NAN;
+        let _ = TorusInvariant::from_ticks(&ticks, &[0, 1], &[200.0, 200.0], false);
+    }
+
+    #[test]
+    fn test_load_rejects_ticks_without_a_radius() {
+        use std::fs;
+        let dir = crate::testing::temp_dir("zero-radius");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("multi_tick.json");
+
+        let state =
+            r#"{
+            "schema_version": 2,
+            "ticks": [{
+                "sphere_amm": { "radius": 0.0, "reserves": [0.0, 0.0],
+                                "token_names": ["USDC", "USDT"] },
+                "plane_constant": 0.0,
+                "lp_shares": {},
+                "fee_bps": 0,
+                "fees_accrued": [0.0, 0.0]
+            }],
+            "global_reserves": [0.0, 0.0],
+            "token_names": ["USDC", "USDT"]
+        }"#;
+        fs::write(&path, state).unwrap();
+        let err = MultiTickAMM::load_state(&path, Vec::new()).unwrap_err();
+        assert_eq!(err.code(), "corrupt_state");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_v0_pool_is_interior_and_routes_after_load() {
+        use std::fs;
+        let dir = crate::testing::temp_dir("v0-route");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("multi_tick.json");
+
+        // Interior under the v0 convention because r_parallel ≈ 1732 > 600.
+        let v0 =
+            r#"{
+            "ticks": [{
+                "sphere_amm": { "radius": 2366.0254037844386,
+                                "reserves": [1000.0, 1000.0, 1000.0],
+                                "token_names": ["USDC", "USDT", "DAI"] },
+                "plane_constant": 600.0,
+                "lp_shares": {}
+            }],
+            "global_reserves": [1000.0, 1000.0, 1000.0],
+            "token_names": ["USDC", "USDT", "DAI"]
+        }"#;
+        fs::write(&path, v0).unwrap();
+        let mut multi = MultiTickAMM::load_state(&path, Vec::new()).unwrap();
+        assert_eq!(multi.classify_ticks(), (vec![0], vec![]));
+        let out = multi.route_trade("USDC", "USDT", 10.0).unwrap();
+        assert!(out > 9.0 && out < 10.0);
+        assert!(multi.ticks[0].is_interior() && multi.ticks[0].sphere_amm.check_invariant());
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
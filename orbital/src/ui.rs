@@ -1,100 +1,374 @@
-use std::{ error::Error, io, time::{ Duration, Instant } };
+use std::{ error::Error, fs, io, time::{ Duration, Instant, SystemTime } };
 
 use crossterm::{
-    event::{ self, Event as CEvent, KeyCode },
+    event::{ self, Event as CEvent, KeyCode, KeyEventKind },
     execute,
     terminal::{ disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen },
 };
 use ratatui::{
-    backend::CrosstermBackend,
+    backend::{ Backend, CrosstermBackend },
+    Frame,
     Terminal,
-    widgets::{ Block, Borders, Row, Table },
+    widgets::{ Block, Borders, Paragraph, Row, Table },
     layout::{ Constraint, Direction, Layout },
-    style::{ Modifier, Style },
 };
 
+use serde_json::Value;
+
+use crate::journal::Operation;
 use crate::ticks::MultiTickAMM;
 
-pub fn run_ui(mut amm: MultiTickAMM) -> Result<(), Box<dyn Error>> {
+/// Action waiting for a line of input at the prompt.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Prompt {
+    Trade,
+    AddTick,
+    AddLiquidity,
+    RemoveLiquidity,
+}
+
+impl Prompt {
+    fn usage(self) -> &'static str {
+        match self {
+            Prompt::Trade => "trade: FROM TO AMOUNT",
+            Prompt::AddTick => "add tick: PLANE R1,R2,.. [FEE_BPS]",
+            Prompt::AddLiquidity => "add liquidity: TICK LP A1,A2,..",
+            Prompt::RemoveLiquidity => "remove liquidity: TICK LP FRACTION",
+        }
+    }
+}
+
+struct App {
+    amm: MultiTickAMM,
+    prompt: Option<Prompt>,
+    input: String,
+    status: String,
+    /// Modification time of the state file as of our last load or save.
+    seen_mtime: Option<SystemTime>,
+}
+
+impl App {
+    /// Reload the pool from disk. A file that fails to load leaves the
+    /// current view in place and is reported in the status line.
+    fn reload(&mut self, what: &str) {
+        let path = self.amm.state_path().to_path_buf();
+        self.status = match MultiTickAMM::load_state(&path, self.amm.token_names.clone()) {
+            Ok(amm) => {
+                self.amm = amm;
+                format!("{} {}", path.display(), what)
+            }
+            Err(e) => format!("Error: {}", e),
+        };
+        self.seen_mtime = state_mtime(&self.amm);
+    }
+}
+
+fn state_mtime(amm: &MultiTickAMM) -> Option<SystemTime> {
+    fs::metadata(amm.state_path()).and_then(|m| m.modified()).ok()
+}
+
+fn parse_f64(s: &str, what: &str) -> Result<f64, String> {
+    s.parse().map_err(|_| format!("invalid {} '{}'", what, s))
+}
+
+fn parse_list(s: &str) -> Result<Vec<f64>, String> {
+    s.split(',')
+        .map(|v| parse_f64(v.trim(), "amount"))
+        .collect()
+}
+
+/// Parse one line entered at `prompt` into the operation it requests.
+fn parse_prompt(prompt: Prompt, line: &str) -> Result<Operation, String> {
+    let args: Vec<&str> = line.split_whitespace().collect();
+    let parse_index = |s: &str| s.parse().map_err(|_| format!("invalid tick index '{}'", s));
+    match (prompt, args.as_slice()) {
+        (Prompt::Trade, [from, to, amount]) =>
+            Ok(Operation::Trade {
+                from: from.to_string(),
+                to: to.to_string(),
+                amount: parse_f64(amount, "amount")?,
+                exact_out: false,
+                max_amount_in: None,
+            }),
+        (Prompt::AddTick, [plane, reserves, rest @ ..]) if rest.len() <= 1 => {
+            let fee_bps = match rest.first() {
+                Some(f) => f.parse().map_err(|_| format!("invalid fee '{}'", f))?,
+                None => 0,
+            };
+            Ok(Operation::AddTick {
+                plane: Some(parse_f64(plane, "plane constant")?),
+                depeg_price: None,
+                reserves: parse_list(reserves)?,
+                fee_bps,
+            })
+        }
+        (Prompt::AddLiquidity, [tick, lp, amounts]) =>
+            Ok(Operation::AddLiquidity {
+                tick_index: parse_index(tick)?,
+                lp_id: lp.to_string(),
+                amounts: parse_list(amounts)?,
+            }),
+        (Prompt::RemoveLiquidity, [tick, lp, fraction]) =>
+            Ok(Operation::RemoveLiquidity {
+                tick_index: parse_index(tick)?,
+                lp_id: lp.to_string(),
+                percentage: parse_f64(fraction, "fraction")?,
+            }),
+        _ => Err(format!("usage – {}", prompt.usage())),
+    }
+}
+
+/// Status line for a committed operation.
+fn describe(op: &Operation, output: &Value) -> String {
+    match op {
+        Operation::Trade { from, to, .. } =>
+            format!(
+                "Swapped {} {} for {} {}",
+                output["amount_in"],
+                from,
+                output["amount_out"],
+                to
+            ),
+        Operation::AddTick { .. } =>
+            format!(
+                "Added tick {} with plane constant {}",
+                output["tick_index"],
+                output["plane_constant"]
+            ),
+        Operation::AddLiquidity { tick_index, lp_id, .. } =>
+            format!("Added liquidity for LP {} to tick {}", lp_id, tick_index),
+        Operation::RemoveLiquidity { tick_index, lp_id, .. } =>
+            format!("Withdrew {} for LP {} from tick {}", output["withdrawn"], lp_id, tick_index),
+        _ => "Done".to_string(),
+    }
+}
+
+fn draw<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let amm = &app.amm;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints(
+            [
+                Constraint::Min(6),
+                Constraint::Length((amm.token_names.len() as u16) + 3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+            ].as_ref()
+        )
+        .split(f.size());
+
+    // Table of ticks
+    let header = Row::new(
+        vec!["idx", "plane c", "parallel", "state", "radius", "fee bps", "liquidity", "invariant"]
+    );
+    let rows: Vec<Row> = amm.ticks
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            let (par, _) = crate::sphere::decompose_reserves(&t.sphere_amm.reserves);
+            let state = if t.is_interior() {
+                "interior"
+            } else if t.is_boundary() {
+                "boundary"
+            } else {
+                "exterior"
+            };
+            let invariant = if t.sphere_amm.check_invariant() { "ok" } else { "BROKEN" };
+            Row::new(
+                vec![
+                    i.to_string(),
+                    format!("{:.2}", t.plane_constant),
+                    format!("{:.2}", par),
+                    state.to_string(),
+                    format!("{:.2}", t.sphere_amm.radius),
+                    t.fee_bps.to_string(),
+                    format!("{:.2}", t.liquidity()),
+                    invariant.to_string()
+                ]
+            )
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(5),
+        Constraint::Length(10),
+        Constraint::Length(12),
+        Constraint::Length(10),
+        Constraint::Length(12),
+        Constraint::Length(8),
+        Constraint::Length(12),
+        Constraint::Length(10),
+    ];
+    let table = Table::new(rows)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title("Ticks"))
+        .widths(&widths);
+    f.render_widget(table, chunks[0]);
+
+    let middle = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(35), Constraint::Percentage(65)].as_ref())
+        .split(chunks[1]);
+
+    // Global reserves
+    let broken = amm.ticks
+        .iter()
+        .filter(|t| !t.sphere_amm.check_invariant())
+        .count();
+    let invariant = if broken == 0 {
+        "invariant ok".to_string()
+    } else {
+        format!("{} tick(s) off their sphere", broken)
+    };
+    let reserve_rows: Vec<Row> = amm.token_names
+        .iter()
+        .zip(&amm.global_reserves)
+        .map(|(name, r)| Row::new(vec![name.clone(), format!("{:.4}", r)]))
+        .collect();
+    let reserve_widths = [Constraint::Length(8), Constraint::Length(16)];
+    let reserves = Table::new(reserve_rows)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Global reserves – {}", invariant))
+        )
+        .widths(&reserve_widths);
+    f.render_widget(reserves, middle[0]);
+
+    // Pairwise prices: row token priced in column token
+    let mut price_header = vec![String::new()];
+    price_header.extend(amm.token_names.iter().cloned());
+    let price_rows: Vec<Row> = amm.token_names
+        .iter()
+        .map(|from| {
+            let mut cells = vec![from.clone()];
+            for to in &amm.token_names {
+                cells.push(if from == to {
+                    "–".to_string()
+                } else {
+                    amm.get_aggregated_price(from, to)
+                        .map(|p| format!("{:.6}", p))
+                        .unwrap_or_else(|_| "n/a".to_string())
+                });
+            }
+            Row::new(cells)
+        })
+        .collect();
+    let price_widths = vec![Constraint::Length(10); amm.token_names.len() + 1];
+    let prices = Table::new(price_rows)
+        .header(Row::new(price_header))
+        .block(Block::default().borders(Borders::ALL).title("Prices"))
+        .widths(&price_widths);
+    f.render_widget(prices, middle[1]);
+
+    let status = Paragraph::new(app.status.as_str()).block(
+        Block::default().borders(Borders::ALL).title("Status")
+    );
+    f.render_widget(status, chunks[2]);
+
+    let (title, line) = match app.prompt {
+        Some(prompt) => (prompt.usage().to_string(), format!("> {}", app.input)),
+        None =>
+            (
+                "Controls".to_string(),
+                [
+                    "q quit | t trade | a add tick | l add liquidity | w withdraw",
+                    "u undo | y redo | r reload",
+                ].join(" | "),
+            ),
+    };
+    let input = Paragraph::new(line).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(input, chunks[3]);
+}
+
+/// Interactive dashboard over the pool's state file. Changes are saved after
+/// every successful action and the view reloads when the file changes on disk.
+pub fn run_ui(amm: MultiTickAMM) -> Result<(), Box<dyn Error>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
+    let mut app = App {
+        amm,
+        prompt: None,
+        input: String::new(),
+        status: String::new(),
+        seen_mtime: None,
+    };
+    app.seen_mtime = state_mtime(&app.amm);
+    app.status = format!("Loaded {}", app.amm.state_path().display());
     let tick_rate = Duration::from_millis(500);
     let mut last_tick = Instant::now();
 
     loop {
-        terminal.draw(|f| {
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .margin(1)
-                .constraints([Constraint::Percentage(80), Constraint::Percentage(20)].as_ref())
-                .split(f.size());
-
-            // Table of ticks
-            let header = Row::new(vec!["idx", "plane c", "parallel", "state"]);
-            let rows: Vec<Row> = amm.ticks
-                .iter()
-                .enumerate()
-                .map(|(i, t)| {
-                    let (par, _) = crate::sphere::decompose_reserves(&t.sphere_amm.reserves);
-                    let state = if t.is_interior() {
-                        "interior"
-                    } else if t.is_boundary() {
-                        "boundary"
-                    } else {
-                        "exterior"
-                    };
-                    Row::new(
-                        vec![
-                            i.to_string(),
-                            format!("{:.2}", t.plane_constant),
-                            format!("{:.2}", par),
-                            state.to_string()
-                        ]
-                    )
-                })
-                .collect();
-
-            let table = Table::new(rows)
-                .header(header)
-                .block(Block::default().borders(Borders::ALL).title("Ticks"))
-                .widths(
-                    &[
-                        Constraint::Length(5),
-                        Constraint::Length(10),
-                        Constraint::Length(12),
-                        Constraint::Length(10),
-                    ]
-                );
-            f.render_widget(table, chunks[0]);
-
-            // Footer info
-            let footer = Block::default()
-                .borders(Borders::ALL)
-                .title("Controls: q quit | r refresh from file | any key to continue");
-            f.render_widget(footer, chunks[1]);
-        })?;
+        terminal.draw(|f| draw(f, &app))?;
 
         let timeout = tick_rate
             .checked_sub(last_tick.elapsed())
             .unwrap_or_else(|| Duration::from_secs(0));
         if crossterm::event::poll(timeout)? {
             if let CEvent::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') => {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match (app.prompt, key.code) {
+                    (None, KeyCode::Char('q')) => {
                         break;
                     }
-                    KeyCode::Char('r') => {
-                        amm = MultiTickAMM::load_state(amm.token_names.clone());
+                    (None, KeyCode::Char('r')) => {
+                        app.reload("reloaded");
+                    }
+                    (None, KeyCode::Char(c @ ('u' | 'y'))) => {
+                        let (result, done) = if c == 'u' {
+                            (app.amm.undo(), "Undid last operation")
+                        } else {
+                            (app.amm.redo(), "Redid last undone operation")
+                        };
+                        app.status = match result {
+                            Ok(_) => done.to_string(),
+                            Err(e) => format!("Error: {}", e),
+                        };
+                        app.seen_mtime = state_mtime(&app.amm);
+                    }
+                    (None, KeyCode::Char(c)) => {
+                        app.prompt = match c {
+                            't' => Some(Prompt::Trade),
+                            'a' => Some(Prompt::AddTick),
+                            'l' => Some(Prompt::AddLiquidity),
+                            'w' => Some(Prompt::RemoveLiquidity),
+                            _ => None,
+                        };
+                        app.input.clear();
+                    }
+                    (Some(_), KeyCode::Esc) => {
+                        app.prompt = None;
+                    }
+                    (Some(_), KeyCode::Backspace) => {
+                        app.input.pop();
+                    }
+                    (Some(_), KeyCode::Char(c)) => {
+                        app.input.push(c);
+                    }
+                    (Some(prompt), KeyCode::Enter) => {
+                        let result = parse_prompt(prompt, &app.input).and_then(|op| {
+                            let output = app.amm.commit(op.clone()).map_err(|e| e.to_string())?;
+                            Ok(describe(&op, &output))
+                        });
+                        app.status = result.unwrap_or_else(|e| format!("Error: {}", e));
+                        app.seen_mtime = state_mtime(&app.amm);
+                        app.prompt = None;
                     }
                     _ => {}
                 }
             }
         }
         if last_tick.elapsed() >= tick_rate {
+            // Pick up writes from the server or another CLI invocation.
+            if state_mtime(&app.amm) != app.seen_mtime {
+                app.reload("changed on disk – reloaded");
+            }
             last_tick = Instant::now();
         }
     }
@@ -104,3 +378,48 @@ pub fn run_ui(mut amm: MultiTickAMM) -> Result<(), Box<dyn Error>> {
     terminal.show_cursor()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool() -> MultiTickAMM {
+        let mut amm = MultiTickAMM::new(vec!["USDC".into(), "USDT".into()]);
+        amm.add_tick(150.0, vec![100.0, 100.0]).unwrap();
+        amm
+    }
+
+    #[test]
+    fn test_prompt_actions() {
+        let mut amm = pool();
+        let mut run = |prompt, line| {
+            let op = parse_prompt(prompt, line)?;
+            op.apply(&mut amm).map_err(|e| e.to_string())
+        };
+        assert!(run(Prompt::Trade, "USDC USDT 5").is_ok());
+        assert!(run(Prompt::AddTick, "80 50,50 30").is_ok());
+        assert!(run(Prompt::AddLiquidity, "1 alice 10,10").is_ok());
+        assert!(run(Prompt::RemoveLiquidity, "1 alice 0.5").is_ok());
+
+        assert!(run(Prompt::Trade, "USDC USDT").is_err());
+        assert!(run(Prompt::AddTick, "80 50").is_err());
+        assert!(run(Prompt::RemoveLiquidity, "7 alice 0.5").is_err());
+        assert_eq!(amm.ticks[1].fee_bps, 30);
+    }
+
+    #[test]
+    fn test_dashboard_renders() {
+        let app = App {
+            amm: pool(),
+            prompt: Some(Prompt::Trade),
+            input: "USDC USDT 1".into(),
+            status: String::new(),
+            seen_mtime: None,
+        };
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let text: String = buffer.content.iter().map(|c| c.symbol.as_str()).collect();
+        assert!(text.contains("interior") && text.contains("> USDC USDT 1"));
+    }
+}
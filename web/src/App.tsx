@@ -13,6 +13,7 @@ import {
     Legend,
 } from 'chart.js';
 import PhaseDiagram from './PhaseDiagram';
+import { API_PREFIX, POOL } from './api';
 ChartJS.register(CategoryScale, LinearScale, PointElement, LineElement, BarElement, Title, Tooltip, Legend);
 
 interface TickInfo {
@@ -32,7 +33,6 @@ interface State {
     tick_count: number;
 }
 
-const API_PREFIX = window.location.hostname === 'localhost' ? 'http://localhost:8080/api' : '/api';
 
 const colors = ['#3b82f6', '#ef4444', '#10b981', '#f59e0b', '#8b5cf6'];
 
@@ -49,10 +49,10 @@ export default function App() {
     const [lpId, setLpId] = useState('');
     const [lpAmounts, setLpAmounts] = useState('');
     const [resetReserves, setResetReserves] = useState('');
-    const [resetPlane, setResetPlane] = useState('600');
+    const [resetPlane, setResetPlane] = useState('1800');
     const [configTokens, setConfigTokens] = useState('');
     const [configReserves, setConfigReserves] = useState('');
-    const [configPlane, setConfigPlane] = useState('600');
+    const [configPlane, setConfigPlane] = useState('1800');
     const [showConfig, setShowConfig] = useState(false);
     const [showPhaseDiagram, setShowPhaseDiagram] = useState(false);
 
@@ -74,8 +74,30 @@ export default function App() {
 
     useEffect(() => {
         fetchState();
-        const id = setInterval(fetchState, 3000);
-        return () => clearInterval(id);
+        // Live updates from /api/stream; poll only while the stream is down.
+        let poll = null;
+        const source = new EventSource(`${API_PREFIX}/stream`);
+        source.addEventListener('snapshot', e => setState(JSON.parse(e.data).state));
+        source.addEventListener('state', e => {
+            const diff = JSON.parse(e.data);
+            setState(prev => {
+                if (!prev) return prev;
+                const ticks = prev.ticks.slice(0, diff.tick_count);
+                for (const tick of diff.ticks || []) ticks[tick.index] = tick;
+                return { ...prev, ...diff, ticks };
+            });
+        });
+        source.onopen = () => {
+            if (poll) clearInterval(poll);
+            poll = null;
+        };
+        source.onerror = () => {
+            if (!poll) poll = setInterval(fetchState, 3000);
+        };
+        return () => {
+            source.close();
+            if (poll) clearInterval(poll);
+        };
     }, []);
 
     const showStatus = (msg: string, type: 'success' | 'error' = 'success') => {
@@ -162,7 +184,8 @@ export default function App() {
             if (result.success) {
                 setLpId('');
                 setLpAmounts('');
-                showStatus('Liquidity added');
+                const refunded = (result.refunded ?? []).some((r: number) => r > 0);
+                showStatus(refunded ? 'Liquidity added, unbalanced remainder refunded' : 'Liquidity added');
                 fetchState();
             } else {
                 showStatus(result.message, 'error');
@@ -192,6 +215,21 @@ export default function App() {
         }
     };
 
+    const historyAction = async (action: 'undo' | 'redo') => {
+        try {
+            const res = await fetch(`${API_PREFIX}/${action}`, { method: 'POST' });
+            const result = await res.json();
+            if (result.success) {
+                showStatus(result.message);
+                fetchState();
+            } else {
+                showStatus(result.message, 'error');
+            }
+        } catch (e) {
+            showStatus(`${action} failed`, 'error');
+        }
+    };
+
     const reconfigureAMM = async () => {
         if (!configTokens || !configReserves) {
             showStatus('Please fill in tokens and reserves', 'error');
@@ -281,7 +319,7 @@ export default function App() {
 
     return (
         <div style={{ padding: 20, maxWidth: 1200, margin: '0 auto' }}>
-            <h1>Orbital Sandbox</h1>
+            <h1>Orbital Sandbox{POOL ? ` – ${POOL}` : ''}</h1>
 
             {/* Add toggle button */}
             <div style={{ marginBottom: 20 }}>
@@ -298,6 +336,23 @@ export default function App() {
                 >
                     {showPhaseDiagram ? 'Hide' : 'Show'} Phase Diagram
                 </button>
+                {(['undo', 'redo'] as const).map(action => (
+                    <button
+                        key={action}
+                        onClick={() => historyAction(action)}
+                        style={{
+                            padding: '8px 16px',
+                            marginLeft: 8,
+                            backgroundColor: '#6c757d',
+                            color: 'white',
+                            border: 'none',
+                            borderRadius: '4px',
+                            cursor: 'pointer'
+                        }}
+                    >
+                        {action === 'undo' ? 'Undo' : 'Redo'}
+                    </button>
+                ))}
             </div>
             {/* Conditionally render phase diagram */}
             {showPhaseDiagram && <PhaseDiagram />}
@@ -429,7 +484,7 @@ export default function App() {
                     <div style={{ display: 'grid', gap: '8px' }}>
                         <input
                             type="number"
-                            placeholder="Plane constant (e.g., 500)"
+                            placeholder="Plane constant (e.g., 1800)"
                             value={plane}
                             onChange={e => setPlane(e.target.value)}
                             style={{ padding: '8px', border: '1px solid #d1d5db', borderRadius: '6px' }}
@@ -706,7 +761,7 @@ export default function App() {
                         <strong>Interior/Boundary:</strong> Interior ticks are active for trading. Boundary ticks have hit their limits.
                     </div>
                     <div>
-                        <strong>Trading:</strong> Routes through ticks automatically, starting with smallest plane constants first.
+                        <strong>Trading:</strong> Routes through all interior ticks at once; ticks that reach their plane are pinned to the boundary.
                     </div>
                     <div>
                         <strong>Sphere Constraint:</strong> Each tick maintains Σ(r - xᵢ)² = r² to ensure mathematical consistency.
@@ -1,5 +1,6 @@
 // @ts-nocheck
 import React, { useEffect, useState, useRef } from 'react';
+import { API_PREFIX } from './api';
 
 interface PhasePoint {
     x1: number;
@@ -31,7 +32,7 @@ const PhaseDiagram: React.FC = () => {
 
     const fetchPhaseData = async () => {
         try {
-            const response = await fetch('/api/phase-diagram');
+            const response = await fetch(`${API_PREFIX}/phase-diagram`);
             if (response.ok) {
                 const data = await response.json();
                 setPhaseData(data);
@@ -0,0 +1,6 @@
+const API_ROOT = window.location.hostname === 'localhost' ? 'http://localhost:8080/api' : '/api';
+
+// `?pool=<id>` opens another pool served by the same server, e.g. in a second tab.
+export const POOL = new URLSearchParams(window.location.search).get('pool');
+
+export const API_PREFIX = POOL ? `${API_ROOT}/pools/${encodeURIComponent(POOL)}` : API_ROOT;